@@ -0,0 +1,227 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::{buffer::AlignedBuf, recovery::Input};
+
+
+/// Samples reading below median / SLOW_FACTOR are flagged as slow.
+const SLOW_FACTOR: f64 = 2.0;
+
+
+/// Read speed of a single sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sample {
+    /// Offset in bytes.
+    pub offset: u64,
+    /// None if the read failed.
+    pub bytes_per_sec: Option<f64>,
+    pub is_slow: bool,
+}
+
+
+/// Read samples of sample_len bytes at evenly spaced offsets across the
+/// first len bytes of input, timing each. Offsets and the buffer read into
+/// are aligned to align, as uncached input requires.
+/// Read errors are recorded as failed samples rather than aborting,
+/// unless every sample fails, when the last error is returned.
+pub fn benchmark<R: Input>(
+    input: &mut R,
+    len: u64,
+    samples: usize,
+    sample_len: usize,
+    align: usize,
+) -> io::Result<Vec<Sample>> {
+    let epoch = Instant::now();
+
+    benchmark_with_clock(
+        input,
+        len,
+        samples,
+        sample_len,
+        align,
+        || epoch.elapsed(),
+    )
+}
+
+/// benchmark(), timing reads by a clock returning time since some epoch.
+fn benchmark_with_clock<R: Input, C: Fn() -> Duration>(
+    input: &mut R,
+    len: u64,
+    samples: usize,
+    sample_len: usize,
+    align: usize,
+    clock: C,
+) -> io::Result<Vec<Sample>> {
+    let align = align.max(1) as u64;
+    let span = len.saturating_sub(sample_len as u64);
+    let mut buf = AlignedBuf::new(sample_len, align as usize);
+    let mut results: Vec<Sample> = vec![];
+    let mut last_err = None;
+
+    for i in 0..samples as u64 {
+        let offset = match samples {
+            1 => 0,
+            _ => span * i / (samples as u64 - 1) / align * align,
+        };
+
+        let start = clock();
+        let bytes_per_sec = match input.read_exact_at(&mut buf, offset) {
+            Ok(_) => Some(
+                sample_len as f64 / (clock() - start).as_secs_f64().max(1e-9)
+            ),
+            Err(err) => {
+                last_err = Some(err);
+                None
+            },
+        };
+
+        results.push(Sample { offset, bytes_per_sec, is_slow: false });
+    }
+
+    // Nothing was measured, so there's nothing to report.
+    if let Some(err) = last_err.filter(|_| results.iter().all(|s| s.bytes_per_sec.is_none())) {
+        return Err(err);
+    }
+
+    flag_slow(&mut results);
+    Ok(results)
+}
+
+/// Flag samples which failed, or read far below the median speed.
+fn flag_slow(samples: &mut [Sample]) {
+    let mut rates: Vec<f64> = samples.iter()
+        .filter_map(|s| s.bytes_per_sec)
+        .collect();
+    rates.sort_by(|a, b| a.total_cmp(b));
+
+    let median = rates.get(rates.len() / 2).copied().unwrap_or(0.0);
+
+    for sample in samples.iter_mut() {
+        sample.is_slow = match sample.bytes_per_sec {
+            Some(rate) => rate < median / SLOW_FACTOR,
+            None => true,
+        };
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{platform::open_uncached, testing::scratch_path};
+    use std::{
+        cell::Cell,
+        fs::OpenOptions,
+        io::{Cursor, Read, Seek, SeekFrom},
+        ops::Range,
+        rc::Rc,
+    };
+
+    /// Every read advances clock by delay,
+    /// or slow_delay if it starts within slow.
+    struct SlowRegion {
+        data: Cursor<Vec<u8>>,
+        slow: Range<u64>,
+        delay: Duration,
+        slow_delay: Duration,
+        clock: Rc<Cell<Duration>>,
+    }
+
+    impl Read for SlowRegion {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let delay = if self.slow.contains(&self.data.position()) {
+                self.slow_delay
+            } else {
+                self.delay
+            };
+
+            self.clock.set(self.clock.get() + delay);
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for SlowRegion {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for SlowRegion {}
+
+    // Test for benchmark_with_clock()
+    #[test]
+    fn test_benchmark() {
+        let clock = Rc::new(Cell::new(Duration::ZERO));
+        let mut input = SlowRegion {
+            data: Cursor::new(vec![0u8; 8 * 512]),
+            slow: 5 * 512..6 * 512,
+            delay: Duration::from_millis(1),
+            slow_delay: Duration::from_millis(50),
+            clock: Rc::clone(&clock),
+        };
+
+        let samples = benchmark_with_clock(
+            &mut input,
+            8 * 512,
+            8,
+            512,
+            512,
+            || clock.get(),
+        )
+        .unwrap();
+
+        let offsets: Vec<u64> = samples.iter().map(|s| s.offset).collect();
+        let slow: Vec<u64> = samples.iter()
+            .filter(|s| s.is_slow)
+            .map(|s| s.offset)
+            .collect();
+
+        assert!(
+            offsets == (0..8).map(|i| i * 512).collect::<Vec<u64>>(),
+            "Expected evenly spaced samples, got offsets {:?}.",
+            offsets
+        );
+        assert!(
+            slow == vec![5 * 512],
+            "Expected only offset {} flagged as slow, got {:?}.",
+            5 * 512, slow
+        )
+    }
+
+    // Test for benchmark() of a file opened uncached
+    #[test]
+    fn test_benchmark_uncached() {
+        let path = scratch_path("benchmark_uncached");
+        std::fs::write(&path, vec![0xaa; 64 * 4096]).unwrap();
+
+        let mut input = open_uncached(OpenOptions::new().read(true), &path).unwrap();
+        // Offsets would fall between blocks if they weren't rounded.
+        let samples = benchmark(&mut input, 64 * 4096, 7, 8192, 4096);
+
+        std::fs::remove_file(&path).unwrap();
+
+        let samples = samples.unwrap();
+
+        assert!(
+            samples.iter().all(|s| s.bytes_per_sec.is_some() && s.offset % 4096 == 0),
+            "Expected every sample of an uncached file read at aligned offsets, got {:?}.",
+            samples
+        )
+    }
+
+    // Test for benchmark() where every sample fails
+    #[test]
+    fn test_benchmark_failed() {
+        // Every sample runs past the end of the input.
+        let mut input = Cursor::new(vec![0u8; 512]);
+        let samples = benchmark(&mut input, 4096, 4, 1024, 512);
+
+        assert!(
+            samples.is_err(),
+            "Expected an error with every sample failed, got {:?}.",
+            samples
+        )
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    alloc::{self, Layout},
+    fs::File,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+};
+
+#[cfg(target_os = "linux")]
+use log::warn;
+#[cfg(target_os = "linux")]
+use std::{
+    io,
+    os::unix::{fs::FileTypeExt, io::AsRawFd},
+};
+
+
+/// BLKSSZGET from linux/fs.h, which libc doesn't export.
+#[cfg(target_os = "linux")]
+const BLKSSZGET: libc::Ioctl = 0x1268;
+
+
+/// Zeroed heap buffer whose address is aligned, as O_DIRECT requires.
+#[derive(Debug)]
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+    layout: Layout,
+}
+
+impl AlignedBuf {
+    /// Allocate len bytes aligned to align.
+    /// align is rounded up to a power of two, and the allocation
+    /// to a multiple of it, so even an empty buffer is valid.
+    pub fn new(len: usize, align: usize) -> Self {
+        let align = align.max(1).next_power_of_two();
+        let layout = Layout::from_size_align(len.max(1).next_multiple_of(align), align)
+            .expect("Buffer length overflows when aligned.");
+
+        let ptr = match NonNull::new(unsafe { alloc::alloc_zeroed(layout) }) {
+            Some(ptr) => ptr,
+            None => alloc::handle_alloc_error(layout),
+        };
+
+        AlignedBuf { ptr, len, layout }
+    }
+
+    /// Alignment of the buffer's address in bytes.
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
+
+// Owns its allocation outright, like a Vec<u8>.
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+
+/// Logical block size of a block device, in bytes.
+/// None if file isn't a block device, or the size can't be queried.
+#[cfg(target_os = "linux")]
+pub fn logical_block_size(file: &File) -> Option<usize> {
+    if !file.metadata().ok()?.file_type().is_block_device() {
+        return None;
+    }
+
+    let mut size: libc::c_int = 0;
+
+    match unsafe { libc::ioctl(file.as_raw_fd(), BLKSSZGET, &mut size) } {
+        -1 => {
+            warn!(
+                "Failed to get logical block size: {:?}",
+                io::Error::last_os_error()
+            );
+
+            None
+        },
+        _ => usize::try_from(size).ok().filter(|&s| s > 0),
+    }
+}
+
+/// Only queried on Linux, so unknown elsewhere.
+#[cfg(not(target_os = "linux"))]
+pub fn logical_block_size(_file: &File) -> Option<usize> {
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for AlignedBuf::new()
+    #[test]
+    fn test_aligned_buf() {
+        let cases = vec![
+            (4096, 512),
+            (2048, 4096),
+            (1, 4096),
+            (0, 512),
+            (6144, 3000),
+        ];
+
+        for (len, align) in cases {
+            let mut buf = AlignedBuf::new(len, align);
+            buf.fill(0xff);
+
+            assert!(
+                (buf.as_ptr() as usize).is_multiple_of(buf.align()) && buf.align() >= align,
+                "Expected buffer at {:p} aligned to {} bytes, for {:?}.",
+                buf.as_ptr(), align, (len, align)
+            );
+            assert!(
+                buf.len() == len,
+                "Expected buffer of {} bytes, got {}.",
+                len, buf.len()
+            )
+        }
+    }
+
+    // Test for logical_block_size()
+    #[test]
+    fn test_logical_block_size() {
+        // Regular files fall back to sector_size.
+        let file = File::open(std::env::current_exe().unwrap()).unwrap();
+
+        assert!(logical_block_size(&file).is_none())
+    }
+}
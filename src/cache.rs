@@ -0,0 +1,67 @@
+use std::{fs::File, io};
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+
+/// Give the kernel advice on how a byte range of file will be used.
+/// A len of 0 covers everything from offset on.
+#[cfg(target_os = "linux")]
+fn fadvise(file: &File, offset: u64, len: u64, advice: libc::c_int) -> io::Result<()> {
+    // Returns the error number, rather than setting errno.
+    match unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice)
+    } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+/// Drop a byte range of file from the page cache,
+/// once written data won't be read again.
+#[cfg(target_os = "linux")]
+pub fn drop_cached(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    fadvise(file, offset, len, libc::POSIX_FADV_DONTNEED)
+}
+
+/// Expect file to be read from start to end, so reads ahead further.
+#[cfg(target_os = "linux")]
+pub fn advise_sequential(file: &File) -> io::Result<()> {
+    fadvise(file, 0, 0, libc::POSIX_FADV_SEQUENTIAL)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn drop_cached(_file: &File, _offset: u64, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn advise_sequential(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_path;
+
+    // Test for drop_cached() and advise_sequential()
+    #[test]
+    fn test_fadvise() {
+        let path = scratch_path("fadvise");
+        std::fs::write(&path, vec![0xaa; 8192]).unwrap();
+
+        let file = File::open(&path).unwrap();
+        let sequential = advise_sequential(&file);
+        let dropped = drop_cached(&file, 4096, 4096);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(
+            sequential.is_ok() && dropped.is_ok(),
+            "Expected advice to be taken, got {:?} and {:?}.",
+            sequential, dropped
+        )
+    }
+}
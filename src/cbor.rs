@@ -0,0 +1,167 @@
+//! Minimal CBOR (RFC 8949), only as much as maps are saved with:
+//! unsigned integers, text strings, and arrays and maps of definite length.
+//!
+//! Integers are always written most significant byte first, as the RFC
+//! requires, never in the host's byte order. So CBOR written on a little
+//! endian machine reads the same on a big endian one, and the other way.
+
+use std::io::{self, Read, Write};
+
+
+pub const UNSIGNED: u8 = 0;
+pub const TEXT: u8 = 3;
+pub const ARRAY: u8 = 4;
+pub const MAP: u8 = 5;
+pub const TAG: u8 = 6;
+
+/// Tag marking what follows as CBOR, so it's recognisable by its first bytes.
+pub const SELF_DESCRIBE: u64 = 55799;
+
+/// SELF_DESCRIBE, as it's encoded.
+pub const SELF_DESCRIBE_HEAD: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Write the head of an item of major type, with argument n.
+/// n is written in as few bytes as fit it, most significant first.
+pub fn write_head<W: Write>(writer: &mut W, major: u8, n: u64) -> io::Result<()> {
+    let major = major << 5;
+
+    match n {
+        0..=23 => writer.write_all(&[major | n as u8]),
+        24..=0xff => writer.write_all(&[major | 24, n as u8]),
+        0x100..=0xffff => {
+            writer.write_all(&[major | 25])?;
+            writer.write_all(&(n as u16).to_be_bytes())
+        },
+        0x1_0000..=0xffff_ffff => {
+            writer.write_all(&[major | 26])?;
+            writer.write_all(&(n as u32).to_be_bytes())
+        },
+        _ => {
+            writer.write_all(&[major | 27])?;
+            writer.write_all(&n.to_be_bytes())
+        },
+    }
+}
+
+pub fn write_text<W: Write>(writer: &mut W, s: &str) -> io::Result<()> {
+    write_head(writer, TEXT, s.len() as u64)?;
+    writer.write_all(s.as_bytes())
+}
+
+/// Read the head of an item, as its major type and argument.
+pub fn read_head<R: Read>(reader: &mut R) -> io::Result<(u8, u64)> {
+    let mut initial = [0u8; 1];
+    reader.read_exact(&mut initial)?;
+
+    let (major, info) = (initial[0] >> 5, initial[0] & 0x1f);
+
+    let n = match info {
+        0..=23 => info as u64,
+        24..=27 => {
+            let len = 1 << (info - 24);
+            let mut buf = [0u8; 8];
+
+            reader.read_exact(&mut buf[8 - len..])?;
+            u64::from_be_bytes(buf)
+        },
+        _ => return Err(invalid(format!(
+            "Unsupported CBOR item {:#04x}, lengths must be definite",
+            initial[0],
+        ))),
+    };
+
+    Ok((major, n))
+}
+
+/// Argument of head, which must be of major type.
+pub fn expect(head: (u8, u64), major: u8) -> io::Result<u64> {
+    match head {
+        (m, n) if m == major => Ok(n),
+        (m, _) => Err(invalid(format!(
+            "Expected CBOR major type {}, got {}",
+            major, m,
+        ))),
+    }
+}
+
+/// Read an unsigned integer.
+pub fn read_unsigned<R: Read>(reader: &mut R) -> io::Result<u64> {
+    expect(read_head(reader)?, UNSIGNED)
+}
+
+/// Read a text string, whose head is already read.
+pub fn read_text_body<R: Read>(reader: &mut R, len: u64) -> io::Result<String> {
+    let mut buf = vec![];
+
+    reader.take(len).read_to_end(&mut buf)?;
+
+    if (buf.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+
+    String::from_utf8(buf).map_err(|err| invalid(format!("Invalid CBOR text: {}", err)))
+}
+
+/// Read a text string.
+pub fn read_text<R: Read>(reader: &mut R) -> io::Result<String> {
+    let len = expect(read_head(reader)?, TEXT)?;
+    read_text_body(reader, len)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for write_head() and read_head()
+    #[test]
+    fn test_head() {
+        let cases: Vec<(u8, u64, Vec<u8>)> = vec![
+            (UNSIGNED, 0, vec![0x00]),
+            (UNSIGNED, 23, vec![0x17]),
+            (UNSIGNED, 24, vec![0x18, 0x18]),
+            (UNSIGNED, 2048, vec![0x19, 0x08, 0x00]),
+            (UNSIGNED, 1_000_000, vec![0x1a, 0x00, 0x0f, 0x42, 0x40]),
+            (UNSIGNED, 1 << 40, vec![0x1b, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00]),
+            (TEXT, 3, vec![0x63]),
+            (ARRAY, 0, vec![0x80]),
+            (MAP, 4, vec![0xa4]),
+            (TAG, SELF_DESCRIBE, SELF_DESCRIBE_HEAD.to_vec()),
+        ];
+
+        for (major, n, expected) in cases {
+            let mut recieved = vec![];
+            write_head(&mut recieved, major, n).unwrap();
+
+            assert!(
+                recieved == expected,
+                "Expected {:02x?} for ({}, {}), got {:02x?}.",
+                expected, major, n, recieved
+            );
+
+            let read = read_head(&mut expected.as_slice()).unwrap();
+
+            assert!(
+                read == (major, n),
+                "Expected ({}, {}) read back from {:02x?}, got {:?}.",
+                major, n, expected, read
+            )
+        }
+    }
+
+    // Test for read_head() refusing indefinite lengths
+    #[test]
+    fn test_read_head_indefinite() {
+        let recieved = read_head(&mut [0x9fu8].as_slice());
+
+        assert!(
+            recieved.is_err_and(|err| err.kind() == io::ErrorKind::InvalidData),
+            "Expected an indefinite length array refused."
+        )
+    }
+}
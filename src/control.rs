@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, BufRead, Write},
+    sync::mpsc,
+    thread,
+};
+
+
+/// Command to a running recovery, sent as a line of JSON such as
+/// {"cmd":"set_rate","bps":1000000}, and applied between clusters.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Command {
+    Pause,
+    Resume,
+    /// Read at most bps bytes per second, or as fast as possible if 0.
+    SetRate { bps: u64 },
+    /// Save the map now.
+    Checkpoint,
+    /// Stop as SIGINT does, saving the map.
+    Stop,
+}
+
+/// Answer to a line of commands, as a line of JSON naming the command,
+/// if the line was one, and the error, if it failed.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(flatten)]
+    pub command: Option<Command>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn new(command: Option<Command>, result: Result<(), String>) -> Self {
+        Response {
+            ok: result.is_ok(),
+            command,
+            error: result.err(),
+        }
+    }
+
+    /// Write as a line of JSON.
+    pub fn write_line<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer(&mut *writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()
+    }
+}
+
+
+/// Commands to a running recovery, and where to answer them.
+pub struct Control {
+    /// Commands in the order sent, or why a line wasn't one.
+    pub commands: mpsc::Receiver<Result<Command, String>>,
+    pub responses: Box<dyn Write>,
+}
+
+impl std::fmt::Debug for Control {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Control").finish_non_exhaustive()
+    }
+}
+
+impl Control {
+    /// Take commands a line at a time from reader, on a thread of their own
+    /// so reading never holds up recovery, answering them on responses.
+    /// Blank lines are skipped. Once reader ends, no more commands come.
+    pub fn spawn<R: BufRead + Send + 'static>(reader: R, responses: Box<dyn Write>) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let command = serde_json::from_str::<Command>(&line)
+                    .map_err(|err| format!("Invalid command {:?}: {}", line, err));
+
+                // Nobody is listening once the run is over.
+                if tx.send(command).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Control { commands: rx, responses }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // Test for Command parsing
+    #[test]
+    fn test_command_parse() {
+        let cases = vec![
+            (r#"{"cmd":"pause"}"#, Some(Command::Pause)),
+            (r#"{"cmd":"resume"}"#, Some(Command::Resume)),
+            (r#"{"cmd":"set_rate","bps":2000000}"#, Some(Command::SetRate { bps: 2_000_000 })),
+            (r#"{ "cmd": "checkpoint" }"#, Some(Command::Checkpoint)),
+            (r#"{"cmd":"stop"}"#, Some(Command::Stop)),
+            // Missing bps.
+            (r#"{"cmd":"set_rate"}"#, None),
+            (r#"{"cmd":"eject"}"#, None),
+            ("pause", None),
+        ];
+
+        for (line, expected) in cases {
+            let recieved = serde_json::from_str::<Command>(line).ok();
+
+            assert!(
+                recieved == expected,
+                "Expected {:?} from {:?}, got {:?}.",
+                expected, line, recieved
+            )
+        }
+    }
+
+    // Test for Control::spawn()
+    #[test]
+    fn test_spawn() {
+        let lines = "{\"cmd\":\"pause\"}\n\n{\"cmd\":\"eject\"}\n{\"cmd\":\"stop\"}\n";
+        let control = Control::spawn(io::Cursor::new(lines), Box::new(io::sink()));
+
+        let recieved: Vec<Result<Command, String>> = (0..3)
+            .map(|_| control.commands.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+
+        assert!(
+            recieved[0] == Ok(Command::Pause)
+            && recieved[1].is_err()
+            && recieved[2] == Ok(Command::Stop),
+            "Expected pause, an error for the blank line skipped then the \
+            unknown command, and stop, got {:?}.",
+            recieved
+        );
+        assert!(
+            control.commands.recv_timeout(Duration::from_secs(5)).is_err(),
+            "Expected no more commands once the input ended."
+        )
+    }
+
+    // Test for Response::write_line()
+    #[test]
+    fn test_write_line() {
+        let cases = vec![
+            (
+                Response::new(Some(Command::SetRate { bps: 512 }), Ok(())),
+                "{\"ok\":true,\"cmd\":\"set_rate\",\"bps\":512}\n",
+            ),
+            (
+                Response::new(None, Err(String::from("Invalid command"))),
+                "{\"ok\":false,\"error\":\"Invalid command\"}\n",
+            ),
+        ];
+
+        for (response, expected) in cases {
+            let mut recieved = vec![];
+            response.write_line(&mut recieved).unwrap();
+
+            assert!(
+                recieved == expected.as_bytes(),
+                "Expected {:?}, got {:?}.",
+                expected, String::from_utf8_lossy(&recieved)
+            )
+        }
+    }
+}
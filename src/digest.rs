@@ -0,0 +1,555 @@
+use clap::ValueEnum;
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use crate::mapping::{MapFile, Stage};
+
+
+/// Bytes read at once while hashing.
+const CHUNK_LEN: u64 = 1 << 20;
+
+/// Round constants of SHA-256, from FIPS 180-4.
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Initial state of SHA-256.
+const SHA256_H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Initial state of SHA-1.
+const SHA1_H: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+/// Round constants of MD5, from RFC 1321.
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Per round rotations of MD5, repeating every four steps.
+const MD5_S: [[u32; 4]; 4] = [[7, 12, 17, 22], [5, 9, 14, 20], [4, 11, 16, 23], [6, 10, 15, 21]];
+
+/// Initial state of MD5.
+const MD5_H: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+/// CRC-32 (IEEE 802.3) lookup table, for the reflected polynomial.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xedb88320,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+};
+
+
+/// Checksum algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Algorithm {
+    Sha256,
+    Sha1,
+    Md5,
+    Crc32,
+}
+
+
+/// Input to a hash, buffered into the 64 byte blocks
+/// which SHA-256, SHA-1 and MD5 each compress.
+#[derive(Clone, Debug)]
+struct Blocks {
+    block: [u8; 64],
+    block_len: usize,
+    /// Bytes hashed in total.
+    len: u64,
+}
+
+impl Default for Blocks {
+    fn default() -> Self {
+        Blocks {
+            block: [0; 64],
+            block_len: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Blocks {
+    /// Buffer data, passing each block to compress as it fills.
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+        self.len += data.len() as u64;
+
+        while !data.is_empty() {
+            let n = (64 - self.block_len).min(data.len());
+
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+
+            if self.block_len == 64 {
+                compress(&self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Pad out the last block, ending it with the length hashed in bits,
+    /// big endian for the SHA family, and little endian for MD5.
+    fn finish(&mut self, is_big_endian: bool, mut compress: impl FnMut(&[u8; 64])) {
+        let bits = self.len * 8;
+
+        self.update(&[0x80], &mut compress);
+
+        while self.block_len != 56 {
+            self.update(&[0], &mut compress);
+        }
+
+        match is_big_endian {
+            true => self.update(&bits.to_be_bytes(), &mut compress),
+            false => self.update(&bits.to_le_bytes(), &mut compress),
+        }
+    }
+}
+
+
+/// Streaming SHA-256.
+#[derive(Clone, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            state: SHA256_H,
+            blocks: Blocks::default(),
+        }
+    }
+}
+
+impl Sha256 {
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| Self::compress(state, block));
+    }
+
+    /// Pad out the last block, and return the digest.
+    pub fn finish(mut self) -> [u8; 32] {
+        let state = &mut self.state;
+        self.blocks.finish(true, |block| Self::compress(state, block));
+
+        let mut digest = [0u8; 32];
+
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        digest
+    }
+
+    fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, word) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(word);
+        }
+    }
+}
+
+
+/// Streaming SHA-1, for tools which expect it.
+/// Broken for signing, but fine for checking an image was copied intact.
+#[derive(Clone, Debug)]
+pub struct Sha1 {
+    state: [u32; 5],
+    blocks: Blocks,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Sha1 {
+            state: SHA1_H,
+            blocks: Blocks::default(),
+        }
+    }
+}
+
+impl Sha1 {
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| Self::compress(state, block));
+    }
+
+    /// Pad out the last block, and return the digest.
+    pub fn finish(mut self) -> [u8; 20] {
+        let state = &mut self.state;
+        self.blocks.finish(true, |block| Self::compress(state, block));
+
+        let mut digest = [0u8; 20];
+
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        digest
+    }
+
+    fn compress(state: &mut [u32; 5], block: &[u8; 64]) {
+        let mut w = [0u32; 80];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
+
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+
+        for (state, word) in state.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(word);
+        }
+    }
+}
+
+
+/// Streaming MD5, for tools which expect it.
+/// Broken for signing, but fine for checking an image was copied intact.
+#[derive(Clone, Debug)]
+pub struct Md5 {
+    state: [u32; 4],
+    blocks: Blocks,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Md5 {
+            state: MD5_H,
+            blocks: Blocks::default(),
+        }
+    }
+}
+
+impl Md5 {
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| Self::compress(state, block));
+    }
+
+    /// Pad out the last block, and return the digest.
+    pub fn finish(mut self) -> [u8; 16] {
+        let state = &mut self.state;
+        self.blocks.finish(false, |block| Self::compress(state, block));
+
+        let mut digest = [0u8; 16];
+
+        for (bytes, word) in digest.chunks_mut(4).zip(self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+
+        digest
+    }
+
+    fn compress(state: &mut [u32; 4], block: &[u8; 64]) {
+        let mut m = [0u32; 16];
+
+        for (i, word) in block.chunks(4).enumerate() {
+            m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        }
+
+        let [mut a, mut b, mut c, mut d] = *state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f
+                .wrapping_add(a)
+                .wrapping_add(MD5_K[i])
+                .wrapping_add(m[g]);
+
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_S[i / 16][i % 4]));
+        }
+
+        for (state, word) in state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(word);
+        }
+    }
+}
+
+
+/// Streaming CRC-32, as used by zip and gzip.
+#[derive(Clone, Debug)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32 { crc: !0 }
+    }
+}
+
+impl Crc32 {
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = (self.crc >> 8) ^ CRC32_TABLE[((self.crc ^ byte as u32) & 0xff) as usize];
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.crc
+    }
+}
+
+
+/// Digest of the bytes in ranges of reader, concatenated, as hex.
+/// Ranges are read a chunk at a time, so may be any length.
+pub fn digest_ranges<R: Read + Seek>(
+    reader: &mut R,
+    ranges: &[Range<u64>],
+    algorithm: Algorithm,
+) -> io::Result<String> {
+    let mut sha256 = Sha256::default();
+    let mut sha1 = Sha1::default();
+    let mut md5 = Md5::default();
+    let mut crc32 = Crc32::default();
+    let mut buf = vec![0u8; CHUNK_LEN as usize];
+
+    for range in ranges {
+        reader.seek(SeekFrom::Start(range.start))?;
+
+        let mut pos = range.start;
+
+        while pos < range.end {
+            let len = (range.end - pos).min(CHUNK_LEN) as usize;
+            reader.read_exact(&mut buf[..len])?;
+
+            match algorithm {
+                Algorithm::Sha256 => sha256.update(&buf[..len]),
+                Algorithm::Sha1 => sha1.update(&buf[..len]),
+                Algorithm::Md5 => md5.update(&buf[..len]),
+                Algorithm::Crc32 => crc32.update(&buf[..len]),
+            }
+
+            pos += len as u64;
+        }
+    }
+
+    let hex = |bytes: &[u8]| bytes.iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    Ok(match algorithm {
+        Algorithm::Sha256 => hex(&sha256.finish()),
+        Algorithm::Sha1 => hex(&sha1.finish()),
+        Algorithm::Md5 => hex(&md5.finish()),
+        Algorithm::Crc32 => format!("{:08x}", crc32.finish()),
+    })
+}
+
+/// Byte ranges of map's recovered sectors, in order,
+/// cut short at len for a last sector past the end of the output.
+pub fn recovered_ranges(map: &MapFile, len: u64) -> Vec<Range<u64>> {
+    let sector_size = map.sector_size as u64;
+
+    let mut domains = map.get_domains(Stage::Recovered);
+    domains.append(&mut map.get_domains(Stage::Verified));
+    domains.sort_by_key(|d| d.start);
+
+    domains.iter()
+        .map(|d| (d.start as u64 * sector_size).min(len)..(d.end as u64 * sector_size).min(len))
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for Sha256, Sha1, Md5 and Crc32
+    #[test]
+    fn test_known_digests() {
+        let cases = vec![
+            (
+                b"".to_vec(),
+                [
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+                    "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+                    "d41d8cd98f00b204e9800998ecf8427e",
+                    "00000000",
+                ],
+            ),
+            (
+                b"abc".to_vec(),
+                [
+                    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                    "a9993e364706816aba3e25717850c26c9cd0d89d",
+                    "900150983cd24fb0d6963f7d28e17f72",
+                    "352441c2",
+                ],
+            ),
+            (
+                b"123456789".to_vec(),
+                [
+                    "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+                    "f7c3bc1d808e04732adf679965ccc34ca7ae3441",
+                    "25f9e794323b453885f5181f1b624d0b",
+                    "cbf43926",
+                ],
+            ),
+            // Spans many blocks, and more than one chunk.
+            (
+                vec![b'a'; 1_000_000],
+                [
+                    "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0",
+                    "34aa973cd4c4daa4f61eeb2bdbad27316534016f",
+                    "7707d6ae4e027c70eea2a935c2296f21",
+                    "dc25bfbc",
+                ],
+            ),
+        ];
+        let algorithms = [Algorithm::Sha256, Algorithm::Sha1, Algorithm::Md5, Algorithm::Crc32];
+
+        for (data, digests) in cases {
+            let len = data.len() as u64;
+            let mut reader = io::Cursor::new(data);
+
+            for (algorithm, expected) in algorithms.into_iter().zip(digests) {
+                let recieved = digest_ranges(&mut reader, &[Range { start: 0, end: len }], algorithm).unwrap();
+
+                assert!(
+                    recieved == expected,
+                    "Expected {:?} digest {} of {} bytes, got {}.",
+                    algorithm, expected, len, recieved
+                )
+            }
+        }
+    }
+
+    // Test for recovered_ranges() with digest_ranges()
+    #[test]
+    fn test_digest_recovered() {
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 2,
+            domain: (start: 0, end: 5),
+            map: [
+                (domain: (start: 0, end: 1), stage: Recovered),
+                (domain: (start: 1, end: 2), stage: Damaged),
+                (domain: (start: 2, end: 3), stage: Verified),
+                (domain: (start: 3, end: 4), stage: Untested),
+                (domain: (start: 4, end: 5), stage: Recovered),
+            ],
+        )").unwrap();
+
+        // Only the recovered bytes spell out "abcde", the last sector cut short.
+        let mut output = io::Cursor::new(b"ab??cd??e".to_vec());
+        let ranges = recovered_ranges(&map, 9);
+
+        assert!(ranges == vec![0..2, 4..6, 8..9], "Got ranges {:?}.", ranges);
+
+        let recieved = digest_ranges(&mut output, &ranges, Algorithm::Sha256).unwrap();
+
+        assert!(
+            recieved == "36bbe50ed96841d10443bcb670d6554f0a34b761be67ec9c4a8ad2c0c44ca42c",
+            "Expected the digest of \"abcde\", got {}.",
+            recieved
+        )
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs::File, io};
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+
+use crate::mapping::{Domain, MapFile, Stage};
+
+
+/// BLKDISCARD from linux/fs.h, which libc doesn't export.
+#[cfg(target_os = "linux")]
+const BLKDISCARD: libc::Ioctl = 0x1277;
+
+
+/// A device which can have byte ranges discarded (TRIM).
+pub trait Discard {
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()>;
+}
+
+impl Discard for File {
+    /// Issue BLKDISCARD over the byte range.
+    /// Only meaningful if the file is a block device.
+    #[cfg(target_os = "linux")]
+    fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        let range: [u64; 2] = [offset, len];
+
+        match unsafe { libc::ioctl(self.as_raw_fd(), BLKDISCARD, &range) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn discard(&mut self, _offset: u64, _len: u64) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+
+/// Discard all regions of the map at any of stages,
+/// such as those untested and damaged.
+pub fn discard_unrecovered<D: Discard>(
+    device: &mut D,
+    map: &MapFile,
+    stages: &[Stage],
+) -> io::Result<()> {
+    let sector_size = map.sector_size as u64;
+
+    let mut domains: Vec<Domain> = stages.iter()
+        .flat_map(|&stage| map.get_domains(stage))
+        .collect();
+    domains.sort_by_key(|d| d.start);
+
+    for domain in domains {
+        device.discard(
+            domain.start as u64 * sector_size,
+            domain.len() as u64 * sector_size,
+        )?;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records requested ranges rather than issuing the ioctl.
+    #[derive(Default)]
+    struct MockDevice {
+        ranges: Vec<(u64, u64)>,
+    }
+
+    impl Discard for MockDevice {
+        fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            self.ranges.push((offset, len));
+            Ok(())
+        }
+    }
+
+    // Test for discard_unrecovered()
+    #[test]
+    fn test_discard_unrecovered() {
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 10),
+            map: [
+                (domain: (start: 0, end: 2), stage: Untested),
+                (domain: (start: 2, end: 4), stage: ForIsolation(0)),
+                (domain: (start: 4, end: 7), stage: Damaged),
+                (domain: (start: 7, end: 10), stage: Untested),
+            ],
+        )").unwrap();
+
+        let cases = vec![
+            (
+                vec![Stage::Untested, Stage::Damaged],
+                vec![(0, 1024), (2048, 1536), (3584, 1536)],
+            ),
+            // Such as with damaged regions filled, and worth keeping.
+            (vec![Stage::Untested], vec![(0, 1024), (3584, 1536)]),
+        ];
+
+        for (stages, expected) in cases {
+            let mut device = MockDevice::default();
+            discard_unrecovered(&mut device, &map, &stages).unwrap();
+
+            assert!(
+                expected == device.ranges,
+                "Expected discarded ranges {:?} for {:?}, got {:?}.",
+                expected, stages, device.ranges
+            )
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use ron::de::SpannedError;
+use std::{
+    error::Error,
+    fmt,
+    io,
+    process::ExitCode,
+};
+
+
+// Exit codes follow sysexits.h, so scripts can tell failures apart.
+
+/// Arguments are invalid.
+const EXIT_USAGE: u8 = 64;
+/// Input couldn't be opened or read.
+const EXIT_INPUT: u8 = 66;
+/// Output couldn't be created or written.
+const EXIT_OUTPUT: u8 = 73;
+/// Map, retry list or sector map is malformed.
+const EXIT_DATA: u8 = 65;
+/// Map couldn't be opened or saved.
+const EXIT_IO: u8 = 74;
+/// Stopped by SIGINT, as a shell reports it.
+const EXIT_INTERRUPTED: u8 = 130;
+/// Some inputs of a batch failed, each reported as it did.
+const EXIT_BATCH: u8 = 1;
+
+
+/// Fatal errors, reported to the user before exiting.
+#[derive(Debug)]
+pub enum KramerError {
+    /// Arguments are invalid, such as bounds outside of the input.
+    Args(String),
+    /// Input couldn't be opened or read.
+    Input(io::Error),
+    /// Output couldn't be created, sized or written.
+    Output(io::Error),
+    /// Map couldn't be opened or saved.
+    Map(io::Error),
+    /// Map couldn't be parsed.
+    MapParse(SpannedError),
+    /// Map parsed, but can't be used as is.
+    BadMap(String),
+    /// Retry list couldn't be read or parsed.
+    RetryList(String),
+    /// Sector map couldn't be read or parsed, or lacks a name asked for.
+    SectorMap(String),
+    /// Profile couldn't be read or parsed.
+    Profile(String),
+    /// Recovery was stopped early, with the map saved.
+    Interrupted,
+    /// Inputs of a batch failed, of how many.
+    Batch(usize, usize),
+}
+
+impl KramerError {
+    /// Process exit code to report this error with.
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            KramerError::Args(_) => EXIT_USAGE,
+            KramerError::Input(_) => EXIT_INPUT,
+            KramerError::Output(_) => EXIT_OUTPUT,
+            KramerError::Map(_) => EXIT_IO,
+            KramerError::MapParse(_)
+            | KramerError::BadMap(_)
+            | KramerError::RetryList(_)
+            | KramerError::SectorMap(_)
+            | KramerError::Profile(_) => EXIT_DATA,
+            KramerError::Interrupted => EXIT_INTERRUPTED,
+            KramerError::Batch(..) => EXIT_BATCH,
+        })
+    }
+}
+
+impl fmt::Display for KramerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KramerError::Args(msg) => write!(f, "Invalid arguments: {}", msg),
+            KramerError::Input(err) => write!(f, "Input: {}", err),
+            KramerError::Output(err) if err.kind() == io::ErrorKind::StorageFull => write!(
+                f,
+                "Output: {}. Mapping file saved, free some space and rerun to resume.",
+                err,
+            ),
+            KramerError::Output(err) => write!(f, "Output: {}", err),
+            KramerError::Map(err) => write!(f, "Mapping file: {}", err),
+            KramerError::MapParse(err) => write!(f, "Failed to parse mapping file: {}", err),
+            KramerError::BadMap(msg) => write!(f, "Bad mapping file: {}", msg),
+            KramerError::RetryList(msg) => write!(f, "Retry list: {}", msg),
+            KramerError::SectorMap(msg) => write!(f, "Sector map: {}", msg),
+            KramerError::Profile(msg) => write!(f, "Profile: {}", msg),
+            KramerError::Interrupted => {
+                write!(f, "Interrupted. Mapping file saved, rerun to resume.")
+            },
+            KramerError::Batch(failed, total) => {
+                write!(f, "{} of {} inputs of the batch failed", failed, total)
+            },
+        }
+    }
+}
+
+impl Error for KramerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KramerError::Input(err)
+            | KramerError::Output(err)
+            | KramerError::Map(err) => Some(err),
+            KramerError::MapParse(err) => Some(err),
+            KramerError::Args(_)
+            | KramerError::BadMap(_)
+            | KramerError::RetryList(_)
+            | KramerError::SectorMap(_)
+            | KramerError::Profile(_)
+            | KramerError::Interrupted
+            | KramerError::Batch(..) => None,
+        }
+    }
+}
+
+impl From<SpannedError> for KramerError {
+    fn from(err: SpannedError) -> Self {
+        KramerError::MapParse(err)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for KramerError::exit_code()
+    #[test]
+    fn test_exit_code() {
+        let cases = vec![
+            (KramerError::Input(io::Error::from(io::ErrorKind::NotFound)), EXIT_INPUT),
+            (KramerError::Output(io::Error::from(io::ErrorKind::PermissionDenied)), EXIT_OUTPUT),
+            (KramerError::BadMap(String::from("Overlapping clusters")), EXIT_DATA),
+        ];
+
+        for (err, expected) in cases {
+            assert!(
+                err.exit_code() == ExitCode::from(expected),
+                "Expected exit code {} for {:?}.",
+                expected, err
+            )
+        }
+    }
+
+    // Test for KramerError's Display on a full output
+    #[test]
+    fn test_display_output_full() {
+        let cases = vec![
+            (io::ErrorKind::StorageFull, true),
+            (io::ErrorKind::PermissionDenied, false),
+        ];
+
+        for (kind, expected) in cases {
+            let recieved = KramerError::Output(io::Error::from(kind)).to_string();
+
+            assert!(
+                recieved.contains("rerun to resume") == expected,
+                "Expected resume hint {} for {:?}, got {:?}.",
+                if expected { "given" } else { "omitted" }, kind, recieved
+            )
+        }
+    }
+}
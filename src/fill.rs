@@ -0,0 +1,76 @@
+/// Bytes written repeatedly over damaged regions of the output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern(pub Vec<u8>);
+
+impl Pattern {
+    /// Fill buf with the pattern repeated from its start,
+    /// truncating the last repetition to fit.
+    pub fn fill(&self, buf: &mut [u8]) {
+        for (byte, pattern) in buf.iter_mut().zip(self.0.iter().cycle()) {
+            *byte = *pattern;
+        }
+    }
+}
+
+/// Parse a pattern from hex, such as BAD or 0xFF.
+/// Odd lengths are read as if led by a 0, so BAD is 0x0B 0xAD.
+pub fn parse_pattern(s: &str) -> Result<Pattern, String> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix("0x")
+        .or(hex.strip_prefix("0X"))
+        .unwrap_or(hex);
+
+    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Expected a hex pattern such as 0xFF, got {:?}", s));
+    }
+
+    let hex = match hex.len() % 2 {
+        0 => hex.to_owned(),
+        _ => format!("0{}", hex),
+    };
+
+    Ok(Pattern(
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for parse_pattern()
+    #[test]
+    fn test_parse_pattern() {
+        let cases = vec![
+            ("FF", Ok(vec![0xff])),
+            ("0xBAD", Ok(vec![0x0b, 0xad])),
+            ("deadbeef", Ok(vec![0xde, 0xad, 0xbe, 0xef])),
+            ("", Err(())),
+            ("0x", Err(())),
+            ("0xBADG", Err(())),
+        ];
+
+        for (s, expected) in cases {
+            let recieved = parse_pattern(s);
+
+            assert!(
+                recieved.to_owned().map(|p| p.0).map_err(|_| ()) == expected,
+                "Expected {:?} for {:?}, got {:?}.",
+                expected, s, recieved
+            )
+        }
+    }
+
+    // Test for Pattern::fill()
+    #[test]
+    fn test_fill() {
+        let mut buf = [0u8; 8];
+        Pattern(vec![1, 2, 3]).fill(&mut buf);
+
+        assert!(buf == [1, 2, 3, 1, 2, 3, 1, 2])
+    }
+}
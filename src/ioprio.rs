@@ -0,0 +1,115 @@
+use clap::ValueEnum;
+use log::warn;
+use std::io;
+
+
+/// Shift of the class within an ioprio value, from linux/ioprio.h.
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+
+/// ioprio_set(2) "which" for a single process.
+#[cfg(target_os = "linux")]
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+/// Level within the realtime and best-effort classes, 0 (high) to 7 (low).
+/// 4 is what the kernel assumes when none was set.
+const IOPRIO_DEFAULT_LEVEL: i32 = 4;
+
+
+/// IO scheduling class.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum IoPriority {
+    /// Only serviced when no other process wants the disk
+    Idle,
+    /// Normal scheduling
+    BestEffort,
+    /// Serviced ahead of everything else
+    Rt,
+}
+
+impl IoPriority {
+    /// Encode as an ioprio value.
+    pub fn ioprio(self) -> i32 {
+        let (class, level) = match self {
+            IoPriority::Rt => (1, IOPRIO_DEFAULT_LEVEL),
+            IoPriority::BestEffort => (2, IOPRIO_DEFAULT_LEVEL),
+            IoPriority::Idle => (3, 0),
+        };
+
+        class << IOPRIO_CLASS_SHIFT | level
+    }
+}
+
+
+/// Something whose IO priority can be set.
+pub trait SetIoPriority {
+    fn set_ioprio(&mut self, ioprio: i32) -> io::Result<()>;
+}
+
+/// The running process.
+pub struct CurrentProcess;
+
+impl SetIoPriority for CurrentProcess {
+    #[cfg(target_os = "linux")]
+    fn set_ioprio(&mut self, ioprio: i32) -> io::Result<()> {
+        // pid 0 is the calling process.
+        match unsafe {
+            libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio)
+        } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_ioprio(&mut self, _ioprio: i32) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+
+/// Request priority, warning rather than failing if it can't be set.
+pub fn set_io_priority<S: SetIoPriority>(target: &mut S, priority: IoPriority) {
+    if let Err(err) = target.set_ioprio(priority.ioprio()) {
+        warn!("Failed to set IO priority {:?}, continuing: {:?}", priority, err);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the requested ioprio rather than making the syscall.
+    #[derive(Default)]
+    struct MockProcess {
+        ioprio: Option<i32>,
+    }
+
+    impl SetIoPriority for MockProcess {
+        fn set_ioprio(&mut self, ioprio: i32) -> io::Result<()> {
+            self.ioprio = Some(ioprio);
+            Ok(())
+        }
+    }
+
+    // Test for set_io_priority()
+    #[test]
+    fn test_set_io_priority() {
+        let cases = vec![
+            (IoPriority::Rt, (1 << 13) | 4),
+            (IoPriority::BestEffort, (2 << 13) | 4),
+            (IoPriority::Idle, 3 << 13),
+        ];
+
+        for (priority, expected) in cases {
+            let mut process = MockProcess::default();
+            set_io_priority(&mut process, priority);
+
+            assert!(
+                process.ioprio == Some(expected),
+                "Expected ioprio {:#x} for {:?}, got {:?}.",
+                expected, priority, process.ioprio
+            )
+        }
+    }
+}
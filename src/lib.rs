@@ -0,0 +1,409 @@
+//! Recovery of data from failing media, such as scratched optical discs.
+//!
+//! Recover copies good data from an input to an output, isolating bad
+//! regions, as tracked by a MapFile of sector ranges and their Stage.
+
+pub mod benchmark;
+pub mod buffer;
+pub mod cache;
+pub mod cbor;
+pub mod control;
+pub mod digest;
+pub mod discard;
+pub mod error;
+pub mod fill;
+pub mod ioprio;
+pub mod logger;
+pub mod mapping;
+#[cfg(feature = "optical")]
+pub mod optical;
+pub mod platform;
+pub mod profile;
+pub mod progress;
+pub mod recovery;
+pub mod segment;
+pub mod signal;
+pub mod size;
+pub mod space;
+#[cfg(test)]
+mod testing;
+pub mod throttle;
+pub mod timeout;
+
+use clap::{Parser, ValueEnum};
+use ioprio::IoPriority;
+use std::path::PathBuf;
+
+
+pub const FB_SECTOR_SIZE: u16 = 2048;
+
+
+/// How --list-bad prints damaged regions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum BadFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+
+/// What sector offsets are shown counting from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OffsetBase {
+    /// The start of the input.
+    #[default]
+    Absolute,
+    /// --start.
+    Relative,
+}
+
+impl OffsetBase {
+    /// Sector offsets are shown counting from, with --start at start.
+    pub fn origin(self, start: usize) -> usize {
+        match self {
+            OffsetBase::Absolute => 0,
+            OffsetBase::Relative => start,
+        }
+    }
+}
+
+
+// Recovery options, as parsed from the command line.
+// Not a doc comment, as clap would show it as the command description.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Path to source file or block device, or a directory of them to
+    /// recover each in turn
+    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+    pub input: PathBuf,
+
+    /// Path to output file. Defaults to input path with extension .iso.
+    /// For a directory of inputs, the directory to put each output in
+    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+    pub output: Option<PathBuf>,
+
+    /// Path to rescue map. Defaults to input path with extension .map.
+    /// For a directory of inputs, the directory to put each map in
+    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
+    pub map: Option<PathBuf>,
+
+    /// Format to save the map in. Maps are read in whichever format they
+    /// were saved in, so this converts them. Defaults to the format read,
+    /// or for new maps, that of a .ron, .json or .cbor extension, else ron
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub map_format: Option<mapping::MapFormat>,
+
+    /// Format to read the map in, rather than detecting it from its first
+    /// bytes. Also saved in, unless --map-format is given
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub map_input_format: Option<mapping::MapFormat>,
+
+    /// Print the map formats supported, and that detected for the map if
+    /// there is one, and exit. Only the map is read, so the input needn't
+    /// be present
+    #[arg(long)]
+    pub list_formats: bool,
+
+    /// Max number of consecutive sectors to test as a group
+    #[arg(short, long, default_value_t = 128)]
+    pub cluster_length: u16,
+
+    /// Max bytes to test as a group, such as 256K, instead of --cluster-length.
+    /// Must be a whole number of sectors
+    #[arg(long, value_parser = size::parse_size, conflicts_with = "cluster_length")]
+    pub cluster_size: Option<u64>,
+
+    /// After a failed read, skip ahead by a growing stride of up to this
+    /// many bytes, such as 64M, returning to what was skipped afterwards.
+    /// Maps out large bad regions quickly. Off by default
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size)]
+    pub skip_size: Option<u64>,
+
+    /// Threads to read untested clusters with, each with its own handle on
+    /// the input. Isolating damaged regions is always single threaded
+    #[arg(long, default_value_t = 1, value_name = "N", conflicts_with = "skip_size")]
+    pub threads: usize,
+
+    /// RON file of options to use unless given here, such as for a model of
+    /// drive. Holds any of sector_size, cluster_length, brute_passes,
+    /// max_isolation_level and read_timeout, as in (sector_size: 512)
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    pub profile: Option<PathBuf>,
+
+    /// Number of brute force read passes
+    #[arg(short, long, default_value_t = 2)]
+    pub brute_passes: usize,
+
+    /// Isolation passes to run before giving up on sectors as damaged.
+    /// The last reads single sectors, however long clusters are
+    #[arg(long, default_value_t = 4, value_name = "N", value_parser = clap::value_parser!(u8).range(1..))]
+    pub max_isolation_level: u8,
+
+    /// Times in a row a sector must fail to read, each in a later pass,
+    /// before it's mapped damaged. Until then it's isolated again, for
+    /// drives which only read some sectors now and then
+    #[arg(long, default_value_t = 1, value_name = "N", value_parser = clap::value_parser!(u8).range(1..))]
+    pub confirm_damaged: u8,
+
+    /// Sector size in bytes, such as 512 or 4K
+    #[arg(short, long, default_value_t = FB_SECTOR_SIZE, value_parser = size::parse_size_u16)]
+    pub sector_size: u16,
+
+    /// First sector to recover. Defaults to the start of the input
+    #[arg(long, value_name = "SECTOR")]
+    pub start: Option<usize>,
+
+    /// Sector to stop recovering at, exclusive. Defaults to the end of the input
+    #[arg(long, value_name = "SECTOR")]
+    pub end: Option<usize>,
+
+    /// Show offsets in logs, --dry-run, --list-bad and --badblocks counting
+    /// from the start of the input, or from --start. Relative reports only
+    /// cover --start to --end. Maps always count from the start of the input
+    #[arg(long, value_enum, value_name = "BASE", default_value_t = OffsetBase::Absolute)]
+    pub offset_base: OffsetBase,
+
+    /// Sector of the input to read as the first of the output and map,
+    /// such as the start of a partition within a disk image
+    #[arg(long, value_name = "SECTOR", default_value_t = 0)]
+    pub input_offset: usize,
+
+    /// Read at most SIZE bytes per second, such as 2M, to go easy on
+    /// failing hardware. 0 is unlimited
+    #[arg(long, value_name = "SIZE", default_value_t = 0, value_parser = size::parse_size)]
+    pub rate_limit: u64,
+
+    /// Read clusters from the end of the input backwards
+    #[arg(long)]
+    pub reverse: bool,
+
+    /// Isolate failed reads within each stretch of the input as soon as it's
+    /// copied, rather than once all of it is, so data near errors comes first
+    #[arg(long, conflicts_with_all = ["skip_size", "threads"])]
+    pub interleave: bool,
+
+    /// Read everything left in a single pass from start to end, isolating
+    /// failed reads where they're found, so the input is never read
+    /// backwards. Gentler on failing drives' heads, but slower to recover
+    /// most of the data
+    #[arg(
+        long,
+        conflicts_with_all = ["skip_size", "threads", "reverse", "interleave", "verify"],
+    )]
+    pub sweep: bool,
+
+    /// Start copying with reads of a single sector, doubling their length
+    /// each time several in a row succeed, up to the cluster length.
+    /// Lets cold or marginal drives warm up before reading at full size
+    #[arg(long, conflicts_with_all = ["threads", "interleave", "sweep"])]
+    pub gentle_start: bool,
+
+    /// Seconds between saves of the map during recovery
+    #[arg(long, visible_alias = "autosave-interval", default_value_t = 60, value_name = "SECONDS")]
+    pub save_interval: u64,
+
+    /// Don't keep the map before each save as {map}.bak, which otherwise
+    /// holds the previous map should a save go wrong
+    #[arg(long)]
+    pub no_map_backup: bool,
+
+    /// Once recovery can go no further, re-read recovered data and
+    /// isolate again any which no longer matches the output.
+    /// Clusters checked are mapped as verified, so if interrupted,
+    /// running again resumes from those left
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Once each pass finishes, re-read what it recovered and isolate again
+    /// any which no longer matches the output, before the next pass
+    #[arg(long, conflicts_with = "scan_only")]
+    pub verify_after_pass: bool,
+
+    /// Hash N sectors spread across the input before recovery and again
+    /// after, warning of any which read differently, as a source changing
+    /// under recovery leaves the output inconsistent. Kept in the map,
+    /// unless saved as a ddrescue mapfile
+    #[arg(long, value_name = "N")]
+    pub sample_source: Option<usize>,
+
+    /// Give up on reads taking longer than this, treating them as failed.
+    /// 0 waits forever
+    #[arg(long, default_value_t = 0, value_name = "MS")]
+    pub read_timeout: u64,
+
+    /// On resume, use the map's sector size unless --sector-size is given.
+    /// Otherwise, a map of another sector size is refused
+    #[arg(long)]
+    pub use_map_sector_size: bool,
+
+    /// Bytes to keep free on the output filesystem. The run is refused if
+    /// the rest of the output wouldn't fit alongside them, and checked
+    /// again every --save-interval, ending with the map saved if not
+    #[arg(long, default_value_t = 0, value_name = "BYTES")]
+    pub min_free: u64,
+
+    /// Shrink an existing output longer than the input, discarding its tail
+    #[arg(long)]
+    pub truncate_output_to_input: bool,
+
+    /// Refuse an output too short for the sectors to recover,
+    /// rather than extending it, such as when writing to a partition
+    #[arg(long)]
+    pub no_autoextend: bool,
+
+    /// Report per-stage sector counts over N equal zones of the map and exit
+    #[arg(long, value_name = "N")]
+    pub zone_stats: Option<usize>,
+
+    /// Print the damaged regions of the map as byte and sector ranges and
+    /// exit. Only the map is read, so the input needn't be present
+    #[arg(long)]
+    pub list_bad: bool,
+
+    /// Format to print --list-bad in
+    #[arg(long, value_enum, default_value_t = BadFormat::Text, value_name = "FORMAT")]
+    pub bad_format: BadFormat,
+
+    /// Write the blocks of the map holding damaged sectors to FILE, one block
+    /// number per line, for badblocks or e2fsck -l, and exit.
+    /// Only the map is read, so the input needn't be present
+    #[arg(long, value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    pub badblocks: Option<PathBuf>,
+
+    /// Block size of the filesystem to write --badblocks for.
+    /// Defaults to the map's sector size
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size, requires = "badblocks")]
+    pub badblocks_blocksize: Option<u64>,
+
+    /// Time reads of one cluster at N evenly spaced offsets of the input,
+    /// report read speed by offset and exit. Nothing is written
+    #[arg(long, value_name = "N")]
+    pub benchmark_device: Option<usize>,
+
+    /// Hex pattern, such as 0xBAD or FF, to write repeatedly over sectors
+    /// once they're found damaged. By default they're left untouched
+    #[arg(long, value_name = "HEX", value_parser = fill::parse_pattern)]
+    pub fill_pattern: Option<fill::Pattern>,
+
+    /// Once a run completes, checksum the recovered sectors of the output,
+    /// printing it and saving it beside the output with extension .digest
+    #[arg(long, value_enum, value_name = "ALGO")]
+    pub digest: Option<digest::Algorithm>,
+
+    /// Checksum the whole output with --digest, not only recovered sectors
+    #[arg(long, requires = "digest")]
+    pub digest_whole: bool,
+
+    /// Once a run completes, checksum the whole output with ALGO, as
+    /// --digest with --digest-whole does, noting if the image is incomplete
+    #[arg(long, value_enum, value_name = "ALGO", conflicts_with = "digest")]
+    pub final_hash: Option<digest::Algorithm>,
+
+    /// Print the clusters the next pass would read and exit,
+    /// without creating or writing to the output
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Read and map the input without copying it anywhere, such as to check
+    /// a drive's health. The output is never created or written to
+    #[arg(long, conflicts_with_all = ["dry_run", "verify", "digest"])]
+    pub scan_only: bool,
+
+    /// Print reports, and the summary after a run, as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Take commands on stdin, a line of JSON each, applied between
+    /// clusters: {"cmd":"pause"}, {"cmd":"resume"}, {"cmd":"set_rate","bps":N},
+    /// {"cmd":"checkpoint"} to save the map, and {"cmd":"stop"}.
+    /// Each is answered with a line of JSON on stdout
+    #[arg(long, conflicts_with = "threads")]
+    pub control_stdin: bool,
+
+    /// File naming sector ranges, such as those of each file on a filesystem,
+    /// as start..end then the name, one per line. Names may repeat,
+    /// for files in several fragments
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath,
+        requires = "recover_only",
+    )]
+    pub sector_map: Option<PathBuf>,
+
+    /// Only recover the ranges of --sector-map with these names,
+    /// within --start and --end
+    #[arg(long, value_name = "NAMES", value_delimiter = ',', requires = "sector_map")]
+    pub recover_only: Vec<String>,
+
+    /// File of damaged sector ranges (start..end, one per line) to retry
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    pub retry_list: Option<PathBuf>,
+
+    /// Read damaged sectors within --start and --end again,
+    /// such as after the drive has cooled down
+    #[arg(long)]
+    pub retry_damaged: bool,
+
+    /// Image from an earlier, unfinished rescue to copy the sectors it
+    /// recovered from, as mapped by --prefill-map, before reading the input.
+    /// Those sectors are mapped as recovered, so are never read from it
+    #[arg(
+        long,
+        value_name = "IMAGE",
+        value_hint = clap::ValueHint::FilePath,
+        requires = "prefill_map",
+        conflicts_with_all = ["scan_only", "dry_run"],
+    )]
+    pub prefill_from: Option<PathBuf>,
+
+    /// Map of --prefill-from, in any format a map is read in
+    #[arg(
+        long,
+        value_name = "MAP",
+        value_hint = clap::ValueHint::FilePath,
+        requires = "prefill_from",
+    )]
+    pub prefill_map: Option<PathBuf>,
+
+    /// Repair inconsistent maps (e.g. overlapping clusters) rather than aborting
+    #[arg(long)]
+    pub force: bool,
+
+    /// IO scheduling class to run under, so recovery can yield to other tasks
+    #[arg(long, value_enum)]
+    pub io_priority: Option<IoPriority>,
+
+    /// Discard (TRIM) untested and damaged regions of a block device output.
+    /// Damaged regions are kept if filled with --fill-pattern
+    #[arg(long)]
+    pub discard_unrecovered: bool,
+
+    /// Split the output into numbered files of SIZE bytes each, such as
+    /// out.000 and out.001, rather than one file. Must be whole sectors
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size)]
+    pub split_size: Option<u64>,
+
+    /// Gather recovered sectors into writes of SIZE bytes, at offsets a
+    /// multiple of it, for outputs faster at larger writes. Reads are
+    /// unchanged. Must be whole sectors
+    #[arg(long, value_name = "SIZE", value_parser = size::parse_size, conflicts_with = "sparse")]
+    pub write_block_size: Option<u64>,
+
+    /// Leave holes in the output for all-zero sectors rather than writing
+    /// them, so a mostly empty input doesn't claim its full size on disk
+    #[arg(long)]
+    pub sparse: bool,
+
+    /// Distrust sectors read as nothing but zeros, as some failing drives
+    /// return for bad sectors rather than an error. They're retried as
+    /// failed reads are, and those still read as zeros marked damaged.
+    /// Only for inputs where zeroed sectors shouldn't be found
+    #[arg(long)]
+    pub zero_is_suspect: bool,
+
+    /// Log more of what's going on to stderr, and count reads by length
+    /// after a run. Repeat for more detail, up to -vvv for every read
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
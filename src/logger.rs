@@ -0,0 +1,75 @@
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+use crate::progress;
+
+
+/// Logs to stderr, on lines of their own apart from the progress line.
+#[derive(Debug)]
+pub struct StderrLogger {
+    level: LevelFilter,
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            // Redrawn on the next report.
+            progress::clear_line();
+
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+
+/// Level logged at for a count of --verbose flags.
+/// Warnings are always logged.
+pub fn level_filter(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Log to stderr at the level for verbose. Only the first call has any effect.
+pub fn init(verbose: u8) -> Result<(), SetLoggerError> {
+    let level = level_filter(verbose);
+
+    log::set_boxed_logger(Box::new(StderrLogger { level }))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for level_filter()
+    #[test]
+    fn test_level_filter() {
+        let cases = vec![
+            (0, LevelFilter::Warn),
+            (1, LevelFilter::Info),
+            (2, LevelFilter::Debug),
+            (3, LevelFilter::Trace),
+            (9, LevelFilter::Trace),
+        ];
+
+        for (verbose, expected) in cases {
+            assert!(
+                level_filter(verbose) == expected,
+                "Expected {:?} for {} --verbose, got {:?}.",
+                expected, verbose, level_filter(verbose)
+            )
+        }
+    }
+}
@@ -1,136 +1,748 @@
-mod recovery;
-mod mapping;
-
-use clap::Parser;
-use libc::O_DIRECT;
-use mapping::MapFile;
-use recovery::Recover;
+use clap::{
+    parser::ValueSource,
+    ArgMatches,
+    CommandFactory,
+    FromArgMatches,
+    ValueEnum,
+};
+use kramer::{
+    Args,
+    BadFormat,
+    OffsetBase,
+    benchmark::{benchmark, Sample},
+    buffer::logical_block_size,
+    cache::advise_sequential,
+    control::Control,
+    digest::{digest_ranges, recovered_ranges, Algorithm},
+    error::KramerError,
+    ioprio::{CurrentProcess, set_io_priority},
+    logger,
+    mapping::{BadRegion, Cluster, Domain, MapFile, MapFormat, ZoneStats},
+    platform::open_uncached,
+    profile::Profile,
+    progress::StderrReport,
+    recovery::{Input, Output, Pinpoint, ReadHistogram, Recover, RecoveryStats, StopReason},
+    segment::{segment_len, segment_paths, Segmented},
+    signal::stop_on_sigint,
+    size::cluster_length_from_size,
+    space::{allocated_len, check_free_space, SpaceCheck, Statvfs},
+    timeout::{ReadAt, TimeoutReader},
+};
+use log::{info, warn};
 use std::{
     fs::{File, OpenOptions},
-    io::{self, Seek, SeekFrom},
-    os::unix::fs::OpenOptionsExt,
-    path::PathBuf,
+    io::{self, BufReader, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
 };
 
-
-const FB_SECTOR_SIZE: u16 = 2048;
+// Shared with the library's tests, which the binary can't otherwise reach.
+#[cfg(test)]
+#[path = "testing.rs"]
+mod testing;
 
 
-#[derive(Parser, Debug)]
-struct Args {
-    /// Path to source file or block device
-    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
-    input: PathBuf,
+/// Extensions of files kramer writes, never taken as inputs of a batch,
+/// so maps and outputs left beside their inputs aren't recovered in turn.
+const WRITTEN_EXTENSIONS: [&str; 5] = ["iso", "map", "bak", "tmp", "digest"];
 
-    /// Path to output file. Defaults to {input}.iso
-    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
-    output: Option<PathBuf>,
+fn main() -> ExitCode {
+    let matches = Args::command().get_matches();
+    let config = Args::from_arg_matches(&matches)
+        .unwrap_or_else(|err| err.exit());
 
-    /// Path to rescue map. Defaults to {input}.map
-    #[arg(short, long, value_hint = clap::ValueHint::DirPath)]
-    map: Option<PathBuf>,
+    // Only fails if a logger is already set, which it can't be yet.
+    let _ = logger::init(config.verbose);
 
-    /// Max number of consecutive sectors to test as a group
-    #[arg(short, long, default_value_t = 128)]
-    cluster_length: u16,
+    let result = match config.input.is_dir() {
+        true => run_batch(config, &matches),
+        false => run(config, &matches),
+    };
 
-    /// Number of brute force read passes
-    #[arg(short, long, default_value_t = 2)]
-    brute_passes: usize,
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {}", err);
 
-    /// Sector size
-    #[arg(short, long, default_value_t = FB_SECTOR_SIZE)]
-    sector_size: u16,
+            err.exit_code()
+        },
+    }
 }
 
+/// Run recovery, or a report, as configured.
+fn run(mut config: Args, matches: &ArgMatches) -> Result<(), KramerError> {
+    if let Some(path) = &config.profile {
+        Profile::load(path)?.apply(&mut config, matches);
+    }
 
-fn main() {
-    let config = Args::parse();
+    use_cluster_size(&mut config)?;
 
-    // Live with it, prefer to use expect() here.
-    // I'm lazy and don't want to mess around with comparing error types.
-    // Thus, any error in I/O here should be treated as fatal.
+    if let Some(priority) = config.io_priority {
+        set_io_priority(&mut CurrentProcess, priority);
+    }
 
-    let mut input: File = {
-        match OpenOptions::new()
-            .custom_flags(O_DIRECT)
-            .read(true)
-            .write(false)
-            .append(false)
-            .create(false)
-            .open(&config.input.as_path())
-        {
-            Ok(f) => f,
-            Err(err) => panic!("Failed to open input file: {:?}", err)
-        }
-    };
+    // Reports only need the map, not the devices.
+    if config.list_formats {
+        let path = get_path(
+            &config.map,
+            config.input.to_str().unwrap(),
+            "map"
+        );
+
+        let detected = match File::open(&path) {
+            Ok(mut file) => Some(MapFormat::detect_from(&mut file).map_err(KramerError::Map)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(KramerError::Map(err)),
+        };
+
+        // Only fails if stdout is closed, with nobody left to tell.
+        let _ = write_formats(&mut io::stdout(), Path::new(&path), detected);
+        return Ok(());
+    }
 
-    let mut output: File = {
-        // Keep this clean, make a short-lived binding.
+    if let Some(zones) = config.zone_stats {
         let path = get_path(
-            &config.output,
-            &config.input.to_str().unwrap(),
-            "iso"
+            &config.map,
+            config.input.to_str().unwrap(),
+            "map"
         );
 
-        match OpenOptions::new()
-            .custom_flags(O_DIRECT)
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path)
-        {
-            Ok(f) => f,
-            Err(err) => panic!("Failed to open/create output file. {:?}", err)
-        }
-    };
+        let (map, _) = load_map(File::open(path).map_err(KramerError::Map)?, &config)?;
 
-    // Check if output file is shorter than input.
-    // If so, autoextend the output file.
-    {
-        let input_len = get_stream_length(&mut input)
-            .expect("Failed to get the length of the input data.");
-        let output_len = get_stream_length(&mut output)
-            .expect("Failed to get the length of the output file.");
+        print_zone_stats(&map.zone_stats(zones), config.json);
+        return Ok(());
+    }
 
-        if output_len < input_len {
-            output.set_len(input_len)
-                .expect("Failed to autofill output file.")
-        }
+    if config.list_bad {
+        let path = get_path(
+            &config.map,
+            config.input.to_str().unwrap(),
+            "map"
+        );
+
+        let (map, _) = load_map(File::open(path).map_err(KramerError::Map)?, &config)?;
+        let map = shown_map(map, &config);
+
+        // Only fails if stdout is closed, with nobody left to tell.
+        let _ = write_bad_regions(&mut io::stdout(), &map.bad_regions(), config.bad_format);
+        return Ok(());
     }
 
-    let map: MapFile = {
+    if let Some(badblocks_path) = &config.badblocks {
         let path = get_path(
-            &config.output,
-            &config.input.to_str().unwrap(),
+            &config.map,
+            config.input.to_str().unwrap(),
             "map"
         );
 
-        let file = match OpenOptions::new()
-            .read(true)
-            .create(true)
-            .open(path)
-        {
-            Ok(f) => f,
-            Err(err) => panic!("Failed to open/create mapping file. {:?}", err)
-        }; 
-        
-        if let Ok(map) = MapFile::try_from(file) {
-            map
-        } else {
-            MapFile::new(config.sector_size)
+        let (map, _) = load_map(File::open(path).map_err(KramerError::Map)?, &config)?;
+        let map = shown_map(map, &config);
+        let block_size = config.badblocks_blocksize.unwrap_or(map.sector_size as u64);
+
+        if block_size == 0 {
+            return Err(KramerError::Args(String::from("Block size must be at least a byte")));
+        }
+
+        let blocks = map.bad_blocks(block_size);
+
+        std::fs::write(
+            badblocks_path,
+            blocks.iter().map(|b| format!("{}\n", b)).collect::<String>(),
+        )
+        .map_err(KramerError::Output)?;
+
+        info!(
+            "Wrote {} bad blocks of {} bytes to {}.",
+            blocks.len(), block_size, badblocks_path.display(),
+        );
+        return Ok(());
+    }
+
+    let mut input = open_input(&config.input)
+        .map_err(KramerError::Input)?;
+
+    let source_len = get_stream_length(&mut input)
+        .map_err(KramerError::Input)?;
+
+    // Only advice, so failing to take it doesn't matter.
+    if !config.reverse {
+        let _ = advise_sequential(&input);
+    }
+
+    if let Some(samples) = config.benchmark_device {
+        // Uncached reads must be aligned to the device's blocks.
+        let align = logical_block_size(&input).unwrap_or(config.sector_size as usize);
+        let samples = benchmark(
+            &mut input,
+            source_len,
+            samples,
+            config.sector_size as usize * config.cluster_length as usize,
+            align,
+        )
+        .map_err(KramerError::Input)?;
+
+        print_benchmark(&samples);
+        return Ok(());
+    }
+
+    let map_path = get_path(
+        &config.map,
+        config.input.to_str().unwrap(),
+        "map"
+    );
+
+    // Everything before --input-offset is out of sight of the map and output.
+    let mut input_len = check_input_offset(config.input_offset, config.sector_size, source_len)
+        .map_err(KramerError::Args)?;
+    let mut input_sectors = whole_sectors(input_len, config.sector_size)
+        .map_err(KramerError::Args)?;
+
+    // New maps span every whole sector of the input.
+    let new_map = || {
+        MapFile::new(config.sector_size)
+            .set_domain(Domain { start: 0, end: input_sectors })
+            .to_owned()
+    };
+
+    // A missing map is created when first saved.
+    // Maps which don't parse, or are of an unsupported format version,
+    // are refused rather than replaced, so their progress isn't lost.
+    // GNU ddrescue mapfiles are saved back as such, so either tool can resume.
+    let (mut map, loaded_format) = match File::open(&map_path) {
+        Ok(file) => load_map(file, &config)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (
+            new_map(),
+            MapFormat::from_extension(&map_path).unwrap_or_default(),
+        ),
+        Err(err) => return Err(KramerError::Map(err)),
+    };
+
+    if loaded_format == MapFormat::Ddrescue {
+        info!("Read {} as a GNU ddrescue mapfile.", map_path.display());
+    }
+
+    let map_format = config.map_format.unwrap_or(loaded_format);
+
+    if map.has_overlaps() {
+        if !config.force {
+            return Err(KramerError::BadMap(String::from(
+                "Contains overlapping clusters. Use --force to repair it."
+            )));
+        }
+
+        warn!("Repairing overlapping clusters in map, keeping the last listed.");
+        map.normalize();
+    }
+
+    // Sectors in no cluster would never be read.
+    if let Err(err) = map.validate() {
+        warn!("{}, repairing it with them untested.", err);
+        map.repair();
+    }
+
+    if let Some(path) = &config.retry_list {
+        let list = std::fs::read_to_string(path)
+            .map_err(|err| KramerError::RetryList(err.to_string()))?;
+
+        for domain in parse_domains(&list).map_err(KramerError::RetryList)? {
+            map.retry(domain);
+        }
+    }
+
+    // Offsets in the map are in its own sectors.
+    if config.sector_size != map.sector_size {
+        use_map_sector_size(
+            &mut config,
+            &map,
+            sector_size_is_explicit(matches),
+        )
+        .map_err(KramerError::BadMap)?;
+
+        // The map may have changed how many sectors --cluster-size holds,
+        // and how far in --input-offset is.
+        use_cluster_size(&mut config)?;
+        input_len = check_input_offset(config.input_offset, config.sector_size, source_len)
+            .map_err(KramerError::Args)?;
+        input_sectors = whole_sectors(input_len, config.sector_size)
+            .map_err(KramerError::Args)?;
+    }
+
+    check_sector_size(config.sector_size, logical_block_size(&input))
+        .map_err(KramerError::Args)?;
+
+    // Clusters are split at segment boundaries, which must fall between
+    // sectors for uncached writes.
+    if let Some(split_size) = config.split_size {
+        if split_size == 0 || !split_size.is_multiple_of(config.sector_size as u64) {
+            return Err(KramerError::Args(format!(
+                "Split size {} isn't a whole number of {} byte sectors",
+                split_size, config.sector_size,
+            )));
+        }
+    }
+
+    if let Some(block_size) = config.write_block_size {
+        if block_size == 0 || !block_size.is_multiple_of(config.sector_size as u64) {
+            return Err(KramerError::Args(format!(
+                "Write block size {} isn't a whole number of {} byte sectors",
+                block_size, config.sector_size,
+            )));
+        }
+    }
+
+    // Bounds are in sectors of the map.
+    let bounds = check_bounds(config.start, config.end, input_sectors)
+        .map_err(KramerError::Args)?;
+
+    // Maps saved when a partial last sector was mapped too would otherwise
+    // have it read, and fail, past the end of the input.
+    config.end = Some(bounds.end);
+
+    // Only the named ranges are recovered, such as those of some files.
+    let ranges = match &config.sector_map {
+        Some(path) if !config.recover_only.is_empty() => {
+            let list = std::fs::read_to_string(path)
+                .map_err(|err| KramerError::SectorMap(err.to_string()))?;
+
+            Some(named_ranges(&list, &config.recover_only).map_err(KramerError::SectorMap)?)
+        },
+        _ => None,
+    };
+
+    // Bytes past the last whole sector aren't in the map,
+    // so are copied once the run is done, if it runs to the end.
+    let tail = match bounds.end == input_sectors && ranges.is_none() {
+        true => input_len % config.sector_size as u64,
+        false => 0,
+    };
+    let tail_offset = input_sectors as u64 * config.sector_size as u64;
+
+    if config.retry_damaged {
+        map.retry(bounds);
+    }
+
+    // Nothing is read or written, so the output is never opened.
+    if config.dry_run {
+        let origin = config.offset_base.origin(bounds.start);
+        let mut recover_tool = Recover::new(config, input, io::Cursor::new(vec![]), map)?;
+
+        if let Some(ranges) = &ranges {
+            recover_tool.set_ranges(ranges);
+        }
+
+        print_plan(&recover_tool.plan(), recover_tool.map().sector_size, origin);
+        return Ok(());
+    }
+
+    // Sectors are read and mapped, but the output is never opened.
+    if config.scan_only {
+        return scan(config, input, map, &map_path, map_format, ranges);
+    }
+
+    let output_path = get_path(
+        &config.output,
+        config.input.to_str().unwrap(),
+        "iso"
+    );
+
+    let segment_paths = segment_paths(&output_path, input_len, config.split_size);
+    let mut segments: Vec<File> = vec![];
+
+    for path in segment_paths.iter() {
+        segments.push(open_uncached(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(!config.no_autoextend),
+            path,
+        )
+        .map_err(KramerError::Output)?);
+    }
+
+    // Check output file length against input.
+    // If shorter than the sectors to recover, autoextend the output file.
+    let needed = {
+        let needed_len = bounds.end as u64 * map.sector_size as u64 + tail;
+
+        // Extending only makes a sparse file, so space is claimed as data
+        // is written. Count what the output hasn't allocated yet.
+        // With --sparse, zeros are never written, and the holes left by
+        // extending stay holes, so how much will be claimed isn't known
+        // until the run shows how much of what it copies is zeros.
+        let mut allocated = 0;
+
+        for segment in segments.iter() {
+            allocated += allocated_len(segment).map_err(KramerError::Output)?;
+        }
+
+        let needed = match config.sparse {
+            true => None,
+            false => Some(needed_len.saturating_sub(allocated)),
+        };
+
+        check_free_space(&Statvfs, &segment_paths[0], needed.unwrap_or(0), config.min_free)
+            .map_err(KramerError::Output)?;
+
+        for (i, segment) in segments.iter_mut().enumerate() {
+            fit_output_len(
+                segment,
+                segment_len(i, needed_len, config.split_size),
+                segment_len(i, input_len, config.split_size),
+                config.truncate_output_to_input,
+                !config.no_autoextend,
+            )
+            .map_err(KramerError::Output)?;
+        }
+
+        needed
+    };
+
+    // Uncached IO needs buffers aligned to the block size of both devices.
+    let align = [&input, &segments[0]].into_iter()
+        .filter_map(logical_block_size)
+        .max()
+        .unwrap_or(config.sector_size as usize);
+
+    let timeout = match config.read_timeout {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
+    };
+    let input = TimeoutReader::new(input, timeout, align);
+
+    // Parallel reads each get their own handle on the input.
+    let mut workers: Vec<Box<dyn ReadAt>> = vec![];
+
+    if config.threads > 1 {
+        for _ in 0..config.threads {
+            workers.push(Box::new(TimeoutReader::new(
+                open_input(&config.input).map_err(KramerError::Input)?,
+                timeout,
+                align,
+            )));
+        }
+    }
+
+    let space_check = SpaceCheck {
+        fs: Box::new(Statvfs),
+        path: segment_paths[0].to_owned(),
+        needed,
+        min_free: config.min_free,
+    };
+
+    // Sectors recovered by an earlier rescue are copied from its image.
+    let prefill = match (&config.prefill_from, &config.prefill_map) {
+        (Some(image_path), Some(prefill_map_path)) => Some((
+            File::open(image_path).map_err(KramerError::Input)?,
+            MapFile::load(
+                File::open(prefill_map_path).map_err(KramerError::Map)?,
+                config.sector_size,
+            )?.0,
+        )),
+        _ => None,
+    };
+
+    let config_digest = config.digest.or(config.final_hash);
+    let digest_whole = config.digest_whole || config.final_hash.is_some();
+    let split_size = config.split_size;
+    let input_path = config.input.to_owned();
+    let input_offset = config.input_offset as u64 * config.sector_size as u64;
+
+    // Unsplit, the only segment is never full.
+    let output = Segmented::new(segments, split_size.unwrap_or(u64::MAX));
+
+    let (verbose, json) = (config.verbose, config.json);
+    let map_backup = !config.no_map_backup;
+    let pinpoint = optical_pinpoint(&config);
+    let control_stdin = config.control_stdin;
+    let mut recover_tool  = Recover::new(config, input, output, map)?;
+
+    recover_tool
+        .set_buf_alignment(align)
+        .set_map_path(map_path.to_owned())
+        .set_map_format(map_format)
+        .set_space_check(space_check)
+        .set_reporter(Box::new(StderrReport::default()))
+        .set_workers(workers);
+
+    if let Some(ranges) = &ranges {
+        recover_tool.set_ranges(ranges);
+    }
+
+    if let Some(pinpoint) = pinpoint {
+        recover_tool.set_pinpoint(pinpoint);
+    }
+
+    if control_stdin {
+        let control = Control::spawn(BufReader::new(io::stdin()), Box::new(io::stdout()));
+        recover_tool.set_control(control);
+    }
+
+    match stop_on_sigint() {
+        Ok(stop) => { recover_tool.set_stop_flag(stop); },
+        Err(err) => warn!("Failed to handle SIGINT, continuing: {:?}", err),
+    }
+
+    // The map is saved even if writing the output failed,
+    // so everything recovered before then is kept.
+    let result = match prefill {
+        Some((mut image, prefill_map)) => recover_tool.prefill(&mut image, &prefill_map)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::InvalidInput => KramerError::Args(err.to_string()),
+                _ => KramerError::Input(err),
+            })?
+            .run(),
+        None => recover_tool.run(),
+    }
+    .map(|_| ());
+
+    recover_tool.map()
+        .save_with_backup(&map_path, map_format, map_backup)
+        .map_err(KramerError::Map)?;
+
+    // How far the run got, even if it failed.
+    print_report(
+        &recover_tool.summary(),
+        recover_tool.stop_reason(),
+        recover_tool.read_histogram(),
+        verbose,
+        json,
+    );
+    result.map_err(KramerError::Output)?;
+
+    if recover_tool.stop_reason() == Some(StopReason::Interrupted) {
+        return Err(KramerError::Interrupted);
+    }
+
+    if tail > 0 {
+        copy_tail(
+            &input_path,
+            input_offset + tail_offset,
+            &segment_paths,
+            split_size,
+            tail_offset,
+            tail as usize,
+        )?;
+    }
+
+    if let Some(algorithm) = config_digest {
+        save_digest(
+            &output_path,
+            &segment_paths,
+            split_size,
+            recover_tool.map(),
+            algorithm,
+            digest_whole,
+        )
+        .map_err(KramerError::Output)?;
+    }
+
+    Ok(())
+}
+
+/// Recover each image or device in the directory config.input names,
+/// in order of name, as run() would alone. Outputs and maps are named
+/// after their inputs, in the directories --output and --map name if any,
+/// else beside the inputs. A failed input is reported and the batch goes
+/// on, unless interrupted. Once done, how each input went is summarized.
+fn run_batch(config: Args, matches: &ArgMatches) -> Result<(), KramerError> {
+    // Each input's commands would be read by another thread on stdin.
+    if config.control_stdin {
+        return Err(KramerError::Args(String::from(
+            "--control-stdin can't be used with a directory of inputs"
+        )));
+    }
+
+    for dir in [&config.output, &config.map].into_iter().flatten() {
+        if !dir.is_dir() {
+            return Err(KramerError::Args(format!(
+                "{} isn't a directory, to hold a file per input of the batch",
+                dir.display(),
+            )));
+        }
+    }
+
+    let inputs = batch_inputs(&config.input).map_err(KramerError::Input)?;
+    let mut outcomes: Vec<(PathBuf, Result<(), KramerError>)> = vec![];
+
+    for input in inputs {
+        info!("Recovering {}", input.display());
+
+        let named = |dir: &Option<PathBuf>, extension| dir.as_ref().map(|dir| get_path(
+            &None,
+            dir.join(input.file_name().unwrap_or_default()).to_str().unwrap(),
+            extension,
+        ));
+
+        // Parsed again for each input, as run() takes its config.
+        let mut item = Args::from_arg_matches(matches)
+            .map_err(|err| KramerError::Args(err.to_string()))?;
+        item.output = named(&config.output, "iso");
+        item.map = named(&config.map, "map");
+        item.input = input.to_owned();
+
+        let result = run(item, matches);
+
+        if let Err(err) = &result {
+            warn!("Failed to recover {}: {}", input.display(), err);
+        }
+
+        let is_interrupted = matches!(result, Err(KramerError::Interrupted));
+        outcomes.push((input, result));
+
+        if is_interrupted {
+            break;
+        }
+    }
+
+    print_batch_summary(&outcomes, config.json);
+
+    let failed = outcomes.iter().filter(|(_, result)| result.is_err()).count();
+
+    match outcomes.last() {
+        Some((_, Err(KramerError::Interrupted))) => Err(KramerError::Interrupted),
+        _ if failed > 0 => Err(KramerError::Batch(failed, outcomes.len())),
+        _ => Ok(()),
+    }
+}
+
+/// Images and devices in dir, in order of name, leaving out directories,
+/// and files kramer writes, such as maps and split outputs' segments.
+fn batch_inputs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut inputs = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        // Links are followed, so those to devices are inputs too.
+        let is_written = WRITTEN_EXTENSIONS.contains(&extension)
+            || (!extension.is_empty() && extension.bytes().all(|b| b.is_ascii_digit()));
+
+        if !path.is_dir() && !is_written {
+            inputs.push(path);
         }
+    }
+
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// Read and map sectors without writing them anywhere.
+/// As with a full run, the map is saved even if interrupted.
+fn scan(
+    config: Args,
+    input: File,
+    map: MapFile,
+    map_path: &Path,
+    map_format: MapFormat,
+    ranges: Option<Vec<Domain>>,
+) -> Result<(), KramerError> {
+    let align = logical_block_size(&input).unwrap_or(config.sector_size as usize);
+    let timeout = match config.read_timeout {
+        0 => None,
+        ms => Some(Duration::from_millis(ms)),
     };
+    let input = TimeoutReader::new(input, timeout, align);
+
+    let (verbose, json) = (config.verbose, config.json);
+    let map_backup = !config.no_map_backup;
+    let pinpoint = optical_pinpoint(&config);
+    let control_stdin = config.control_stdin;
+    let mut recover_tool = Recover::new(config, input, io::empty(), map)?;
+
+    recover_tool
+        .set_buf_alignment(align)
+        .set_map_path(map_path.to_owned())
+        .set_map_format(map_format)
+        .set_reporter(Box::new(StderrReport::default()));
+
+    if let Some(ranges) = &ranges {
+        recover_tool.set_ranges(ranges);
+    }
+
+    if let Some(pinpoint) = pinpoint {
+        recover_tool.set_pinpoint(pinpoint);
+    }
+
+    if control_stdin {
+        let control = Control::spawn(BufReader::new(io::stdin()), Box::new(io::stdout()));
+        recover_tool.set_control(control);
+    }
+
+    match stop_on_sigint() {
+        Ok(stop) => { recover_tool.set_stop_flag(stop); },
+        Err(err) => warn!("Failed to handle SIGINT, continuing: {:?}", err),
+    }
+
+    let result = recover_tool.run().map(|_| ());
+
+    recover_tool.map()
+        .save_with_backup(map_path, map_format, map_backup)
+        .map_err(KramerError::Map)?;
+
+    print_report(
+        &recover_tool.summary(),
+        recover_tool.stop_reason(),
+        recover_tool.read_histogram(),
+        verbose,
+        json,
+    );
+    result.map_err(KramerError::Output)?;
+
+    match recover_tool.stop_reason() {
+        Some(StopReason::Interrupted) => Err(KramerError::Interrupted),
+        _ => Ok(()),
+    }
+}
+
+/// READ CD with C2 error pointers, if the input is an optical drive,
+/// to pinpoint bad sectors within reads failing to isolate.
+#[cfg(all(feature = "optical", target_os = "linux"))]
+fn optical_pinpoint(config: &Args) -> Option<Box<dyn Pinpoint>> {
+    use kramer::optical::{is_optical, ReadCd, SgDevice, CD_SECTOR_SIZE};
+
+    if config.sector_size as usize != CD_SECTOR_SIZE || config.sweep {
+        return None;
+    }
 
-    let mut recover_tool  = Recover::new(config, input, output, map);
+    // Anything but a drive refuses SG_IO, so isn't optical.
+    let mut device = SgDevice::open(&config.input).ok()?;
 
-    recover_tool.run();
+    match is_optical(&mut device) {
+        Ok(true) => {
+            info!(
+                "Reading {} as an optical drive, with C2 error pointers.",
+                config.input.display(),
+            );
+            Some(Box::new(ReadCd::new(device)))
+        },
+        _ => None,
+    }
+}
+
+#[cfg(not(all(feature = "optical", target_os = "linux")))]
+fn optical_pinpoint(_config: &Args) -> Option<Box<dyn Pinpoint>> {
+    None
+}
 
-    todo!("Recovery, Map saving, and closure of all files.");
+/// Open the input for reading, bypassing the page cache.
+fn open_input(path: &Path) -> io::Result<File> {
+    open_uncached(
+        OpenOptions::new()
+            .read(true)
+            .write(false)
+            .append(false)
+            .create(false),
+        path,
+    )
 }
 
 /// Generates a file path if one not provided.
-/// source_name for fallback name.
+/// source_name for fallback name, with its extension replaced.
 fn get_path(
     output: &Option<PathBuf>,
     source_name: &str,
@@ -139,38 +751,1422 @@ fn get_path(
     if let Some(f) = output {
         f.to_owned()
     } else {
-        PathBuf::from(format!(
-            "{:?}.{}",
-            source_name,
-            extention,
-        ))
-        .as_path()
-        .to_owned()
+        PathBuf::from(source_name).with_extension(extention)
     }
 }
 
-/// Get length of data stream.
-/// Physical length of data stream in bytes
-/// (multiple of sector_size, rather than actual).
-fn get_stream_length<S: Seek>(input: &mut S) -> io::Result<u64> {
-    let len = input.seek(SeekFrom::End(0))?;
+/// Ensure --start and --end bound a non-empty range
+/// within the input's length in sectors.
+fn check_bounds(
+    start: Option<usize>,
+    end: Option<usize>,
+    input_sectors: usize,
+) -> Result<Domain, String> {
+    let domain = Domain {
+        start: start.unwrap_or(0),
+        end: end.unwrap_or(input_sectors),
+    };
 
-    let _ = input.seek(SeekFrom::Start(0));
+    if domain.end > input_sectors {
+        return Err(format!(
+            "End sector {} is past the end of the {} sector input",
+            domain.end, input_sectors,
+        ));
+    }
 
-    Ok(len)
+    if domain.start >= domain.end {
+        return Err(format!(
+            "Start sector {} must come before end sector {}",
+            domain.start, domain.end,
+        ));
+    }
+
+    Ok(domain)
 }
 
+/// Ensure --input-offset falls within the input,
+/// returning the input's length in bytes from there.
+fn check_input_offset(offset: usize, sector_size: u16, input_len: u64) -> Result<u64, String> {
+    let input_sectors = input_len.div_ceil(sector_size as u64);
 
-#[cfg(test)]
-#[allow(unused)]
-mod tests {
-    use super::*;
+    match input_len.checked_sub(offset as u64 * sector_size as u64) {
+        Some(len) if len > 0 => Ok(len),
+        _ if offset == 0 => Ok(input_len),
+        _ => Err(format!(
+            "Input offset {} is past the end of the {} sector input",
+            offset, input_sectors,
+        )),
+    }
+}
 
-    // Test for get_path
-    // Need to determine how to package files to test with, or at least
-    // how to test with PathBuf present.
-    // Test must also check unwrapping of file name, not just generation.
+/// Number of whole sectors in input_len bytes, of which there must be one.
+/// Any bytes past the last are copied separately, outside of the map.
+fn whole_sectors(input_len: u64, sector_size: u16) -> Result<usize, String> {
+    match input_len / sector_size as u64 {
+        0 => Err(format!(
+            "Input is {} bytes, shorter than a {} byte sector",
+            input_len, sector_size,
+        )),
+        sectors => Ok(sectors as usize),
+    }
+}
 
-    // Test for get_stream_length
-    // Need to determine how to test with Seek-able objects.
+/// Copy len bytes at input_offset of the input, past its last whole sector,
+/// to offset of the output. No map covers them, as every read of the map
+/// is of whole sectors. They're read and written with caching, as
+/// uncached IO can't be of part of a sector.
+fn copy_tail(
+    input_path: &Path,
+    input_offset: u64,
+    segment_paths: &[PathBuf],
+    split_size: Option<u64>,
+    offset: u64,
+    len: usize,
+) -> Result<(), KramerError> {
+    let mut tail = vec![0u8; len];
+
+    File::open(input_path)
+        .and_then(|mut input| Input::read_exact_at(&mut input, &mut tail, input_offset))
+        .map_err(|err| KramerError::Input(io::Error::new(err.kind(), format!(
+            "Failed to read the last {} bytes, past the last whole sector: {}",
+            len, err,
+        ))))?;
+
+    let segments = segment_paths.iter()
+        .map(|path| OpenOptions::new().read(true).write(true).open(path))
+        .collect::<io::Result<Vec<File>>>()
+        .map_err(KramerError::Output)?;
+
+    Segmented::new(segments, split_size.unwrap_or(u64::MAX))
+        .write_all_at(&tail, offset)
+        .map_err(KramerError::Output)
+}
+
+/// Ranges of a sector map with any of names, in the order named.
+/// Each line of list is a sector range then a name, which may repeat.
+/// Blank lines and lines starting with # are ignored.
+fn named_ranges(list: &str, names: &[String]) -> Result<Vec<Domain>, String> {
+    let entries: Vec<(Domain, &str)> = list.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            let (range, name) = l.split_once(char::is_whitespace)
+                .ok_or(format!("Expected a sector range then a name, got {:?}", l))?;
+
+            Ok((range.parse()?, name.trim()))
+        })
+        .collect::<Result<_, String>>()?;
+
+    let mut ranges = vec![];
+
+    for name in names {
+        let named: Vec<Domain> = entries.iter()
+            .filter(|(_, n)| n == name)
+            .map(|&(domain, _)| domain)
+            .collect();
+
+        if named.is_empty() {
+            return Err(format!("Nothing is named {:?}", name));
+        }
+
+        ranges.extend(named);
+    }
+
+    Ok(ranges)
+}
+
+/// Parse a list of sector ranges, one per line.
+/// Blank lines and lines starting with # are ignored.
+fn parse_domains(list: &str) -> Result<Vec<Domain>, String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+/// Map as reports show it, per --offset-base: as is, or only --start to
+/// --end, counting from --start.
+fn shown_map(map: MapFile, config: &Args) -> MapFile {
+    match config.offset_base {
+        OffsetBase::Absolute => map,
+        OffsetBase::Relative => map.rebase(Domain {
+            start: config.start.unwrap_or(map.domain.start),
+            end: config.end.unwrap_or(map.domain.end),
+        }),
+    }
+}
+
+/// Print how a run went, as text or a JSON object.
+/// Reads by length are only included with JSON, or when verbose.
+fn print_report(
+    stats: &RecoveryStats,
+    reason: Option<StopReason>,
+    reads: &ReadHistogram,
+    verbose: u8,
+    json: bool,
+) {
+    if json {
+        println!("{}", serde_json::json!({
+            "summary": stats,
+            "stop_reason": reason,
+            "read_sizes": reads.sizes(),
+        }));
+        return;
+    }
+
+    if let Some(reason) = reason {
+        println!("{}", stopped_line(reason, stats));
+    }
+
+    println!("{}", stats);
+
+    if verbose == 0 {
+        return;
+    }
+
+    println!("{:>10} {:>12} {:>12}", "sectors", "reads", "failed");
+
+    for size in reads.sizes() {
+        println!("{:>10} {:>12} {:>12}", size.sectors, size.reads, size.failed);
+    }
+}
+
+/// Print how each input of a batch went, as text or a JSON object.
+fn print_batch_summary(outcomes: &[(PathBuf, Result<(), KramerError>)], json: bool) {
+    let failed = outcomes.iter().filter(|(_, result)| result.is_err()).count();
+
+    if json {
+        println!("{}", serde_json::json!({
+            "inputs": outcomes.iter()
+                .map(|(path, result)| serde_json::json!({
+                    "input": path,
+                    "error": result.as_ref().err().map(|err| err.to_string()),
+                }))
+                .collect::<Vec<_>>(),
+            "failed": failed,
+        }));
+        return;
+    }
+
+    for (path, result) in outcomes {
+        match result {
+            Ok(()) => println!("{}: done", path.display()),
+            Err(err) => println!("{}: {}", path.display(), err),
+        }
+    }
+
+    println!(
+        "Batch: {} of {} inputs done, {} failed",
+        outcomes.len() - failed, outcomes.len(), failed,
+    );
+}
+
+/// Why a run ended, and how much of the input it had recovered by then.
+fn stopped_line(reason: StopReason, stats: &RecoveryStats) -> String {
+    format!(
+        "Stopped: {} ({} of {} bytes recovered)",
+        reason, stats.recovered_bytes, stats.total_bytes,
+    )
+}
+
+/// Print zone statistics, as a table or JSON array.
+fn print_zone_stats(stats: &[ZoneStats], json: bool) {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(stats).expect("Failed to serialize zone stats.")
+        );
+        return;
+    }
+
+    println!(
+        "{:>5} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "zone", "start", "end", "recovered", "untested", "isolation", "damaged",
+    );
+
+    for (i, zone) in stats.iter().enumerate() {
+        println!(
+            "{:>5} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            i,
+            zone.start,
+            zone.end,
+            zone.recovered,
+            zone.untested,
+            zone.for_isolation,
+            zone.damaged,
+        );
+    }
+}
+
+/// Read the map from file, in map_input_format if set, else that detected.
+fn load_map(file: File, config: &Args) -> Result<(MapFile, MapFormat), KramerError> {
+    match config.map_input_format {
+        Some(format) => Ok((MapFile::load_as(file, format, config.sector_size)?, format)),
+        None => MapFile::load(file, config.sector_size),
+    }
+}
+
+/// Write the map formats supported one per line, then that detected
+/// for the map at path, if it exists.
+fn write_formats<W: Write>(
+    writer: &mut W,
+    path: &Path,
+    detected: Option<MapFormat>,
+) -> io::Result<()> {
+    for format in MapFormat::value_variants() {
+        let value = format.to_possible_value().unwrap();
+
+        writeln!(
+            writer,
+            "{:<10}{}",
+            value.get_name(),
+            value.get_help().map(|help| help.to_string()).unwrap_or_default(),
+        )?;
+    }
+
+    match detected {
+        Some(format) => writeln!(
+            writer,
+            "{} is {}.",
+            path.display(), format.to_possible_value().unwrap().get_name(),
+        ),
+        None => writeln!(writer, "{} doesn't exist yet.", path.display()),
+    }
+}
+
+/// Write damaged regions one per line, then their totals.
+fn write_bad_regions<W: Write>(
+    writer: &mut W,
+    regions: &[BadRegion],
+    format: BadFormat,
+) -> io::Result<()> {
+    let bytes: u64 = regions.iter().map(|r| r.end_byte - r.start_byte).sum();
+
+    if format == BadFormat::Json {
+        return writeln!(writer, "{}", serde_json::json!({
+            "regions": regions,
+            "count": regions.len(),
+            "bytes": bytes,
+        }));
+    }
+
+    for region in regions {
+        writeln!(
+            writer,
+            "{}..{} (sectors {}..{})",
+            region.start_byte, region.end_byte, region.start, region.end,
+        )?;
+    }
+
+    writeln!(writer, "{} damaged regions, {} bytes in total.", regions.len(), bytes)
+}
+
+/// Print the clusters a run would read next, by byte offset,
+/// counting from sector origin.
+fn print_plan(plan: &[Cluster], sector_size: u16, origin: usize) {
+    let sector_size = sector_size as u64;
+    let sectors: usize = plan.iter().map(|c| c.domain().len()).sum();
+
+    println!(
+        "{} clusters to read, {} bytes in total.",
+        plan.len(), sectors as u64 * sector_size,
+    );
+    println!("{:>16} {:>12} {:>16}", "offset", "bytes", "stage");
+
+    for cluster in plan {
+        println!(
+            "{:>16} {:>12} {:>16}",
+            (cluster.domain().start - origin) as u64 * sector_size,
+            cluster.domain().len() as u64 * sector_size,
+            format!("{:?}", cluster.stage()),
+        );
+    }
+}
+
+/// Checksum the output, print it, and save it beside the output
+/// as a .digest file, in the format of sha256sum.
+/// Only recovered sectors are covered, unless whole.
+/// A split output is checksummed as if its segments were joined.
+fn save_digest(
+    output_path: &Path,
+    segment_paths: &[PathBuf],
+    split_size: Option<u64>,
+    map: &MapFile,
+    algorithm: Algorithm,
+    whole: bool,
+) -> io::Result<()> {
+    // Opened again with caching, so reads needn't be aligned.
+    let segments = segment_paths.iter()
+        .map(File::open)
+        .collect::<io::Result<Vec<File>>>()?;
+    let mut output = Segmented::new(segments, split_size.unwrap_or(u64::MAX));
+    let len = get_stream_length(&mut output)?;
+
+    let ranges = match whole {
+        true => vec![Range { start: 0, end: len }],
+        false => recovered_ranges(map, len),
+    };
+
+    let digest = digest_ranges(&mut output, &ranges, algorithm)?;
+    let name = output_path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+
+    println!("{:?} digest: {}", algorithm, digest);
+
+    // Unrecovered sectors are hashed as whatever the output holds for them.
+    let unrecovered = map.domain.len() - map.sector_counts().recovered;
+
+    if whole && unrecovered > 0 {
+        println!("Digest covers a partial image, {} sectors unrecovered.", unrecovered);
+    }
+
+    std::fs::write(
+        get_path(&None, output_path.to_str().unwrap(), "digest"),
+        format!("{}  {}\n", digest, name),
+    )
+}
+
+/// Print read speed per benchmark sample.
+fn print_benchmark(samples: &[Sample]) {
+    println!("{:>16} {:>12}", "offset", "MB/s");
+
+    for sample in samples {
+        match sample.bytes_per_sec {
+            Some(rate) => println!(
+                "{:>16} {:>12.2}{}",
+                sample.offset,
+                rate / 1_000_000.0,
+                if sample.is_slow { "  SLOW" } else { "" },
+            ),
+            None => println!("{:>16} {:>12}", sample.offset, "ERROR"),
+        }
+    }
+}
+
+/// Take cluster_length from --cluster-size, if given.
+fn use_cluster_size(config: &mut Args) -> Result<(), KramerError> {
+    if let Some(bytes) = config.cluster_size {
+        config.cluster_length = cluster_length_from_size(bytes, config.sector_size)
+            .map_err(KramerError::Args)?;
+    }
+
+    Ok(())
+}
+
+/// Whether --sector-size was given on the command line,
+/// rather than falling back to its default.
+fn sector_size_is_explicit(matches: &ArgMatches) -> bool {
+    matches.value_source("sector_size") == Some(ValueSource::CommandLine)
+}
+
+/// Take sector_size from the loaded map with --use-map-sector-size,
+/// unless it was given explicitly.
+/// Otherwise, a map of another sector size is refused, as resuming from it
+/// would read and write every cluster at the wrong offset.
+fn use_map_sector_size(config: &mut Args, map: &MapFile, is_explicit: bool) -> Result<(), String> {
+    if config.sector_size == map.sector_size {
+        return Ok(());
+    }
+
+    if config.use_map_sector_size && !is_explicit {
+        info!("Using sector size {} from map.", map.sector_size);
+
+        config.sector_size = map.sector_size;
+        return Ok(());
+    }
+
+    Err(format!(
+        "Saved with sector size {}, but sector size is {}. \
+        Rerun with --sector-size {}, or --use-map-sector-size alone.",
+        map.sector_size, config.sector_size, map.sector_size,
+    ))
+}
+
+/// Check sector_size is a whole number of the input's logical blocks,
+/// as uncached reads must be, if known.
+fn check_sector_size(sector_size: u16, block_size: Option<usize>) -> Result<(), String> {
+    match block_size {
+        Some(block_size) if !(sector_size as usize).is_multiple_of(block_size) => Err(format!(
+            "Sector size {} isn't a multiple of the input's {} byte logical blocks",
+            sector_size, block_size,
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// How the output length compared to the input length.
+#[derive(Debug, PartialEq)]
+enum OutputFit {
+    /// Long enough, but no longer than input.
+    Exact,
+    Extended,
+    Truncated,
+    /// Longer than input, and left as is.
+    Longer,
+}
+
+/// Extend output to needed_len if shorter, or fail if not to autoextend.
+/// If longer than input_len, warn, or truncate to input_len if truncate is set.
+fn fit_output_len(
+    output: &mut File,
+    needed_len: u64,
+    input_len: u64,
+    truncate: bool,
+    autoextend: bool,
+) -> io::Result<OutputFit> {
+    let output_len = get_stream_length(output)?;
+
+    if output_len < needed_len && !autoextend {
+        Err(io::Error::other(format!(
+            "Output is {} bytes, too short for the {} bytes to recover. \
+            Rerun without --no-autoextend to extend it.",
+            output_len, needed_len,
+        )))
+    } else if output_len < needed_len {
+        output.set_len(needed_len)?;
+
+        Ok(OutputFit::Extended)
+    } else if output_len > input_len && truncate {
+        info!(
+            "Truncating output from {} to {} bytes to match input.",
+            output_len, input_len,
+        );
+        output.set_len(input_len)?;
+
+        Ok(OutputFit::Truncated)
+    } else if output_len > input_len {
+        warn!(
+            "Output is {} bytes, longer than the {} byte input. \
+            Data past the input length is left as is. \
+            Use --truncate-output-to-input to remove it.",
+            output_len, input_len,
+        );
+
+        Ok(OutputFit::Longer)
+    } else {
+        Ok(OutputFit::Exact)
+    }
+}
+
+/// Get length of data stream.
+/// Physical length of data stream in bytes
+/// (multiple of sector_size, rather than actual).
+fn get_stream_length<S: Seek>(input: &mut S) -> io::Result<u64> {
+    let len = input.seek(SeekFrom::End(0))?;
+
+    // Left at the end, reads would start from the wrong offset.
+    input.seek(SeekFrom::Start(0))?;
+
+    Ok(len)
+}
+
+
+#[cfg(test)]
+#[allow(unused)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_path;
+    use kramer::mapping::Stage;
+
+    // Test for get_path
+    #[test]
+    fn test_get_path() {
+        let cases = vec![
+            (Some(PathBuf::from("out.bin")), "disk.img", "out.bin"),
+            (None, "disk.img", "disk.iso"),
+            (None, "/dev/sdb", "/dev/sdb.iso"),
+            (None, "rescue/disc", "rescue/disc.iso"),
+        ];
+
+        for (output, source_name, expected) in cases {
+            let path = get_path(&output, source_name, "iso");
+
+            assert!(
+                path.as_path() == std::path::Path::new(expected),
+                "Expected {:?} for output {:?} and source {:?}, got {:?}.",
+                expected, output, source_name, path
+            )
+        }
+    }
+
+    /// Cursor which fails every seek after the first.
+    struct RewindFails {
+        data: io::Cursor<Vec<u8>>,
+        seeks: usize,
+    }
+
+    impl Seek for RewindFails {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.seeks += 1;
+
+            match self.seeks {
+                1 => self.data.seek(pos),
+                _ => Err(io::Error::other("Seek failed")),
+            }
+        }
+    }
+
+    // Test for get_stream_length
+    #[test]
+    fn test_get_stream_length() {
+        let mut stream = io::Cursor::new(vec![0u8; 4096]);
+        stream.set_position(1024);
+
+        assert!(get_stream_length(&mut stream).unwrap() == 4096);
+        assert!(stream.position() == 0, "Expected the stream rewound.");
+
+        let mut stream = RewindFails {
+            data: io::Cursor::new(vec![0u8; 4096]),
+            seeks: 0,
+        };
+
+        assert!(
+            get_stream_length(&mut stream).is_err(),
+            "Expected a failed rewind to be an error."
+        )
+    }
+
+    // Test for check_input_offset
+    #[test]
+    fn test_check_input_offset() {
+        let cases = vec![
+            (0, 4096, Ok(4096)),
+            (2, 4096, Ok(3072)),
+            (7, 4000, Ok(416)),
+            (8, 4096, Err(())),
+            (9, 4096, Err(())),
+            (0, 0, Ok(0)),
+        ];
+
+        for (offset, input_len, expected) in cases {
+            let recieved = check_input_offset(offset, 512, input_len);
+
+            assert!(
+                recieved.as_ref().ok() == expected.as_ref().ok(),
+                "Expected {:?} for offset {} into {} bytes, got {:?}.",
+                expected, offset, input_len, recieved
+            )
+        }
+    }
+
+    // Test for check_bounds
+    #[test]
+    fn test_check_bounds() {
+        let cases = vec![
+            (None, None, Ok(Domain { start: 0, end: 100 })),
+            (Some(10), None, Ok(Domain { start: 10, end: 100 })),
+            (None, Some(50), Ok(Domain { start: 0, end: 50 })),
+            (Some(10), Some(50), Ok(Domain { start: 10, end: 50 })),
+            (Some(50), Some(10), Err(())),
+            (Some(10), Some(10), Err(())),
+            (None, Some(101), Err(())),
+            (Some(100), None, Err(())),
+        ];
+
+        for (start, end, expected) in cases {
+            let bounds = check_bounds(start, end, 100);
+
+            assert!(
+                bounds.to_owned().map_err(|_| ()) == expected,
+                "Expected {:?} for bounds {:?}..{:?}, got {:?}.",
+                expected, start, end, bounds
+            )
+        }
+    }
+
+    // Test for parse_domains
+    #[test]
+    fn test_parse_domains() {
+        let list = "# Cooled down, try again\n0..4\n\n  16..32\n";
+
+        assert!(
+            parse_domains(list) == Ok(vec![
+                Domain { start: 0, end: 4 },
+                Domain { start: 16, end: 32 },
+            ])
+        );
+        assert!(parse_domains("0..4\n16-32\n").is_err())
+    }
+
+    // Test for named_ranges
+    #[test]
+    fn test_named_ranges() {
+        let list = "# Fragments of each file\n0..4 a.jpg\n8..12\tb.jpg\n\n16..20 a.jpg\n";
+        let names = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect::<Vec<_>>();
+
+        let cases = vec![
+            (names(&["a.jpg"]), Ok(vec![Domain { start: 0, end: 4 }, Domain { start: 16, end: 20 }])),
+            (names(&["b.jpg", "a.jpg"]), Ok(vec![
+                Domain { start: 8, end: 12 },
+                Domain { start: 0, end: 4 },
+                Domain { start: 16, end: 20 },
+            ])),
+            (names(&["c.jpg"]), Err("Nothing is named \"c.jpg\"".to_owned())),
+        ];
+
+        for (names, expected) in cases {
+            let recieved = named_ranges(list, &names);
+
+            assert!(
+                recieved == expected,
+                "Expected {:?} for {:?}, got {:?}.",
+                expected, names, recieved
+            )
+        }
+
+        assert!(named_ranges("0..4\n", &names(&["a.jpg"])).is_err())
+    }
+
+    /// Create a scratch file of len bytes, unique to this test run.
+    fn scratch_file(name: &str, len: u64) -> (PathBuf, File) {
+        let path = scratch_path(name);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        file.set_len(len).unwrap();
+
+        (path, file)
+    }
+
+    // Test for fit_output_len
+    #[test]
+    fn test_fit_output_len() {
+        // With a 2048 byte input, and --end bounding what's needed.
+        let cases = vec![
+            (1024, 2048, false, true, Ok(OutputFit::Extended), 2048),
+            (2048, 2048, false, true, Ok(OutputFit::Exact), 2048),
+            (4096, 2048, false, true, Ok(OutputFit::Longer), 4096),
+            (4096, 2048, true, true, Ok(OutputFit::Truncated), 2048),
+            // --no-autoextend
+            (1024, 2048, false, false, Err(()), 1024),
+            (2048, 2048, false, false, Ok(OutputFit::Exact), 2048),
+            (4096, 2048, false, false, Ok(OutputFit::Longer), 4096),
+            (4096, 2048, true, false, Ok(OutputFit::Truncated), 2048),
+            // Bounded short of the end of the input.
+            (512, 1024, false, true, Ok(OutputFit::Extended), 1024),
+            (1024, 1024, false, false, Ok(OutputFit::Exact), 1024),
+            (512, 1024, false, false, Err(()), 512),
+        ];
+
+        for (output_len, needed_len, truncate, autoextend, expected, expected_len) in cases {
+            let (path, mut output) = scratch_file("fit_output_len", output_len);
+
+            let fit = fit_output_len(&mut output, needed_len, 2048, truncate, autoextend);
+            let len = output.metadata().unwrap().len();
+            std::fs::remove_file(path).unwrap();
+
+            assert!(
+                fit.as_ref().ok() == expected.as_ref().ok() && len == expected_len,
+                "Expected {:?} to {} bytes for a {} byte output needing {}, \
+                with truncate {} and autoextend {}, got {:?} to {} bytes.",
+                expected, expected_len, output_len, needed_len, truncate, autoextend, fit, len
+            )
+        }
+    }
+
+    // Test for run() with --dry-run
+    #[test]
+    fn test_run_dry_run() {
+        let (input_path, _) = scratch_file("dry_run.img", 64 * 512);
+        let (map_path, _) = scratch_file("dry_run.map", 0);
+        let output_path = scratch_path("dry_run.iso");
+
+        MapFile::new(512)
+            .set_domain(Domain { start: 0, end: 64 })
+            .save(&map_path)
+            .unwrap();
+        let saved = std::fs::read(&map_path).unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_path.to_str().unwrap(),
+            "-o", output_path.to_str().unwrap(),
+            "-m", map_path.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+            "--dry-run",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let result = run(config, &matches);
+        let map = std::fs::read(&map_path).unwrap();
+
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&map_path).unwrap();
+
+        assert!(result.is_ok(), "Expected a dry run to succeed, got {:?}.", result);
+        assert!(!output_path.exists(), "Expected no output created by a dry run.");
+        assert!(map == saved, "Expected the map left unchanged by a dry run.")
+    }
+
+    // Test for run() with --scan-only
+    #[test]
+    fn test_run_scan_only() {
+        let (input_path, _) = scratch_file("scan_only.img", 64 * 512);
+        let map_path = scratch_path("scan_only.map");
+        let output_path = scratch_path("scan_only.iso");
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_path.to_str().unwrap(),
+            "-o", output_path.to_str().unwrap(),
+            "-m", map_path.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+            "--scan-only",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let result = run(config, &matches);
+        let map = MapFile::try_from(File::open(&map_path).unwrap());
+
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&map_path).unwrap();
+
+        assert!(result.is_ok(), "Expected a scan to succeed, got {:?}.", result);
+        assert!(!output_path.exists(), "Expected no output created by a scan.");
+        assert!(
+            map.is_ok_and(|map| map.get_stage() == Stage::Recovered),
+            "Expected every sector mapped as read by a scan."
+        )
+    }
+
+    // Test for run() with a map which doesn't parse
+    #[test]
+    fn test_run_unparsable_map() {
+        let cases = vec![
+            b"garbage".to_vec(),
+            // A ddrescue mapfile, with a block of unknown status.
+            b"# Mapfile. Created by GNU ddrescue version 1.27\n\
+            0x00000000     +     1\n\
+            0x00000000  0x00004000  X\n".to_vec(),
+        ];
+
+        for (i, contents) in cases.into_iter().enumerate() {
+            let (input_path, _) = scratch_file(&format!("unparsable_{}.img", i), 32 * 512);
+            let (map_path, _) = scratch_file(&format!("unparsable_{}.map", i), 0);
+            let output_path = scratch_path(&format!("unparsable_{}.iso", i));
+
+            std::fs::write(&map_path, &contents).unwrap();
+
+            let matches = Args::command().get_matches_from([
+                "kramer",
+                "-i", input_path.to_str().unwrap(),
+                "-o", output_path.to_str().unwrap(),
+                "-m", map_path.to_str().unwrap(),
+                "-s", "512",
+            ]);
+            let config = Args::from_arg_matches(&matches).unwrap();
+
+            let result = run(config, &matches);
+            let map = std::fs::read(&map_path).unwrap();
+
+            std::fs::remove_file(&input_path).unwrap();
+            std::fs::remove_file(&map_path).unwrap();
+            let _ = std::fs::remove_file(&output_path);
+
+            assert!(
+                matches!(result, Err(KramerError::MapParse(_) | KramerError::BadMap(_))),
+                "Expected an unparsable map refused, got {:?}.",
+                result
+            );
+            assert!(map == contents, "Expected the unparsable map left unchanged.")
+        }
+    }
+
+    // Test for run() resuming from a GNU ddrescue mapfile
+    #[test]
+    fn test_run_ddrescue_map() {
+        let (input_path, _) = scratch_file("ddrescue_map.img", 0);
+        let (output_path, _) = scratch_file("ddrescue_map.iso", 0);
+        let (map_path, _) = scratch_file("ddrescue_map.map", 0);
+
+        std::fs::write(&input_path, vec![0xaa; 32 * 512]).unwrap();
+        std::fs::write(&output_path, vec![0x11; 32 * 512]).unwrap();
+        // The first half rescued already, by ddrescue.
+        let rescued = "# Mapfile. Created by GNU ddrescue version 1.27\n\
+            0x00002000     ?     1\n\
+            0x00000000  0x00002000  +\n\
+            0x00002000  0x00002000  ?\n";
+        std::fs::write(&map_path, rescued).unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_path.to_str().unwrap(),
+            "-o", output_path.to_str().unwrap(),
+            "-m", map_path.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let result = run(config, &matches);
+        let output = std::fs::read(&output_path).unwrap();
+        let saved = std::fs::read(&map_path).unwrap();
+        let backup_path = scratch_path("ddrescue_map.map.bak");
+        let backup = std::fs::read_to_string(&backup_path).unwrap();
+
+        for path in [&input_path, &output_path, &map_path, &backup_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(result.is_ok(), "Expected the run to succeed, got {:?}.", result);
+        assert!(
+            backup == rescued,
+            "Expected ddrescue's mapfile kept as a backup, got {:?}.",
+            backup
+        );
+        assert!(
+            output[..16 * 512].iter().all(|&b| b == 0x11)
+            && output[16 * 512..].iter().all(|&b| b == 0xaa),
+            "Expected only the half ddrescue hadn't rescued read."
+        );
+
+        let map = MapFile::from_ddrescue(saved.as_slice(), 512).unwrap();
+
+        assert!(
+            MapFormat::detect(&saved) == MapFormat::Ddrescue
+            && map.get_domains(Stage::Recovered) == vec![Domain { start: 0, end: 32 }],
+            "Expected the map saved back as a ddrescue mapfile, all rescued, got {:?}.",
+            String::from_utf8_lossy(&saved)
+        )
+    }
+
+    // Test for run() with --map-format, and formats by extension
+    #[test]
+    fn test_run_map_format() {
+        let cases = vec![
+            ("map_format.cbor", None, MapFormat::Cbor),
+            ("map_format.json", None, MapFormat::Json),
+            ("map_format.map", None, MapFormat::Ron),
+            ("map_format_json.map", Some("json"), MapFormat::Json),
+        ];
+
+        for (name, format, expected) in cases {
+            let (input_path, _) = scratch_file(&format!("{}.img", name), 16 * 512);
+            let map_path = scratch_path(name);
+            let backup_path = scratch_path(&format!("{}.bak", name));
+
+            let first = vec![
+                "kramer",
+                "-i", input_path.to_str().unwrap(),
+                "-m", map_path.to_str().unwrap(),
+                "-s", "512",
+                "--scan-only",
+            ];
+            let mut second = first.to_owned();
+
+            // The second run converts the map saved by the first.
+            if let Some(format) = format {
+                second.extend(["--map-format", format]);
+            }
+
+            let mut result = Ok(());
+
+            for args in [first, second] {
+                let matches = Args::command().get_matches_from(&args);
+                let config = Args::from_arg_matches(&matches).unwrap();
+
+                result = result.and(run(config, &matches));
+            }
+
+            let recieved = MapFile::load(File::open(&map_path).unwrap(), 512);
+
+            for path in [&input_path, &map_path, &backup_path] {
+                std::fs::remove_file(path).unwrap();
+            }
+
+            assert!(result.is_ok(), "Expected runs to succeed, got {:?}.", result);
+            assert!(
+                recieved.is_ok_and(|(map, format)| {
+                    format == expected && map.get_stage() == Stage::Recovered
+                }),
+                "Expected {} saved as {:?}.",
+                name, expected
+            )
+        }
+    }
+
+    // Test for run() with --badblocks and --offset-base
+    #[test]
+    fn test_run_badblocks_offset_base() {
+        let cases = vec![
+            ("absolute", "20\n21\n22\n23\n40\n"),
+            ("relative", "4\n5\n6\n7\n"),
+        ];
+
+        let map_path = scratch_path("offset_base.map");
+        let badblocks_path = scratch_path("offset_base.badblocks");
+
+        MapFile::new(512)
+            .set_domain(Domain { start: 0, end: 64 })
+            .update(Cluster::new(Domain { start: 20, end: 24 }, Stage::Damaged))
+            .update(Cluster::new(Domain { start: 40, end: 41 }, Stage::Damaged))
+            .save(&map_path)
+            .unwrap();
+
+        for (base, expected) in cases {
+            let matches = Args::command().get_matches_from([
+                "kramer",
+                "-i", "offset_base.img",
+                "-m", map_path.to_str().unwrap(),
+                "-s", "512",
+                "--start", "16",
+                "--end", "32",
+                "--offset-base", base,
+                "--badblocks", badblocks_path.to_str().unwrap(),
+            ]);
+            let config = Args::from_arg_matches(&matches).unwrap();
+
+            let result = run(config, &matches);
+            let recieved = std::fs::read_to_string(&badblocks_path).unwrap();
+
+            assert!(result.is_ok(), "Expected --badblocks to succeed, got {:?}.", result);
+            assert!(
+                recieved == expected,
+                "Expected blocks {:?} {}, got {:?}.",
+                expected, base, recieved
+            )
+        }
+
+        std::fs::remove_file(&map_path).unwrap();
+        std::fs::remove_file(&badblocks_path).unwrap();
+    }
+
+    // Test for run() with --retry-damaged
+    #[test]
+    fn test_run_retry_damaged() {
+        let (input_path, _) = scratch_file("retry_damaged.img", 0);
+        let (output_path, _) = scratch_file("retry_damaged.iso", 0);
+        let (map_path, _) = scratch_file("retry_damaged.map", 0);
+
+        std::fs::write(&input_path, vec![0xaa; 32 * 512]).unwrap();
+        std::fs::write(&output_path, vec![0x11; 32 * 512]).unwrap();
+
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 8), stage: Damaged),
+                (domain: (start: 8, end: 16), stage: Recovered),
+                (domain: (start: 16, end: 24), stage: Damaged),
+                (domain: (start: 24, end: 32), stage: Recovered),
+            ],
+        )").unwrap();
+        map.save(&map_path).unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_path.to_str().unwrap(),
+            "-o", output_path.to_str().unwrap(),
+            "-m", map_path.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+            "--end", "20",
+            "--retry-damaged",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let result = run(config, &matches);
+        let map = MapFile::try_from(File::open(&map_path).unwrap()).unwrap();
+        let output = std::fs::read(&output_path).unwrap();
+        let backup_path = scratch_path("retry_damaged.map.bak");
+
+        for path in [&input_path, &output_path, &map_path, &backup_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        // Damaged sectors within bounds were read again.
+        let expected: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 20), stage: Recovered),
+                (domain: (start: 20, end: 24), stage: Damaged),
+                (domain: (start: 24, end: 32), stage: Recovered),
+            ],
+        )").unwrap();
+
+        assert!(result.is_ok(), "Expected the run to succeed, got {:?}.", result);
+        assert!(
+            map == expected,
+            "Expected {:?} after retrying, got {:?}.",
+            expected, map
+        );
+
+        // Recovered sectors weren't read again.
+        for (sector, data) in output.chunks(512).enumerate() {
+            let expected = if sector < 8 || (16..20).contains(&sector) { 0xaa } else { 0x11 };
+
+            assert!(
+                data.iter().all(|&b| b == expected),
+                "Expected sector {} to hold {:#x}.",
+                sector, expected
+            )
+        }
+    }
+
+    // Test for run() with an input which isn't a whole number of sectors
+    #[test]
+    fn test_run_partial_sector() {
+        let (input_path, _) = scratch_file("partial_sector.img", 0);
+        let (map_path, _) = scratch_file("partial_sector.map", 0);
+        let output_path = scratch_path("partial_sector.iso");
+
+        let source: Vec<u8> = (0..32 * 512 + 100).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&input_path, &source).unwrap();
+        std::fs::remove_file(&map_path).unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_path.to_str().unwrap(),
+            "-o", output_path.to_str().unwrap(),
+            "-m", map_path.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let result = run(config, &matches);
+        let map = MapFile::try_from(File::open(&map_path).unwrap()).unwrap();
+        let output = std::fs::read(&output_path).unwrap();
+
+        for path in [&input_path, &output_path, &map_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert!(result.is_ok(), "Expected the run to succeed, got {:?}.", result);
+        assert!(
+            map.get_domains(Stage::Recovered) == vec![Domain { start: 0, end: 32 }]
+            && map.domain == Domain { start: 0, end: 32 },
+            "Expected every whole sector mapped and recovered, got {:?}.",
+            map
+        );
+        assert!(output == source, "Expected the output byte-identical to the input.")
+    }
+
+    // Test for run_batch()
+    #[test]
+    fn test_run_batch() {
+        let input_dir = scratch_path("batch");
+        let output_dir = scratch_path("batch_output");
+
+        for dir in [&input_dir, &output_dir] {
+            std::fs::create_dir(dir).unwrap();
+        }
+
+        let images = [("a.img", 0xaa), ("b.img", 0xbb)];
+
+        for (name, byte) in images {
+            std::fs::write(input_dir.join(name), vec![byte; 16 * 512]).unwrap();
+        }
+
+        // Shorter than a sector, so fails, but the batch goes on.
+        std::fs::write(input_dir.join("c.img"), vec![0xcc; 100]).unwrap();
+        // Left beside its input by an earlier run, so not an input.
+        std::fs::write(input_dir.join("b.iso"), vec![0x11; 16 * 512]).unwrap();
+
+        let matches = Args::command().get_matches_from([
+            "kramer",
+            "-i", input_dir.to_str().unwrap(),
+            "-o", output_dir.to_str().unwrap(),
+            "-s", "512",
+            "-c", "8",
+        ]);
+        let config = Args::from_arg_matches(&matches).unwrap();
+
+        let inputs = batch_inputs(&input_dir).unwrap();
+        let result = run_batch(config, &matches);
+
+        // Outputs in --output, maps beside their inputs.
+        let recovered: Vec<(Option<Vec<u8>>, Option<MapFile>)> = images.iter()
+            .map(|(name, _)| (
+                std::fs::read(output_dir.join(name).with_extension("iso")).ok(),
+                File::open(input_dir.join(name).with_extension("map")).ok()
+                    .and_then(|file| MapFile::try_from(file).ok()),
+            ))
+            .collect();
+        let outputs = std::fs::read_dir(&output_dir).unwrap().count();
+
+        for dir in [&input_dir, &output_dir] {
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        assert!(
+            inputs == ["a.img", "b.img", "c.img"].map(|name| input_dir.join(name)),
+            "Expected the images as inputs, in order, got {:?}.",
+            inputs
+        );
+        assert!(
+            matches!(result, Err(KramerError::Batch(1, 3))),
+            "Expected 1 of 3 inputs failed, got {:?}.",
+            result
+        );
+
+        for ((name, byte), (output, map)) in images.iter().zip(recovered) {
+            assert!(
+                output.is_some_and(|output| output == vec![*byte; 16 * 512])
+                && map.is_some_and(|map| map.get_stage() == Stage::Recovered),
+                "Expected {} recovered in full, despite another input failing.",
+                name
+            )
+        }
+
+        assert!(outputs == 2, "Expected no output for the failed input.")
+    }
+
+    // Test for run() with --final-hash
+    #[test]
+    fn test_run_final_hash() {
+        let cases = vec![
+            ("sha1", "897256b6709e1a4da9daba92b6bde39ccfccd8c1"),
+            ("md5", "ce338fe6899778aacfc28414f2d9498b"),
+        ];
+
+        for (algorithm, expected) in cases {
+            let (input_path, _) = scratch_file(&format!("final_hash_{}.img", algorithm), 32 * 512);
+            let map_path = scratch_path(&format!("final_hash_{}.map", algorithm));
+            let output_path = scratch_path(&format!("final_hash_{}.iso", algorithm));
+            let digest_path = output_path.with_extension("digest");
+
+            let matches = Args::command().get_matches_from([
+                "kramer",
+                "-i", input_path.to_str().unwrap(),
+                "-o", output_path.to_str().unwrap(),
+                "-m", map_path.to_str().unwrap(),
+                "-s", "512",
+                "-c", "8",
+                "--final-hash", algorithm,
+            ]);
+            let config = Args::from_arg_matches(&matches).unwrap();
+
+            let result = run(config, &matches);
+            let digest = std::fs::read_to_string(&digest_path).unwrap();
+
+            for path in [&input_path, &output_path, &map_path, &digest_path] {
+                std::fs::remove_file(path).unwrap();
+            }
+
+            assert!(result.is_ok(), "Expected the run to succeed, got {:?}.", result);
+            assert!(
+                digest.starts_with(&format!("{}  ", expected)),
+                "Expected {} digest {} of 16K of zeros, got {:?}.",
+                algorithm, expected, digest
+            )
+        }
+    }
+
+    // Test for whole_sectors
+    #[test]
+    fn test_whole_sectors() {
+        let cases = vec![
+            (4096, Ok(8)),
+            (4100, Ok(8)),
+            (512, Ok(1)),
+            (511, Err(())),
+            (0, Err(())),
+        ];
+
+        for (input_len, expected) in cases {
+            let recieved = whole_sectors(input_len, 512);
+
+            assert!(
+                recieved.to_owned().map_err(|_| ()) == expected,
+                "Expected {:?} for {} bytes, got {:?}.",
+                expected, input_len, recieved
+            )
+        }
+    }
+
+    // Test for write_bad_regions() of MapFile::bad_regions()
+    #[test]
+    fn test_write_bad_regions() {
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 16),
+            map: [
+                (domain: (start: 0, end: 2), stage: Recovered),
+                (domain: (start: 2, end: 3), stage: Damaged),
+                (domain: (start: 3, end: 5), stage: Damaged),
+                (domain: (start: 5, end: 9), stage: Untested),
+                (domain: (start: 9, end: 10), stage: Damaged),
+                (domain: (start: 10, end: 16), stage: Recovered),
+            ],
+        )").unwrap();
+        let regions = map.bad_regions();
+
+        let mut text = vec![];
+        write_bad_regions(&mut text, &regions, BadFormat::Text).unwrap();
+
+        // Touching damaged clusters are one region.
+        let expected = "1024..2560 (sectors 2..5)\n\
+            4608..5120 (sectors 9..10)\n\
+            2 damaged regions, 2048 bytes in total.\n";
+
+        assert!(
+            text == expected.as_bytes(),
+            "Expected text:\n{}Got:\n{}",
+            expected, String::from_utf8_lossy(&text)
+        );
+
+        let mut json = vec![];
+        write_bad_regions(&mut json, &regions, BadFormat::Json).unwrap();
+
+        let expected = serde_json::json!({
+            "regions": [
+                { "start": 2, "end": 5, "start_byte": 1024, "end_byte": 2560 },
+                { "start": 9, "end": 10, "start_byte": 4608, "end_byte": 5120 },
+            ],
+            "count": 2,
+            "bytes": 2048,
+        });
+        let recieved: serde_json::Value = serde_json::from_slice(&json).unwrap();
+
+        assert!(recieved == expected, "Expected JSON {}, got {}.", expected, recieved)
+    }
+
+    // Test for write_formats
+    #[test]
+    fn test_write_formats() {
+        let cases = vec![
+            (Some(MapFormat::Json), "a.map is json.\n"),
+            (None, "a.map doesn't exist yet.\n"),
+        ];
+
+        for (detected, expected) in cases {
+            let mut recieved = vec![];
+            write_formats(&mut recieved, Path::new("a.map"), detected).unwrap();
+            let recieved = String::from_utf8(recieved).unwrap();
+
+            assert!(
+                ["ron ", "json ", "cbor ", "ddrescue "].iter().all(|f| recieved.contains(f))
+                && recieved.ends_with(expected),
+                "Expected every format listed, then {:?}, got:\n{}",
+                expected, recieved
+            )
+        }
+    }
+
+    // Test for stopped_line
+    #[test]
+    fn test_stopped_line() {
+        let stats = RecoveryStats {
+            sectors: 8,
+            recovered: 6,
+            total_bytes: 4096,
+            recovered_bytes: 3072,
+            ..Default::default()
+        };
+
+        let cases = vec![
+            (StopReason::Complete, "Stopped: recovery complete (3072 of 4096 bytes recovered)"),
+            (StopReason::Interrupted, "Stopped: interrupted (3072 of 4096 bytes recovered)"),
+            (StopReason::OutputFull, "Stopped: output is full (3072 of 4096 bytes recovered)"),
+        ];
+
+        for (reason, expected) in cases {
+            let recieved = stopped_line(reason, &stats);
+
+            assert!(
+                recieved == expected,
+                "Expected {:?} for {:?}, got {:?}.",
+                expected, reason, recieved
+            )
+        }
+    }
+
+    // Test for load_map with --map-input-format
+    #[test]
+    fn test_load_map_input_format() {
+        let path = scratch_path("input_format.map");
+        MapFile::new(512)
+            .set_domain(Domain { start: 0, end: 16 })
+            .save_as(&path, MapFormat::Json)
+            .unwrap();
+
+        let cases = vec![
+            (None, Some(MapFormat::Json)),
+            (Some("json"), Some(MapFormat::Json)),
+            // Forced, so it isn't detected, and doesn't parse.
+            (Some("ron"), None),
+        ];
+
+        for (format, expected) in cases {
+            let mut args = vec!["kramer", "-i", "input_format"];
+
+            if let Some(format) = format {
+                args.extend(["--map-input-format", format]);
+            }
+
+            let matches = Args::command().get_matches_from(&args);
+            let config = Args::from_arg_matches(&matches).unwrap();
+            let recieved = load_map(File::open(&path).unwrap(), &config);
+
+            assert!(
+                recieved.as_ref().ok().map(|(_, f)| *f) == expected,
+                "Expected {:?} read with --map-input-format {:?}, got {:?}.",
+                expected, format, recieved
+            )
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Test for use_map_sector_size
+    #[test]
+    fn test_use_map_sector_size() {
+        let map = MapFile::new(512);
+
+        let cases = vec![
+            (vec!["kramer", "-i", "in", "--use-map-sector-size"], Ok(512)),
+            (vec!["kramer", "-i", "in", "-s", "512"], Ok(512)),
+            // A mismatched map is refused, unless told to use its sector size.
+            (vec!["kramer", "-i", "in"], Err(())),
+            (vec!["kramer", "-i", "in", "-s", "2048"], Err(())),
+            (vec!["kramer", "-i", "in", "--use-map-sector-size", "-s", "4096"], Err(())),
+        ];
+
+        for (argv, expected) in cases {
+            let matches = Args::command().get_matches_from(&argv);
+            let mut config = Args::from_arg_matches(&matches).unwrap();
+
+            let recieved = use_map_sector_size(
+                &mut config,
+                &map,
+                sector_size_is_explicit(&matches),
+            )
+            .map(|_| config.sector_size);
+
+            assert!(
+                recieved.to_owned().map_err(|_| ()) == expected,
+                "Expected sector size {:?} for {:?}, got {:?}.",
+                expected, argv, recieved
+            )
+        }
+    }
+
+    // Test for check_sector_size
+    #[test]
+    fn test_check_sector_size() {
+        let cases = vec![
+            (2048, Some(512), true),
+            (512, Some(512), true),
+            (512, Some(4096), false),
+            (512, None, true),
+        ];
+
+        for (sector_size, block_size, expected) in cases {
+            assert!(
+                check_sector_size(sector_size, block_size).is_ok() == expected,
+                "Expected sector size {} on {:?} byte blocks to be ok: {}.",
+                sector_size, block_size, expected
+            )
+        }
+    }
 }
\ No newline at end of file
@@ -1,13 +1,32 @@
-use ron::de::{from_reader, SpannedError};
-use serde::Deserialize;
-use std::fs::File;
+use clap::ValueEnum;
+use log::trace;
+use ron::{
+    de::from_reader,
+    ser::{to_writer_pretty, PrettyConfig},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write},
+    path::Path,
+    str::FromStr,
+};
 
-use crate::FB_SECTOR_SIZE;
+use crate::{cbor, error::KramerError, FB_SECTOR_SIZE};
+
+
+/// Version of the map format saved, bumped whenever its layout changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Oldest map format version which still parses as the current one.
+const OLDEST_VERSION: u32 = 1;
 
 
 /// Domain, in sectors.
 /// Requires sector_size to be provided elsewhere for conversion to bytes.
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Domain {
     pub start: usize,
     pub end: usize,
@@ -24,101 +43,549 @@ impl Domain {
     pub fn len(self) -> usize {
         self.end - self.start
     }
+
+    /// Whether domain covers no sectors.
+    pub fn is_empty(self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Whether sector is within [start, end).
+    pub fn contains(&self, sector: usize) -> bool {
+        self.start <= sector && sector < self.end
+    }
+
+    /// Whether any sector is within both domains.
+    /// Domains which only touch, such as 0..4 and 4..8, don't overlap.
+    pub fn overlaps(&self, other: &Domain) -> bool {
+        self.intersect(other).is_some()
+    }
+
+    /// Sectors within both domains, if any.
+    pub fn intersect(&self, other: &Domain) -> Option<Domain> {
+        let domain = Domain {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+
+        match domain.is_empty() {
+            true => None,
+            false => Some(domain),
+        }
+    }
+
+    /// Parse a domain written by to_cbor().
+    fn from_cbor<R: Read>(reader: &mut R) -> io::Result<Domain> {
+        let mut domain = Domain { start: 0, end: 0 };
+
+        for _ in 0..cbor::expect(cbor::read_head(reader)?, cbor::MAP)? {
+            let field = cbor::read_text(reader)?;
+            let sector = usize::try_from(cbor::read_unsigned(reader)?)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Sector is too large"))?;
+
+            match field.as_str() {
+                "start" => domain.start = sector,
+                "end" => domain.end = sector,
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown domain field {:?}", field),
+                )),
+            }
+        }
+
+        Ok(domain)
+    }
+
+    /// Write as a CBOR map of start and end.
+    fn to_cbor<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        cbor::write_head(writer, cbor::MAP, 2)?;
+        cbor::write_text(writer, "start")?;
+        cbor::write_head(writer, cbor::UNSIGNED, self.start as u64)?;
+        cbor::write_text(writer, "end")?;
+        cbor::write_head(writer, cbor::UNSIGNED, self.end as u64)
+    }
+}
+
+impl fmt::Display for Domain {
+    /// Written start..end, as parsed by from_str.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl FromStr for Domain {
+    type Err = String;
+
+    /// Parse a half-open sector range, written start..end
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.trim()
+            .split_once("..")
+            .ok_or(format!("Expected a range as start..end, got {:?}", s))?;
+
+        let parse = |n: &str| n.trim()
+            .parse::<usize>()
+            .map_err(|err| format!("Invalid sector {:?} in {:?}: {}", n, s, err));
+
+        let domain = Domain { start: parse(start)?, end: parse(end)? };
+
+        if domain.start >= domain.end {
+            return Err(format!("Range {:?} is empty", s));
+        }
+
+        Ok(domain)
+    }
 }
 
 
 /// A map for data stored in memory for processing and saving to disk.
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+///
+/// ```
+/// use kramer::mapping::{Cluster, Domain, MapFile, Stage};
+///
+/// let mut map = MapFile::new(512);
+/// map.set_domain(Domain { start: 0, end: 8 })
+///     .update(Cluster::new(Domain { start: 2, end: 4 }, Stage::Damaged));
+///
+/// for cluster in map.map.iter() {
+///     println!(
+///         "{} bytes at offset {}: {:?}",
+///         cluster.len_bytes(map.sector_size),
+///         cluster.domain().start * map.sector_size as usize,
+///         cluster.stage(),
+///     );
+/// }
+///
+/// assert_eq!(map.get_clusters(Stage::Damaged)[0].len_bytes(map.sector_size), 1024);
+/// ```
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Cluster {
     domain: Domain,
     stage: Stage,
 }
 
-impl Default for Cluster {
-    fn default() -> Self {
-        Cluster {
-            domain: Domain::default(),
-            stage: Stage::default()
-        }
+impl Cluster {
+    pub fn new(domain: Domain, stage: Stage) -> Self {
+        Cluster { domain, stage }
     }
-}
 
-impl Cluster {
     /// Breaks apart into a vec of clusters,
-    /// each of cluster_size, excepting last.
+    /// each of cluster_len, excepting last, which may be shorter.
+    /// Never produces empty clusters.
     pub fn subdivide(&mut self, cluster_len: usize) -> Vec<Cluster> {
-        let domain_len = self.domain.len();
-        let mut start = self.domain.start;
-        let mut clusters: Vec<Cluster> = vec![];
+        let cluster_len = cluster_len.max(1);
 
-        for _ in 0..(domain_len as f64 / cluster_len as f64).floor() as usize {
-            clusters.push(Cluster {
+        (self.domain.start..self.domain.end)
+            .step_by(cluster_len)
+            .map(|start| Cluster {
                 domain: Domain {
                     start,
-                    end: start + cluster_len,
+                    end: (start + cluster_len).min(self.domain.end),
                 },
                 stage: self.stage,
-            });
+            })
+            .collect()
+    }
 
-            start += cluster_len;
-        }
+    /// Splits into two clusters at sector, each keeping the original stage.
+    /// The first covers start..sector, the second sector..end.
+    pub fn split_at(self, sector: usize) -> (Cluster, Cluster) {
+        debug_assert!(
+            self.domain.start <= sector && sector <= self.domain.end,
+            "Sector {} lies outside of cluster domain {:?}.",
+            sector, self.domain
+        );
 
-        clusters.push(Cluster {
-            domain: Domain {
-                start,
-                end: self.domain.end,
+        (
+            Cluster {
+                domain: Domain { start: self.domain.start, end: sector },
+                stage: self.stage,
+            },
+            Cluster {
+                domain: Domain { start: sector, end: self.domain.end },
+                stage: self.stage,
             },
+        )
+    }
+
+    /// Part of the cluster within domain, if any.
+    pub fn intersect(&self, domain: &Domain) -> Option<Cluster> {
+        Some(Cluster {
+            domain: self.domain.intersect(domain)?,
             stage: self.stage,
-        });
+        })
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
 
-        clusters
+    /// Length in bytes, of sectors of sector_size.
+    pub fn len_bytes(&self, sector_size: u16) -> usize {
+        self.domain.len() * sector_size as usize
     }
 
     pub fn set_stage(&mut self, stage: Stage) -> &mut Self {
         self.stage = stage;
         self
     }
+
+    /// Parse a cluster written by to_cbor().
+    fn from_cbor<R: Read>(reader: &mut R) -> io::Result<Cluster> {
+        let mut cluster = Cluster::default();
+
+        for _ in 0..cbor::expect(cbor::read_head(reader)?, cbor::MAP)? {
+            match cbor::read_text(reader)?.as_str() {
+                "domain" => cluster.domain = Domain::from_cbor(reader)?,
+                "stage" => cluster.stage = Stage::from_cbor(reader)?,
+                field => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unknown cluster field {:?}", field),
+                )),
+            }
+        }
+
+        Ok(cluster)
+    }
+
+    /// Write as a CBOR map of domain and stage.
+    fn to_cbor<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        cbor::write_head(writer, cbor::MAP, 2)?;
+        cbor::write_text(writer, "domain")?;
+        self.domain.to_cbor(writer)?;
+        cbor::write_text(writer, "stage")?;
+        self.stage.to_cbor(writer)
+    }
 }
 
 
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, PartialOrd)]
+/// Recovery stage of a cluster.
+///
+/// Variants are ordered by recovery progress; keep Untested first and
+/// Verified last, as MapFile::get_stage relies on it.
+/// Verified clusters were recovered, then read back the same by a verify pass.
+///
+/// Each isolation level halves the length of the reads attempted,
+/// so a cluster_length of n sectors is down to single sectors by level
+/// log2(n), at most 16 for a u16 cluster_length. Levels beyond that only
+/// retry single sectors; levels are never allowed to overflow.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, PartialOrd, Serialize)]
 pub enum Stage {
+    #[default]
     Untested,
     ForIsolation(u8),
     Damaged,
+    Recovered,
+    Verified,
 }
 
-impl Default for Stage {
-    fn default() -> Self {
-        Stage::Untested
+impl Stage {
+    /// Stage to move to after a failed read.
+    /// Once the isolation level would overflow, the stage becomes Damaged.
+    pub fn escalate(self) -> Stage {
+        match self {
+            Stage::Untested => Stage::ForIsolation(0),
+            Stage::ForIsolation(level) => match level.checked_add(1) {
+                Some(level) => Stage::ForIsolation(level),
+                None => Stage::Damaged,
+            },
+            Stage::Damaged => Stage::Damaged,
+            Stage::Recovered => Stage::Recovered,
+            Stage::Verified => Stage::Verified,
+        }
+    }
+
+    /// Nearest stage to a GNU ddrescue block status.
+    /// Non-trimmed blocks have only failed whole, like ForIsolation(0).
+    /// Non-scraped blocks have been trimmed, so are taken a level further.
+    pub fn from_ddrescue(status: char) -> Option<Stage> {
+        match status {
+            '?' => Some(Stage::Untested),
+            '*' => Some(Stage::ForIsolation(0)),
+            '/' => Some(Stage::ForIsolation(1)),
+            '-' => Some(Stage::Damaged),
+            '+' => Some(Stage::Recovered),
+            _ => None,
+        }
+    }
+
+    /// GNU ddrescue block status nearest to stage.
+    pub fn to_ddrescue(self) -> char {
+        match self {
+            Stage::Untested => '?',
+            Stage::ForIsolation(0) => '*',
+            Stage::ForIsolation(_) => '/',
+            Stage::Damaged => '-',
+            Stage::Recovered | Stage::Verified => '+',
+        }
+    }
+
+    /// Parse a stage written by to_cbor().
+    fn from_cbor<R: Read>(reader: &mut R) -> io::Result<Stage> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let stage = match cbor::read_head(reader)? {
+            (cbor::TEXT, len) => match cbor::read_text_body(reader, len)?.as_str() {
+                "Untested" => Stage::Untested,
+                "Damaged" => Stage::Damaged,
+                "Recovered" => Stage::Recovered,
+                "Verified" => Stage::Verified,
+                stage => return Err(invalid(format!("Unknown stage {:?}", stage))),
+            },
+            (cbor::MAP, 1) => match cbor::read_text(reader)?.as_str() {
+                "ForIsolation" => {
+                    let level = cbor::read_unsigned(reader)?;
+
+                    Stage::ForIsolation(u8::try_from(level)
+                        .map_err(|_| invalid(format!("Invalid isolation level {}", level)))?)
+                },
+                stage => return Err(invalid(format!("Unknown stage {:?}", stage))),
+            },
+            (major, _) => return Err(invalid(format!(
+                "Expected a stage, got CBOR major type {}",
+                major,
+            ))),
+        };
+
+        Ok(stage)
+    }
+
+    /// Write as serde does, the variant's name, or a map of it to its level.
+    fn to_cbor<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Stage::Untested => cbor::write_text(writer, "Untested"),
+            Stage::ForIsolation(level) => {
+                cbor::write_head(writer, cbor::MAP, 1)?;
+                cbor::write_text(writer, "ForIsolation")?;
+                cbor::write_head(writer, cbor::UNSIGNED, level as u64)
+            },
+            Stage::Damaged => cbor::write_text(writer, "Damaged"),
+            Stage::Recovered => cbor::write_text(writer, "Recovered"),
+            Stage::Verified => cbor::write_text(writer, "Verified"),
+        }
+    }
+}
+
+
+/// Sector counts per stage within a zone of the domain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ZoneStats {
+    pub start: usize,
+    pub end: usize,
+    pub untested: usize,
+    pub for_isolation: usize,
+    pub damaged: usize,
+    pub recovered: usize,
+}
+
+impl ZoneStats {
+    /// Count of sectors at stage. Verified sectors count as recovered.
+    pub fn count_mut(&mut self, stage: Stage) -> &mut usize {
+        match stage {
+            Stage::Untested => &mut self.untested,
+            Stage::ForIsolation(_) => &mut self.for_isolation,
+            Stage::Damaged => &mut self.damaged,
+            Stage::Recovered | Stage::Verified => &mut self.recovered,
+        }
+    }
+}
+
+
+/// Format a map is saved in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum MapFormat {
+    /// kramer's own, keeping everything about the map.
+    #[default]
+    Ron,
+    /// As ron, in JSON.
+    Json,
+    /// As ron, in CBOR, far smaller for maps of many clusters.
+    /// Integers are big endian on every machine, so maps can be shared.
+    Cbor,
+    /// GNU ddrescue's mapfile, so either tool can resume from the map.
+    /// Isolation levels past the first, and verification, aren't kept.
+    Ddrescue,
+}
+
+impl MapFormat {
+    /// Guess the format of a map from its first bytes.
+    /// CBOR maps open with the self-describe tag, or a map head,
+    /// JSON with a brace, and ddrescue mapfiles with a comment or a hex position.
+    pub fn detect(head: &[u8]) -> MapFormat {
+        if head.starts_with(&cbor::SELF_DESCRIBE_HEAD)
+            || head.first().is_some_and(|b| b >> 5 == cbor::MAP)
+        {
+            return MapFormat::Cbor;
+        }
+
+        let head = head.trim_ascii_start();
+
+        if head.starts_with(b"{") {
+            MapFormat::Json
+        } else if head.starts_with(b"#") || head.starts_with(b"0x") || head.starts_with(b"0X") {
+            MapFormat::Ddrescue
+        } else {
+            MapFormat::Ron
+        }
+    }
+
+    /// Guess the format of a map from the first bytes of reader, as detect()
+    /// does, leaving reader back at its start.
+    pub fn detect_from<R: Read + Seek>(reader: &mut R) -> io::Result<MapFormat> {
+        let mut head = vec![];
+
+        reader.take(16).read_to_end(&mut head)?;
+        reader.rewind()?;
+
+        Ok(MapFormat::detect(&head))
+    }
+
+    /// Format named by the extension of path, if any.
+    pub fn from_extension(path: &Path) -> Option<MapFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ron" => Some(MapFormat::Ron),
+            "json" => Some(MapFormat::Json),
+            "cbor" => Some(MapFormat::Cbor),
+            _ => None,
+        }
+    }
+}
+
+
+/// A run of damaged sectors, and the bytes of the input they span.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct BadRegion {
+    pub start: usize,
+    pub end: usize,
+    pub start_byte: u64,
+    pub end_byte: u64,
+}
+
+
+/// SHA-256 of a sector of the source, read before recovery and again after,
+/// to catch a source which changed in between, as a device remapping
+/// sectors may. after is None until read again, or if that failed.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SourceSample {
+    pub sector: usize,
+    pub before: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+impl SourceSample {
+    /// Whether the sector read differently after recovery than before.
+    pub fn is_changed(&self) -> bool {
+        self.after.as_ref().is_some_and(|after| *after != self.before)
+    }
+
+    /// Parse a CBOR map of sector, before and, if read again, after.
+    fn from_cbor<R: Read>(reader: &mut R) -> io::Result<SourceSample> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let (mut sector, mut before, mut after) = (None, None, None);
+
+        for _ in 0..cbor::expect(cbor::read_head(reader)?, cbor::MAP)? {
+            match cbor::read_text(reader)?.as_str() {
+                "sector" => sector = Some(usize::try_from(cbor::read_unsigned(reader)?)
+                    .map_err(|_| invalid(String::from("Sector is too large")))?),
+                "before" => before = Some(cbor::read_text(reader)?),
+                "after" => after = Some(cbor::read_text(reader)?),
+                field => return Err(invalid(format!("Unknown sample field {:?}", field))),
+            }
+        }
+
+        let missing = |field: &str| invalid(format!("Missing sample field {:?}", field));
+
+        Ok(SourceSample {
+            sector: sector.ok_or(missing("sector"))?,
+            before: before.ok_or(missing("before"))?,
+            after,
+        })
+    }
+
+    /// Write as a CBOR map, leaving after out until it's read, as in RON.
+    fn to_cbor<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        cbor::write_head(writer, cbor::MAP, 2 + self.after.is_some() as u64)?;
+        cbor::write_text(writer, "sector")?;
+        cbor::write_head(writer, cbor::UNSIGNED, self.sector as u64)?;
+        cbor::write_text(writer, "before")?;
+        cbor::write_text(writer, &self.before)?;
+
+        if let Some(after) = &self.after {
+            cbor::write_text(writer, "after")?;
+            cbor::write_text(writer, after)?;
+        }
+
+        Ok(())
     }
 }
 
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct MapFile {
+    /// Format the map was saved in. Maps from before versioning are 1.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
     pub sector_size: u16,
     pub domain: Domain,
     pub map: Vec<Cluster>,
+    /// Sectors of the source sampled by the last run, if any.
+    /// Left out when empty, so maps without them are laid out as before.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub samples: Vec<SourceSample>,
+}
+
+fn legacy_version() -> u32 {
+    1
 }
 
 impl TryFrom<File> for MapFile {
-    type Error = SpannedError;
+    type Error = KramerError;
 
+    /// Parse a map, rejecting any saved in an unsupported format version.
+    /// Older versions are upgraded, so are saved as the current one.
     fn try_from(file: File) -> Result<Self, Self::Error> {
-        from_reader(file)
+        upgrade(from_reader(file)?)
+    }
+}
+
+/// Refuse map if saved in an unsupported format version, otherwise
+/// upgrade it to the current one.
+fn upgrade(mut map: MapFile) -> Result<MapFile, KramerError> {
+    if !(OLDEST_VERSION..=FORMAT_VERSION).contains(&map.version) {
+        return Err(KramerError::BadMap(format!(
+            "Saved in format version {}, but only versions {} to {} are supported",
+            map.version, OLDEST_VERSION, FORMAT_VERSION,
+        )));
+    }
+
+    map.version = FORMAT_VERSION;
+    Ok(map)
+}
+
+/// KramerError for an error reading a map in a format other than RON.
+fn load_error(err: io::Error) -> KramerError {
+    match err.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            KramerError::BadMap(err.to_string())
+        },
+        _ => KramerError::Map(err),
     }
 }
 
 impl Default for MapFile {
     fn default() -> Self {
         MapFile {
+            version: FORMAT_VERSION,
             sector_size: FB_SECTOR_SIZE,
             domain: Domain::default(),
             map: vec![Cluster {
                 domain: Domain::default(),
                 stage: Stage::Untested,
             }],
+            samples: vec![],
         }
     }
 }
@@ -135,162 +602,1986 @@ impl MapFile {
         self
     }
 
+    /// Parse a map in whichever format it was saved in, returning that too.
+    /// ddrescue mapfiles are mapped in sectors of sector_size.
+    pub fn load(mut file: File, sector_size: u16) -> Result<(MapFile, MapFormat), KramerError> {
+        let format = MapFormat::detect_from(&mut file).map_err(KramerError::Map)?;
+
+        Ok((MapFile::load_as(file, format, sector_size)?, format))
+    }
+
+    /// Read a map from file in format, rather than detecting it, as load() does.
+    pub fn load_as(file: File, format: MapFormat, sector_size: u16) -> Result<MapFile, KramerError> {
+        let reader = BufReader::new(file);
+
+        let map = match format {
+            MapFormat::Ron => upgrade(from_reader(reader)?)?,
+            MapFormat::Json => upgrade(serde_json::from_reader(reader)
+                .map_err(|err| load_error(err.into()))?)?,
+            MapFormat::Cbor => upgrade(MapFile::from_cbor(reader).map_err(load_error)?)?,
+            MapFormat::Ddrescue => MapFile::from_ddrescue(reader, sector_size)
+                .map_err(load_error)?,
+        };
+
+        Ok(map)
+    }
+
+    /// Save as pretty RON, atomically replacing any map at path.
+    /// The map is written to {path}.tmp, synced, then renamed over path,
+    /// so path always holds either the old or new map in full.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.save_as(path, MapFormat::Ron)
+    }
+
+    /// Save in format, atomically replacing any map at path, as save() does.
+    pub fn save_as(&self, path: &Path, format: MapFormat) -> io::Result<()> {
+        let mut tmp_path = OsString::from(path);
+        tmp_path.push(".tmp");
+
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+            match format {
+                MapFormat::Ron => to_writer_pretty(&mut writer, self, PrettyConfig::new())
+                    .map_err(io::Error::other)?,
+                MapFormat::Json => serde_json::to_writer_pretty(&mut writer, self)?,
+                MapFormat::Cbor => self.to_cbor(&mut writer)?,
+                MapFormat::Ddrescue => self.to_ddrescue(&mut writer)?,
+            }
+
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Save in format as save_as() does, first copying any map at path
+    /// to {path}.bak if backup is set, replacing the backup before it.
+    /// Should the save go wrong, the map before it is still to hand.
+    pub fn save_with_backup(&self, path: &Path, format: MapFormat, backup: bool) -> io::Result<()> {
+        if backup {
+            let mut backup_path = OsString::from(path);
+            backup_path.push(".bak");
+
+            match fs::copy(path, &backup_path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => (),
+            }
+        }
+
+        self.save_as(path, format)
+    }
+
+    /// Parse a map saved as CBOR by to_cbor().
+    /// Fields may be in any order, and version may be left out, as in RON.
+    pub fn from_cbor<R: Read>(mut reader: R) -> io::Result<MapFile> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut head = cbor::read_head(&mut reader)?;
+
+        if head == (cbor::TAG, cbor::SELF_DESCRIBE) {
+            head = cbor::read_head(&mut reader)?;
+        }
+
+        let (mut version, mut sector_size, mut domain, mut map) = (None, None, None, None);
+        let mut samples = vec![];
+
+        for _ in 0..cbor::expect(head, cbor::MAP)? {
+            match cbor::read_text(&mut reader)?.as_str() {
+                "version" => version = Some(cbor::read_unsigned(&mut reader)?),
+                "sector_size" => sector_size = Some(cbor::read_unsigned(&mut reader)?),
+                "domain" => domain = Some(Domain::from_cbor(&mut reader)?),
+                "map" => {
+                    let len = cbor::expect(cbor::read_head(&mut reader)?, cbor::ARRAY)?;
+                    let mut clusters = Vec::with_capacity(len.min(1 << 16) as usize);
+
+                    for _ in 0..len {
+                        clusters.push(Cluster::from_cbor(&mut reader)?);
+                    }
+
+                    map = Some(clusters);
+                },
+                "samples" => {
+                    let len = cbor::expect(cbor::read_head(&mut reader)?, cbor::ARRAY)?;
+
+                    for _ in 0..len {
+                        samples.push(SourceSample::from_cbor(&mut reader)?);
+                    }
+                },
+                field => return Err(invalid(format!("Unknown map field {:?}", field))),
+            }
+        }
+
+        let missing = |field: &str| invalid(format!("Missing map field {:?}", field));
+
+        Ok(MapFile {
+            version: match version {
+                Some(v) => u32::try_from(v)
+                    .map_err(|_| invalid(format!("Invalid version {}", v)))?,
+                None => legacy_version(),
+            },
+            sector_size: u16::try_from(sector_size.ok_or(missing("sector_size"))?)
+                .map_err(|_| invalid(String::from("Sector size is too large")))?,
+            domain: domain.ok_or(missing("domain"))?,
+            map: map.ok_or(missing("map"))?,
+            samples,
+        })
+    }
+
+    /// Write as CBOR, with the same fields and layout as RON.
+    pub fn to_cbor<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&cbor::SELF_DESCRIBE_HEAD)?;
+        cbor::write_head(&mut writer, cbor::MAP, 4 + !self.samples.is_empty() as u64)?;
+
+        cbor::write_text(&mut writer, "version")?;
+        cbor::write_head(&mut writer, cbor::UNSIGNED, self.version as u64)?;
+        cbor::write_text(&mut writer, "sector_size")?;
+        cbor::write_head(&mut writer, cbor::UNSIGNED, self.sector_size as u64)?;
+        cbor::write_text(&mut writer, "domain")?;
+        self.domain.to_cbor(&mut writer)?;
+        cbor::write_text(&mut writer, "map")?;
+        cbor::write_head(&mut writer, cbor::ARRAY, self.map.len() as u64)?;
+
+        for cluster in &self.map {
+            cluster.to_cbor(&mut writer)?;
+        }
+
+        if !self.samples.is_empty() {
+            cbor::write_text(&mut writer, "samples")?;
+            cbor::write_head(&mut writer, cbor::ARRAY, self.samples.len() as u64)?;
+
+            for sample in &self.samples {
+                sample.to_cbor(&mut writer)?;
+            }
+        }
+
+        writer.flush()
+    }
+
+    /// Parse a GNU ddrescue mapfile, converting its byte offsets to sectors.
+    /// Blocks must be contiguous and sector aligned, except for the end of
+    /// the last, which is cut short to a whole sector as new maps are.
+    pub fn from_ddrescue<R: BufRead>(reader: R, sector_size: u16) -> io::Result<MapFile> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let parse = |n: &str| match n.strip_prefix("0x").or(n.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => n.parse::<u64>(),
+        }
+        .map_err(|err| invalid(format!("Invalid position or size {:?}: {}", n, err)));
+
+        let sector_size = sector_size as u64;
+        let mut blocks: Vec<(u64, u64, Stage)> = vec![];
+        let mut has_status_line = false;
+
+        for line in reader.lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            if fields.is_empty() || fields[0].starts_with('#') {
+                continue;
+            }
+
+            // current_pos, current_status and, since 1.21, current_pass.
+            if !has_status_line {
+                if !(2..=3).contains(&fields.len()) {
+                    return Err(invalid(format!("Invalid status line {:?}", line)));
+                }
+
+                parse(fields[0])?;
+                has_status_line = true;
+                continue;
+            }
+
+            let status = match fields.as_slice() {
+                [_, _, status] if status.chars().count() == 1 => status.chars().next(),
+                _ => None,
+            }
+            .and_then(Stage::from_ddrescue)
+            .ok_or(invalid(format!("Invalid block line {:?}", line)))?;
+
+            let (pos, size) = (parse(fields[0])?, parse(fields[1])?);
+
+            if let Some(&(last_pos, last_size, _)) = blocks.last() {
+                if last_pos + last_size != pos {
+                    return Err(invalid(format!(
+                        "Block at {:#x} doesn't follow on from the block before it",
+                        pos,
+                    )));
+                }
+
+                if last_size % sector_size != 0 {
+                    return Err(invalid(format!(
+                        "Block at {:#x} isn't aligned to {} byte sectors",
+                        pos, sector_size,
+                    )));
+                }
+            }
+
+            if pos % sector_size != 0 {
+                return Err(invalid(format!(
+                    "Block at {:#x} isn't aligned to {} byte sectors",
+                    pos, sector_size,
+                )));
+            }
+
+            if size > 0 {
+                blocks.push((pos, size, status));
+            }
+        }
+
+        let map: Vec<Cluster> = blocks.iter()
+            .map(|&(pos, size, stage)| Cluster {
+                domain: Domain {
+                    start: (pos / sector_size) as usize,
+                    end: ((pos + size) / sector_size) as usize,
+                },
+                stage,
+            })
+            .filter(|c| !c.domain.is_empty())
+            .collect();
+
+        let domain = match (map.first(), map.last()) {
+            (Some(first), Some(last)) => Domain {
+                start: first.domain.start,
+                end: last.domain.end,
+            },
+            _ => return Err(invalid(String::from("Mapfile has no blocks"))),
+        };
+
+        Ok(MapFile {
+            version: FORMAT_VERSION,
+            sector_size: sector_size as u16,
+            domain,
+            map,
+            samples: vec![],
+        })
+    }
+
+    /// Write as a GNU ddrescue mapfile, with sectors converted to bytes.
+    pub fn to_ddrescue<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let sector_size = self.sector_size as u64;
+
+        // Damaged is as finished as ddrescue gets without retrying.
+        let status = match self.get_stage() {
+            Stage::Damaged => '+',
+            stage => stage.to_ddrescue(),
+        };
+
+        writeln!(writer, "# Mapfile. Created by kramer version {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "# current_pos  current_status  current_pass")?;
+        writeln!(
+            writer,
+            "0x{:08X}     {}               1",
+            self.domain.start as u64 * sector_size, status,
+        )?;
+        writeln!(writer, "#      pos        size  status")?;
+
+        let mut clusters = self.map.to_owned();
+        clusters.sort_by_key(|c| c.domain.start);
+
+        for cluster in clusters {
+            writeln!(
+                writer,
+                "0x{:08X}  0x{:08X}  {}",
+                cluster.domain.start as u64 * sector_size,
+                cluster.domain.len() as u64 * sector_size,
+                cluster.stage.to_ddrescue(),
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// Set domain, mapped as a single Untested cluster.
+    /// Any existing clusters are forgotten, so only use for new maps.
+    pub fn set_domain(&mut self, domain: Domain) -> &mut Self {
+        self.domain = domain;
+        self.map = vec![Cluster { domain, stage: Stage::Untested }];
+        self
+    }
+
     /// Recalculate cluster mappings.
-    fn update(&mut self, new_cluster: Cluster) -> &mut Self {
-        let mut new_map: Vec<Cluster> = vec![Cluster::from(new_cluster.to_owned())];
+    /// Clusters must be in order without overlapping, as validate() checks,
+    /// so only those new_cluster overlaps need replacing.
+    pub fn update(&mut self, mut new_cluster: Cluster) -> &mut Self {
+        // Anything outside of the domain is forgotten.
+        // An empty cluster has nothing to map.
+        new_cluster.domain = match new_cluster.domain.intersect(&self.domain) {
+            Some(domain) => domain,
+            None => return self,
+        };
+
+        trace!("Mapping sectors {} as {:?}", new_cluster.domain, new_cluster.stage);
+
+        // Clusters overlapping new_cluster are first..last.
+        let first = self.map.partition_point(|c| c.domain.end <= new_cluster.domain.start);
+        let last = self.map.partition_point(|c| c.domain.start < new_cluster.domain.end);
+
+        // Any part of them covered by new_cluster is forgotten,
+        // leaving at most the start of the first, and the end of the last.
+        let mut replacement: Vec<Cluster> = vec![];
+        let mut i = first;
+
+        if let Some(head) = self.map[first..last].first() {
+            if head.domain.start < new_cluster.domain.start {
+                replacement.push(Cluster {
+                    domain: Domain {
+                        start: head.domain.start,
+                        end: new_cluster.domain.start,
+                    },
+                    stage: head.stage,
+                });
+                i += 1;
+            }
+        }
+
+        replacement.push(new_cluster);
+
+        if let Some(tail) = self.map[first..last].last() {
+            if new_cluster.domain.end < tail.domain.end {
+                replacement.push(Cluster {
+                    domain: Domain {
+                        start: new_cluster.domain.end,
+                        end: tail.domain.end,
+                    },
+                    stage: tail.stage,
+                });
+            }
+        }
+
+        self.map.splice(first..last, replacement);
+
+        // Merge new_cluster into neighbours of the same stage.
+        // Only its neighbours can have changed, so the rest is left alone.
+        let is_mergeable = |a: &Cluster, b: &Cluster| {
+            a.stage == b.stage && a.domain.end == b.domain.start
+        };
+
+        if i + 1 < self.map.len() && is_mergeable(&self.map[i], &self.map[i + 1]) {
+            self.map[i].domain.end = self.map.remove(i + 1).domain.end;
+        }
+
+        if i > 0 && is_mergeable(&self.map[i - 1], &self.map[i]) {
+            self.map[i - 1].domain.end = self.map.remove(i).domain.end;
+        }
 
-        for map_cluster in self.map.iter() {
-            let mut map_cluster = *map_cluster;
+        self.debug_assert_coverage();
+        self
+    }
 
-            // If new_cluster doesn't start ahead and ends short, map_cluster is forgotten.
-            if new_cluster.domain.start < map_cluster.domain.start 
-            && new_cluster.domain.end < map_cluster.domain.end {
-                /* 
-                new_cluster overlaps the start of map_cluster, 
-                but ends short of map_cluster end.
+    /// Whether any clusters overlap, such as in a corrupt or merged map.
+    pub fn has_overlaps(&self) -> bool {
+        let mut domains: Vec<Domain> = self.map.iter().map(|c| c.domain).collect();
+        domains.sort_by_key(|d| d.start);
+
+        domains.windows(2).any(|pair| pair[1].start < pair[0].end)
+    }
 
-                ACTION: Crop map_cluster to start at end of new_cluster.
-                */
+    /// Rebuild the map as a clean partition of the domain.
+    /// Where clusters overlap, the last listed wins. Gaps become Untested.
+    pub fn normalize(&mut self) -> &mut Self {
+        let clusters = std::mem::replace(
+            &mut self.map,
+            vec![Cluster { domain: self.domain, stage: Stage::Untested }],
+        );
 
-                map_cluster.domain.start = new_cluster.domain.end;
-                new_map.push(map_cluster);
+        // update() forgets anything outside of the domain.
+        for cluster in clusters {
+            self.update(cluster);
+        }
 
-            } else if new_cluster.domain.end < map_cluster.domain.end {
-                /*
-                new_cluster starts within map_cluster domain.
+        self
+    }
 
-                ACTION: Crop
-                */
+    /// Check clusters are in order, and cover the domain exactly,
+    /// without gaps or overlaps.
+    pub fn validate(&self) -> Result<(), KramerError> {
+        let mut pos = self.domain.start;
+
+        for cluster in self.map.iter() {
+            let domain = cluster.domain;
+
+            if domain.is_empty() {
+                return Err(KramerError::BadMap(format!("Cluster {} is empty", domain)));
+            } else if domain.start < pos {
+                return Err(KramerError::BadMap(format!(
+                    "Cluster {} overlaps another, is out of order, or is outside of domain {}",
+                    domain, self.domain,
+                )));
+            } else if pos < domain.start {
+                return Err(KramerError::BadMap(format!(
+                    "Sectors {} aren't in any cluster",
+                    Domain { start: pos, end: domain.start },
+                )));
+            }
+
+            pos = domain.end;
+        }
+
+        match pos.cmp(&self.domain.end) {
+            std::cmp::Ordering::Less => Err(KramerError::BadMap(format!(
+                "Sectors {} aren't in any cluster",
+                Domain { start: pos, end: self.domain.end },
+            ))),
+            std::cmp::Ordering::Greater => Err(KramerError::BadMap(format!(
+                "Clusters run past the end of domain {}",
+                self.domain,
+            ))),
+            std::cmp::Ordering::Equal => Ok(()),
+        }
+    }
+
+    /// Make the map pass validate().
+    /// Clusters are sorted and cropped to the domain, and gaps between them
+    /// filled with Untested clusters. Overlaps are resolved by normalize().
+    pub fn repair(&mut self) -> &mut Self {
+        if self.has_overlaps() {
+            return self.normalize();
+        }
+
+        let mut clusters: Vec<Cluster> = self.map.iter()
+            .filter_map(|c| c.intersect(&self.domain))
+            .collect();
+        clusters.sort_by_key(|c| c.domain.start);
+
+        let mut repaired: Vec<Cluster> = vec![];
+        let mut pos = self.domain.start;
+
+        for cluster in clusters {
+            if pos < cluster.domain.start {
+                repaired.push(Cluster {
+                    domain: Domain { start: pos, end: cluster.domain.start },
+                    stage: Stage::Untested,
+                });
+            }
+
+            pos = cluster.domain.end;
+            repaired.push(cluster);
+        }
+
+        if pos < self.domain.end {
+            repaired.push(Cluster {
+                domain: Domain { start: pos, end: self.domain.end },
+                stage: Stage::Untested,
+            });
+        }
+
+        self.map = repaired;
+        self
+    }
+
+    /// Map of only the clusters within domain, cropped to fit it.
+    pub fn crop(&self, domain: Domain) -> MapFile {
+        let start = domain.start.max(self.domain.start);
+        let domain = Domain {
+            start,
+            end: domain.end.min(self.domain.end).max(start),
+        };
+
+        MapFile {
+            version: self.version,
+            sector_size: self.sector_size,
+            domain,
+            map: self.map.iter()
+                .filter_map(|c| c.intersect(&domain))
+                .collect(),
+            samples: vec![],
+        }
+    }
+
+    /// Map of only the clusters within any of domains, cropped to fit them.
+    /// Spans from the first to the last, but only holds clusters where
+    /// domains do, so may not cover all of its own domain.
+    pub fn crop_all(&self, domains: &[Domain]) -> MapFile {
+        let mut domains = domains.to_owned();
+        domains.sort_by_key(|d| d.start);
+
+        // Overlapping domains would crop the same clusters twice.
+        let mut joined: Vec<Domain> = vec![];
+
+        for domain in domains {
+            match joined.last_mut() {
+                Some(last) if last.end >= domain.start => last.end = last.end.max(domain.end),
+                _ => joined.push(domain),
+            }
+        }
+
+        let map: Vec<Cluster> = joined.iter()
+            .flat_map(|&domain| self.crop(domain).map)
+            .collect();
+
+        let domain = match (map.first(), map.last()) {
+            (Some(first), Some(last)) => Domain { start: first.domain.start, end: last.domain.end },
+            _ => Domain { start: self.domain.start, end: self.domain.start },
+        };
+
+        MapFile {
+            version: self.version,
+            sector_size: self.sector_size,
+            domain,
+            map,
+            samples: vec![],
+        }
+    }
+
+    /// Map of only the clusters within domain, as crop() gives, with sectors
+    /// counted from the start of domain. Only for showing offsets from there.
+    pub fn rebase(&self, domain: Domain) -> MapFile {
+        let mut map = self.crop(domain);
+        let origin = map.domain.start;
+        let shift = |d: Domain| Domain { start: d.start - origin, end: d.end - origin };
+
+        map.domain = shift(map.domain);
+
+        for cluster in map.map.iter_mut() {
+            cluster.domain = shift(cluster.domain);
+        }
+
+        map
+    }
+
+    /// Requeue Damaged sectors within domain as Untested,
+    /// leaving everything else as is.
+    pub fn retry(&mut self, domain: Domain) -> &mut Self {
+        let retries: Vec<Cluster> = self.map.iter()
+            .filter(|c| c.stage == Stage::Damaged)
+            .filter_map(|c| {
+                let start = c.domain.start.max(domain.start);
+                let end = c.domain.end.min(domain.end);
+
+                if start < end {
+                    Some(Cluster {
+                        domain: Domain { start, end },
+                        stage: Stage::Untested,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        for cluster in retries {
+            self.update(cluster);
+        }
+
+        self
+    }
+
+    /// Compare against another map of the same sector_size,
+    /// returning each region whose stage differs as (domain, old, new).
+    /// Regions outside of either map are ignored.
+    pub fn diff(&self, other: &MapFile) -> Result<Vec<(Domain, Stage, Stage)>, String> {
+        if self.sector_size != other.sector_size {
+            return Err(format!(
+                "Cannot compare maps of sector sizes {} and {}",
+                self.sector_size, other.sector_size,
+            ));
+        }
+
+        let mut old = self.map.to_owned();
+        let mut new = other.map.to_owned();
+        old.sort_by_key(|c| c.domain.start);
+        new.sort_by_key(|c| c.domain.start);
+
+        let mut changes: Vec<(Domain, Stage, Stage)> = vec![];
+        let (mut i, mut j) = (0, 0);
+
+        while i < old.len() && j < new.len() {
+            let (old_cluster, new_cluster) = (old[i], new[j]);
+
+            let start = old_cluster.domain.start.max(new_cluster.domain.start);
+            let end = old_cluster.domain.end.min(new_cluster.domain.end);
+
+            if start < end && old_cluster.stage != new_cluster.stage {
+                // Extend the last change if this continues it.
+                match changes.last_mut() {
+                    Some((domain, old_stage, new_stage))
+                    if domain.end == start
+                    && *old_stage == old_cluster.stage
+                    && *new_stage == new_cluster.stage => {
+                        domain.end = end;
+                    },
+                    _ => changes.push((
+                        Domain { start, end },
+                        old_cluster.stage,
+                        new_cluster.stage,
+                    )),
+                }
+            }
+
+            if old_cluster.domain.end <= new_cluster.domain.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Get current recovery stage.
+    /// Stages are ordered by recovery progress, so this is the earliest
+    /// stage of any cluster. Damaged, Recovered and Verified are terminal;
+    /// Recovered is only returned once every cluster is recovered.
+    pub fn get_stage(&self) -> Stage {
+        let mut recover_stage = Stage::Verified;
+
+        for cluster in self.map.iter() {
+            // PartialOrd also compares the internal ForIsolation level,
+            // so the lowest level is always picked first.
+            if cluster.stage < recover_stage {
+                recover_stage = cluster.stage;
+            }
+
+            if recover_stage == Stage::Untested {
+                break;
+            }
+        }
+
+        recover_stage
+    }
+
+    /// Clusters of common stage, in map order, without copying them.
+    pub fn clusters_with_stage(&self, stage: Stage) -> impl Iterator<Item = &Cluster> {
+        self.map.iter().filter(move |mc| mc.stage == stage)
+    }
+
+    /// Get clusters of common stage.
+    pub fn get_clusters(&self, stage: Stage) -> Vec<Cluster> {
+        self.clusters_with_stage(stage).copied().collect()
+    }
+
+    /// Get domains of clusters of common stage.
+    pub fn get_domains(&self, stage: Stage) -> Vec<Domain> {
+        self.clusters_with_stage(stage).map(|mc| mc.domain).collect()
+    }
+
+    /// Damaged sectors in order, with touching clusters joined into one region.
+    pub fn bad_regions(&self) -> Vec<BadRegion> {
+        let sector_size = self.sector_size as u64;
+        let mut domains = self.get_domains(Stage::Damaged);
+        domains.sort_by_key(|d| d.start);
+
+        let mut joined: Vec<Domain> = vec![];
+
+        for domain in domains {
+            match joined.last_mut() {
+                Some(last) if last.end >= domain.start => last.end = last.end.max(domain.end),
+                _ => joined.push(domain),
+            }
+        }
+
+        joined.iter()
+            .map(|d| BadRegion {
+                start: d.start,
+                end: d.end,
+                start_byte: d.start as u64 * sector_size,
+                end_byte: d.end as u64 * sector_size,
+            })
+            .collect()
+    }
+
+    /// Numbers of blocks of block_size bytes holding any damaged sector,
+    /// in order, as read by badblocks and e2fsck -l.
+    /// Blocks only partly damaged are counted as bad.
+    pub fn bad_blocks(&self, block_size: u64) -> Vec<u64> {
+        let mut blocks: Vec<u64> = self.bad_regions()
+            .iter()
+            .flat_map(|r| r.start_byte / block_size..r.end_byte.div_ceil(block_size))
+            .collect();
+
+        // Regions sharing a block would list it twice.
+        blocks.dedup();
+        blocks
+    }
+
+    /// Divide the domain into zones of (near) equal length,
+    /// counting sectors in each stage per zone.
+    pub fn zone_stats(&self, zones: usize) -> Vec<ZoneStats> {
+        let len = self.domain.len();
+
+        (0..zones)
+            .map(|i| {
+                let mut zone = ZoneStats {
+                    start: self.domain.start + i * len / zones,
+                    end: self.domain.start + (i + 1) * len / zones,
+                    ..Default::default()
+                };
+
+                for cluster in self.map.iter() {
+                    let start = cluster.domain.start.max(zone.start);
+                    let end = cluster.domain.end.min(zone.end);
+
+                    if start >= end {
+                        continue;
+                    }
+
+                    *zone.count_mut(cluster.stage) += end - start;
+                }
+
+                zone
+            })
+            .collect()
+    }
+
+    /// Sector counts per stage over the whole domain.
+    /// Counts always sum to the domain's length: any gaps count as
+    /// Untested, and overlaps only once, as normalize() would leave them.
+    pub fn sector_counts(&self) -> ZoneStats {
+        // Only maps which don't partition the domain need copying to count.
+        let mut zones = match self.validate() {
+            Ok(_) => self.zone_stats(1),
+            Err(_) => self.to_owned().normalize().zone_stats(1),
+        };
+
+        zones.remove(0)
+    }
+
+    /// Share of the domain's sectors recovered, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        match self.domain.len() {
+            0 => 0.0,
+            n => self.sector_counts().recovered as f64 / n as f64,
+        }
+    }
+
+    /// Defragments cluster groups.
+    /// I.E. merge every run of touching clusters of common stage
+    /// into a single cluster.
+    ///
+    /// Gaps between clusters are left as they are, and never merged across.
+    /// Overlapping clusters can't be merged, so are an error, leaving the
+    /// map unchanged; normalize() repairs them.
+    pub fn defrag(&mut self) -> Result<&mut Self, KramerError> {
+        if self.has_overlaps() {
+            return Err(KramerError::BadMap(String::from(
+                "Contains overlapping clusters, which can't be defragmented"
+            )));
+        }
+
+        let covered = self.covered_len();
+
+        let mut clusters = std::mem::take(&mut self.map);
+        clusters.sort_by_key(|c| c.domain.start);
+
+        for cluster in clusters {
+            match self.map.last_mut() {
+                Some(last) if last.stage == cluster.stage
+                    && last.domain.end == cluster.domain.start => {
+                    last.domain.end = cluster.domain.end;
+                },
+                _ => self.map.push(cluster),
+            }
+        }
+
+        debug_assert_eq!(
+            self.covered_len(),
+            covered,
+            "Defragmenting changed the sectors covered by map {:?}.",
+            self.map
+        );
+
+        Ok(self)
+    }
+
+    /// Sectors covered by clusters, gaps aside.
+    fn covered_len(&self) -> usize {
+        self.map.iter().map(|c| c.domain.len()).sum()
+    }
+
+    /// Check, in debug builds, that cluster lengths sum to the domain length.
+    /// Anything else means clusters overlap or leave gaps.
+    fn debug_assert_coverage(&self) {
+        debug_assert_eq!(
+            self.map.iter().map(|c| c.domain.len()).sum::<usize>(),
+            self.domain.len(),
+            "Cluster lengths don't sum to domain length in map {:?}.",
+            self.map
+        );
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch_path;
+
+    // Test for MapFile::save()
+    #[test]
+    fn test_save() {
+        let path = scratch_path("save.map");
+
+        let mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 512,
+            domain: Domain { start: 0, end: 8 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 3 },
+                    stage: Stage::Untested,
+                },
+                Cluster {
+                    domain: Domain { start: 3, end: 5 },
+                    stage: Stage::ForIsolation(2),
+                },
+                Cluster {
+                    domain: Domain { start: 5, end: 8 },
+                    stage: Stage::Damaged,
+                },
+            ],
+            samples: vec![],
+        };
+
+        mf.save(&path).unwrap();
+        let recieved = MapFile::try_from(File::open(&path).unwrap()).ok();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            recieved.as_ref() == Some(&mf),
+            "Expected {:?} after saving and loading, got {:?}.",
+            mf, recieved
+        )
+    }
+
+    // Test for MapFile::save_with_backup()
+    #[test]
+    fn test_save_with_backup() {
+        let path = scratch_path("save_with_backup.map");
+        let backup_path = scratch_path("save_with_backup.map.bak");
+
+        let first = MapFile::new(512).set_domain(Domain { start: 0, end: 8 }).to_owned();
+        let second = first.to_owned()
+            .update(Cluster::new(Domain { start: 0, end: 3 }, Stage::Recovered))
+            .to_owned();
+        let third = second.to_owned()
+            .update(Cluster::new(Domain { start: 3, end: 5 }, Stage::Damaged))
+            .to_owned();
+
+        // Nothing to back up yet.
+        first.save_with_backup(&path, MapFormat::Ron, true).unwrap();
+        let no_backup = fs::exists(&backup_path).unwrap();
+
+        second.save_with_backup(&path, MapFormat::Ron, true).unwrap();
+        third.save_with_backup(&path, MapFormat::Ron, true).unwrap();
+
+        let recieved = MapFile::try_from(File::open(&path).unwrap()).ok();
+        let backup = MapFile::try_from(File::open(&backup_path).unwrap()).ok();
+
+        // Left as it is.
+        first.save_with_backup(&path, MapFormat::Ron, false).unwrap();
+        let kept = MapFile::try_from(File::open(&backup_path).unwrap()).ok();
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup_path).unwrap();
+
+        assert!(
+            !no_backup
+            && recieved.as_ref() == Some(&third)
+            && backup.as_ref() == Some(&second)
+            && kept == backup,
+            "Expected {:?} saved with {:?} backed up, got {:?} and {:?}.",
+            third, second, recieved, backup
+        )
+    }
+
+    // Test for MapFile::save_as() and MapFile::load()
+    #[test]
+    fn test_save_as_load() {
+        let mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 2048,
+            domain: Domain { start: 0, end: 1 << 33 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 300 },
+                    stage: Stage::Verified,
+                },
+                Cluster {
+                    domain: Domain { start: 300, end: 70_000 },
+                    stage: Stage::ForIsolation(17),
+                },
+                Cluster {
+                    domain: Domain { start: 70_000, end: 70_001 },
+                    stage: Stage::Damaged,
+                },
+                Cluster {
+                    domain: Domain { start: 70_001, end: 1 << 32 },
+                    stage: Stage::Recovered,
+                },
+                Cluster {
+                    domain: Domain { start: 1 << 32, end: 1 << 33 },
+                    stage: Stage::Untested,
+                },
+            ],
+            // Sampled before and after, and only before.
+            samples: vec![
+                SourceSample {
+                    sector: 0,
+                    before: String::from("5f70bf18"),
+                    after: Some(String::from("0a1b2c3d")),
+                },
+                SourceSample {
+                    sector: 1 << 32,
+                    before: String::from("e3b0c442"),
+                    after: None,
+                },
+            ],
+        };
+
+        for format in [MapFormat::Ron, MapFormat::Json, MapFormat::Cbor] {
+            let path = scratch_path(&format!("save_as_{:?}.map", format));
+
+            mf.save_as(&path, format).unwrap();
+            let recieved = MapFile::load(File::open(&path).unwrap(), 512);
+            fs::remove_file(&path).unwrap();
+
+            assert!(
+                recieved.as_ref().ok() == Some(&(mf.to_owned(), format)),
+                "Expected {:?} as {:?} after saving and loading, got {:?}.",
+                mf, format, recieved
+            )
+        }
+    }
+
+    // Test for MapFile::to_cbor() and MapFile::from_cbor() byte order
+    #[test]
+    fn test_cbor_byte_order() {
+        let mf = MapFile::new(0x0201)
+            .set_domain(Domain { start: 0, end: 0x0102_0304 })
+            .to_owned();
+
+        let mut saved: Vec<u8> = vec![];
+        mf.to_cbor(&mut saved).unwrap();
+
+        let find = |bytes: &[u8]| saved.windows(bytes.len()).position(|w| w == bytes);
+        let (sector_size, end) = (
+            find(&[0x19, 0x02, 0x01]).unwrap(),
+            find(&[0x1a, 0x01, 0x02, 0x03, 0x04]).unwrap(),
+        );
+
+        // As a little endian writer would have saved them.
+        let mut swapped = saved.to_owned();
+        swapped[sector_size + 1..sector_size + 3].reverse();
+        swapped[end + 1..end + 5].reverse();
+
+        let cases = vec![
+            (saved, 0x0201, 0x0102_0304),
+            (swapped, 0x0102, 0x0403_0201),
+        ];
+
+        for (contents, expected_sector_size, expected_end) in cases {
+            let recieved = MapFile::from_cbor(contents.as_slice()).unwrap();
+
+            assert!(
+                recieved.sector_size == expected_sector_size
+                && recieved.domain.end == expected_end,
+                "Expected sector size {:#x} and end {:#x} read most significant \
+                byte first, got {:#x} and {:#x}.",
+                expected_sector_size, expected_end, recieved.sector_size, recieved.domain.end
+            )
+        }
+    }
+
+    // Test for MapFile::from_cbor() with missing and unknown fields
+    #[test]
+    fn test_from_cbor_invalid() {
+        let mut missing: Vec<u8> = vec![];
+        cbor::write_head(&mut missing, cbor::MAP, 1).unwrap();
+        cbor::write_text(&mut missing, "sector_size").unwrap();
+        cbor::write_head(&mut missing, cbor::UNSIGNED, 512).unwrap();
+
+        let mut unknown: Vec<u8> = vec![];
+        cbor::write_head(&mut unknown, cbor::MAP, 1).unwrap();
+        cbor::write_text(&mut unknown, "sectors").unwrap();
+        cbor::write_head(&mut unknown, cbor::UNSIGNED, 512).unwrap();
+
+        let mut truncated: Vec<u8> = vec![];
+        MapFile::new(512).to_cbor(&mut truncated).unwrap();
+        truncated.pop();
+
+        let cases = vec![
+            (missing, "domain"),
+            (unknown, "sectors"),
+            (truncated, "end of file"),
+        ];
+
+        for (contents, expected) in cases {
+            let recieved = MapFile::from_cbor(contents.as_slice());
+
+            assert!(
+                recieved.as_ref().is_err_and(|err| err.to_string().contains(expected)),
+                "Expected an error naming {:?}, got {:?}.",
+                expected, recieved
+            )
+        }
+    }
+
+    // Test for MapFile::try_from() with another format version
+    #[test]
+    fn test_try_from_version() {
+        let path = scratch_path("version.map");
+
+        fs::write(&path, "(
+            version: 99,
+            sector_size: 512,
+            domain: (start: 0, end: 8),
+            map: [(domain: (start: 0, end: 8), stage: Untested)],
+        )").unwrap();
+        let recieved = MapFile::try_from(File::open(&path).unwrap());
+
+        // Maps saved before versioning are the current version.
+        fs::write(&path, "(
+            sector_size: 512,
+            domain: (start: 0, end: 8),
+            map: [(domain: (start: 0, end: 8), stage: Untested)],
+        )").unwrap();
+        let legacy = MapFile::try_from(File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            matches!(&recieved, Err(KramerError::BadMap(msg)) if msg.contains("version 99")),
+            "Expected the version to be rejected by name, got {:?}.",
+            recieved
+        );
+        assert!(
+            legacy.is_ok_and(|map| map.version == FORMAT_VERSION),
+            "Expected an unversioned map to load."
+        )
+    }
+
+    /// Mapfile from GNU ddrescue 1.27 part way through trimming.
+    const DDRESCUE_MAP: &str = "\
+# Mapfile. Created by GNU ddrescue version 1.27
+# Command line: ddrescue -d -b 2048 /dev/sr0 disc.iso disc.map
+# Start time:   2023-03-01 10:00:00
+# Current time: 2023-03-01 10:05:12
+# Trimming failed blocks... (forwards)
+# current_pos  current_status  current_pass
+0x01A10000     *               1
+#      pos        size  status
+0x00000000  0x01A00000  +
+0x01A00000  0x00010000  *
+0x01A10000  0x00000800  -
+0x01A10800  0x0006F800  /
+0x01A80000  0x02580000  ?
+";
+
+    /// Logfile from GNU ddrescue 1.16, before current_pass was added,
+    /// of an input which isn't a whole number of 512 byte sectors.
+    const DDRESCUE_LOG: &str = "\
+# Rescue Logfile. Created by GNU ddrescue version 1.16
+# current_pos  current_status
+0x00000000     +
+#      pos        size  status
+0x00000000  0x00000400  +
+0x00000400  0x00000200  -
+0x00000600  0x00000100  +
+";
+
+    // Test for MapFormat::detect()
+    #[test]
+    fn test_detect_format() {
+        let cases: Vec<(&[u8], MapFormat)> = vec![
+            (b"# Mapfile. Created by GNU ddrescue version 1.27\n", MapFormat::Ddrescue),
+            (b"0x00000000     +\n", MapFormat::Ddrescue),
+            (b"\n  0X00000000     +\n", MapFormat::Ddrescue),
+            (b"(\n    version: 1,\n", MapFormat::Ron),
+            (b"MapFile(sector_size: 512", MapFormat::Ron),
+            (b"{\n  \"version\": 1,", MapFormat::Json),
+            (b"\r\n\t{\"version\":1,", MapFormat::Json),
+            (b"\xd9\xd9\xf7\xa4gversion", MapFormat::Cbor),
+            (b"\xa4gversion", MapFormat::Cbor),
+            (b"", MapFormat::Ron),
+        ];
+
+        for (head, expected) in cases {
+            let recieved = MapFormat::detect(head);
+
+            assert!(
+                recieved == expected,
+                "Expected {:?} for {:?}, got {:?}.",
+                expected, String::from_utf8_lossy(head), recieved
+            )
+        }
+    }
+
+    // Test for MapFormat::detect_from()
+    #[test]
+    fn test_detect_from() {
+        let mut reader = io::Cursor::new(b"# Mapfile. Created by GNU ddrescue version 1.27\n".to_vec());
+        let recieved = MapFormat::detect_from(&mut reader).unwrap();
+
+        assert!(
+            recieved == MapFormat::Ddrescue && reader.position() == 0,
+            "Expected a ddrescue mapfile detected and the reader rewound, got {:?} at {}.",
+            recieved, reader.position()
+        )
+    }
+
+    // Test for MapFile::from_ddrescue()
+    #[test]
+    fn test_from_ddrescue() {
+        let cases = vec![
+            (
+                DDRESCUE_MAP,
+                2048,
+                MapFile {
+                    version: FORMAT_VERSION,
+                    sector_size: 2048,
+                    domain: Domain { start: 0, end: 0x4000000 / 2048 },
+                    map: vec![
+                        Cluster {
+                            domain: Domain { start: 0, end: 0x1A00000 / 2048 },
+                            stage: Stage::Recovered,
+                        },
+                        Cluster {
+                            domain: Domain { start: 0x1A00000 / 2048, end: 0x1A10000 / 2048 },
+                            stage: Stage::ForIsolation(0),
+                        },
+                        Cluster {
+                            domain: Domain { start: 0x1A10000 / 2048, end: 0x1A10800 / 2048 },
+                            stage: Stage::Damaged,
+                        },
+                        Cluster {
+                            domain: Domain { start: 0x1A10800 / 2048, end: 0x1A80000 / 2048 },
+                            stage: Stage::ForIsolation(1),
+                        },
+                        Cluster {
+                            domain: Domain { start: 0x1A80000 / 2048, end: 0x4000000 / 2048 },
+                            stage: Stage::Untested,
+                        },
+                    ],
+                    samples: vec![],
+                },
+            ),
+            (
+                DDRESCUE_LOG,
+                512,
+                MapFile {
+                    version: FORMAT_VERSION,
+                    sector_size: 512,
+                    // The last block is only part of a sector.
+                    domain: Domain { start: 0, end: 3 },
+                    map: vec![
+                        Cluster {
+                            domain: Domain { start: 0, end: 2 },
+                            stage: Stage::Recovered,
+                        },
+                        Cluster {
+                            domain: Domain { start: 2, end: 3 },
+                            stage: Stage::Damaged,
+                        },
+                    ],
+                    samples: vec![],
+                },
+            ),
+        ];
+
+        for (mapfile, sector_size, expected) in cases {
+            let recieved = MapFile::from_ddrescue(mapfile.as_bytes(), sector_size);
+
+            assert!(
+                recieved.as_ref().ok() == Some(&expected),
+                "Expected {:?} from ddrescue mapfile, got {:?}.",
+                expected, recieved
+            )
+        }
+
+        let invalid = vec![
+            // Not aligned to 2048 byte sectors.
+            (DDRESCUE_LOG, 2048),
+            // Gap between blocks.
+            ("0x0 ?\n0x0 0x400 +\n0x600 0x200 -\n", 512),
+            // Unknown status.
+            ("0x0 ?\n0x0 0x400 x\n", 512),
+            // No blocks.
+            ("0x0 ?\n", 512),
+        ];
+
+        for (mapfile, sector_size) in invalid {
+            assert!(
+                MapFile::from_ddrescue(mapfile.as_bytes(), sector_size).is_err(),
+                "Expected {:?} to be rejected for {} byte sectors.",
+                mapfile, sector_size
+            )
+        }
+    }
+
+    // Test for MapFile::to_ddrescue()
+    #[test]
+    fn test_to_ddrescue() {
+        let blocks = |mapfile: &str| -> Vec<String> {
+            mapfile.lines()
+                .filter(|l| !l.starts_with('#'))
+                .skip(1)
+                .map(String::from)
+                .collect()
+        };
+
+        for (mapfile, sector_size) in [(DDRESCUE_MAP, 2048), (DDRESCUE_LOG, 512)] {
+            let mf = MapFile::from_ddrescue(mapfile.as_bytes(), sector_size).unwrap();
+
+            let mut exported: Vec<u8> = vec![];
+            mf.to_ddrescue(&mut exported).unwrap();
+            let exported = String::from_utf8(exported).unwrap();
+
+            let recieved = MapFile::from_ddrescue(exported.as_bytes(), sector_size);
+
+            assert!(
+                recieved.as_ref().ok() == Some(&mf),
+                "Expected {:?} after exporting and importing, got {:?}.",
+                mf, recieved
+            );
+
+            // Byte exact, but for the part of a sector at the end.
+            if mapfile == DDRESCUE_MAP {
+                assert!(
+                    blocks(&exported) == blocks(mapfile),
+                    "Expected blocks {:?}, exported {:?}.",
+                    blocks(mapfile), blocks(&exported)
+                )
+            }
+        }
+    }
+
+    // Test for Cluster::subdivide()
+    #[test]
+    fn test_subdivide() {
+        let domains = |ranges: &[(usize, usize)]| -> Vec<Domain> {
+            ranges.iter().map(|&(start, end)| Domain { start, end }).collect()
+        };
+
+        let cases = vec![
+            // Evenly divisible
+            (Domain { start: 4, end: 12 }, 4, domains(&[(4, 8), (8, 12)])),
+            // Remainder
+            (Domain { start: 4, end: 14 }, 4, domains(&[(4, 8), (8, 12), (12, 14)])),
+            // Shorter than cluster_len
+            (Domain { start: 4, end: 6 }, 4, domains(&[(4, 6)])),
+            // Single sector
+            (Domain { start: 7, end: 8 }, 4, domains(&[(7, 8)])),
+            (Domain { start: 0, end: 3 }, 1, domains(&[(0, 1), (1, 2), (2, 3)])),
+            // Empty
+            (Domain { start: 4, end: 4 }, 4, domains(&[])),
+        ];
+
+        for (domain, cluster_len, expected) in cases {
+            let mut cluster = Cluster { domain, stage: Stage::ForIsolation(1) };
+            let recieved = cluster.subdivide(cluster_len);
+
+            assert!(
+                recieved.iter().map(|c| c.domain).eq(expected.to_owned())
+                && recieved.iter().all(|c| c.stage == cluster.stage),
+                "Expected {:?} subdivided by {} to be {:?}, got {:?}.",
+                domain, cluster_len, expected, recieved
+            )
+        }
+    }
+
+    // Test for Cluster::split_at()
+    #[test]
+    fn test_split_at() {
+        let cluster = Cluster {
+            domain: Domain { start: 4, end: 12 },
+            stage: Stage::ForIsolation(2),
+        };
+
+        let cases = vec![
+            (4, Domain { start: 4, end: 4 }, Domain { start: 4, end: 12 }),
+            (7, Domain { start: 4, end: 7 }, Domain { start: 7, end: 12 }),
+            (12, Domain { start: 4, end: 12 }, Domain { start: 12, end: 12 }),
+        ];
+
+        for (sector, head_domain, tail_domain) in cases {
+            let expected = (
+                Cluster { domain: head_domain, stage: cluster.stage },
+                Cluster { domain: tail_domain, stage: cluster.stage },
+            );
+            let recieved = cluster.split_at(sector);
+
+            assert!(
+                expected == recieved,
+                "Expected split at {} to be {:?}, got {:?}.",
+                sector, expected, recieved
+            )
+        }
+    }
+
+    // Test for Stage::escalate()
+    #[test]
+    fn test_escalate() {
+        let mut stage = Stage::Untested;
+        let mut rounds: usize = 0;
+
+        while stage != Stage::Damaged {
+            stage = stage.escalate();
+            rounds += 1;
+
+            assert!(
+                rounds <= u8::MAX as usize + 2,
+                "Stage failed to reach {:?}, currently {:?}.",
+                Stage::Damaged, stage
+            )
+        }
+
+        // Untested -> ForIsolation(0..=255) -> Damaged
+        assert!(
+            rounds == u8::MAX as usize + 2,
+            "Expected {:?} after {} rounds, took {}.",
+            Stage::Damaged, u8::MAX as usize + 2, rounds
+        );
+        assert!(stage.escalate() == Stage::Damaged)
+    }
+
+    // Test for MapFile::update()
+    #[test]
+    fn test_update() {
+        let map = |clusters: &[(usize, usize, Stage)]| -> Vec<Cluster> {
+            clusters.iter()
+                .map(|&(start, end, stage)| Cluster { domain: Domain { start, end }, stage })
+                .collect()
+        };
+
+        let base = map(&[
+            (0, 4, Stage::Untested),
+            (4, 8, Stage::ForIsolation(0)),
+            (8, 12, Stage::Untested),
+        ]);
+
+        let cases = vec![
+            // Left overlap, cropping the end of one cluster.
+            (
+                (2, 4, Stage::Recovered),
+                map(&[
+                    (0, 2, Stage::Untested),
+                    (2, 4, Stage::Recovered),
+                    (4, 8, Stage::ForIsolation(0)),
+                    (8, 12, Stage::Untested),
+                ]),
+            ),
+            // Right overlap, cropping the start of one cluster.
+            (
+                (4, 6, Stage::Recovered),
+                map(&[
+                    (0, 4, Stage::Untested),
+                    (4, 6, Stage::Recovered),
+                    (6, 8, Stage::ForIsolation(0)),
+                    (8, 12, Stage::Untested),
+                ]),
+            ),
+            // Fully contained, fracturing one cluster.
+            (
+                (5, 7, Stage::Damaged),
+                map(&[
+                    (0, 4, Stage::Untested),
+                    (4, 5, Stage::ForIsolation(0)),
+                    (5, 7, Stage::Damaged),
+                    (7, 8, Stage::ForIsolation(0)),
+                    (8, 12, Stage::Untested),
+                ]),
+            ),
+            // Fully covering one cluster, and cropping both neighbours.
+            (
+                (2, 10, Stage::Recovered),
+                map(&[
+                    (0, 2, Stage::Untested),
+                    (2, 10, Stage::Recovered),
+                    (10, 12, Stage::Untested),
+                ]),
+            ),
+            // Exactly covering two clusters.
+            (
+                (4, 12, Stage::Damaged),
+                map(&[
+                    (0, 4, Stage::Untested),
+                    (4, 12, Stage::Damaged),
+                ]),
+            ),
+            // Covering the whole domain.
+            (
+                (0, 12, Stage::Recovered),
+                map(&[(0, 12, Stage::Recovered)]),
+            ),
+            // Replacing an identical domain.
+            (
+                (4, 8, Stage::Recovered),
+                map(&[
+                    (0, 4, Stage::Untested),
+                    (4, 8, Stage::Recovered),
+                    (8, 12, Stage::Untested),
+                ]),
+            ),
+            // Overhanging the domain, cropped to fit.
+            (
+                (10, 16, Stage::Damaged),
+                map(&[
+                    (0, 4, Stage::Untested),
+                    (4, 8, Stage::ForIsolation(0)),
+                    (8, 10, Stage::Untested),
+                    (10, 12, Stage::Damaged),
+                ]),
+            ),
+            // Empty clusters change nothing, nor do those outside the domain.
+            ((6, 6, Stage::Damaged), base.to_owned()),
+            ((12, 16, Stage::Damaged), base.to_owned()),
+        ];
+
+        for ((start, end, stage), expected) in cases {
+            let mut mf = MapFile {
+                version: FORMAT_VERSION,
+                sector_size: 1,
+                domain: Domain { start: 0, end: 12 },
+                map: base.to_owned(),
+                samples: vec![],
+            };
+            let new_cluster = Cluster { domain: Domain { start, end }, stage };
+
+            mf.update(new_cluster);
+
+            assert!(
+                expected == mf.map && !mf.has_overlaps(),
+                "Expected {:?} after updating with {:?}, got {:?}.",
+                expected, new_cluster, mf.map
+            )
+        }
+    }
+
+    // Test that MapFile::update() merges neighbours of the same stage.
+    #[test]
+    fn test_update_merge() {
+        let domain = Domain { start: 0, end: 16 };
+        let mut mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 1,
+            domain,
+            map: vec![Cluster { domain, stage: Stage::Untested }],
+            samples: vec![],
+        };
+
+        // Out of order, so clusters merge on either side.
+        for sector in (8..16).chain(0..8).rev() {
+            mf.update(Cluster {
+                domain: Domain { start: sector, end: sector + 1 },
+                stage: Stage::Recovered,
+            });
+        }
+
+        let expected = vec![Cluster { domain, stage: Stage::Recovered }];
+
+        assert!(
+            expected == mf.map,
+            "Expected {:?} after updating every sector, got {:?}.",
+            expected, mf.map
+        );
+
+        // Splitting and restoring a cluster leaves it whole.
+        mf.update(Cluster {
+            domain: Domain { start: 4, end: 6 },
+            stage: Stage::Damaged,
+        })
+        .update(Cluster {
+            domain: Domain { start: 4, end: 6 },
+            stage: Stage::Recovered,
+        });
+
+        assert!(
+            expected == mf.map,
+            "Expected {:?} after restoring a split cluster, got {:?}.",
+            expected, mf.map
+        )
+    }
+
+    // Test that MapFile::update() and MapFile::defrag() never double-count.
+    #[test]
+    fn test_coverage_invariant() {
+        // Tiny xorshift, so failures are reproducible from the seed.
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| -> usize {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+
+        let stages = [
+            Stage::Untested,
+            Stage::ForIsolation(0),
+            Stage::ForIsolation(1),
+            Stage::Damaged,
+        ];
+
+        for _ in 0..64 {
+            let domain = Domain { start: 0, end: 1 + next(64) };
+            let mut mf = MapFile {
+                version: FORMAT_VERSION,
+                sector_size: 1,
+                domain,
+                map: vec![Cluster { domain, stage: Stage::Untested }],
+                samples: vec![],
+            };
+
+            for _ in 0..64 {
+                let start = next(domain.end);
+                let end = start + 1 + next(domain.end - start);
+
+                mf.update(Cluster {
+                    domain: Domain { start, end },
+                    stage: stages[next(stages.len())],
+                });
+
+                if next(4) == 0 {
+                    mf.defrag().unwrap();
+                }
+
+                let covered: usize = mf.map.iter().map(|c| c.domain.len()).sum();
+
+                assert!(
+                    covered == mf.domain.len(),
+                    "Clusters cover {} sectors of a {} sector domain in map {:?}.",
+                    covered, mf.domain.len(), mf.map
+                )
+            }
+        }
+    }
+
+    // Test for MapFile::has_overlaps() and MapFile::normalize()
+    #[test]
+    fn test_normalize() {
+        let cases = vec![
+            (
+                vec![
+                    Cluster {
+                        domain: Domain { start: 0, end: 3 },
+                        stage: Stage::Untested,
+                    },
+                    Cluster {
+                        domain: Domain { start: 2, end: 4 },
+                        stage: Stage::Damaged,
+                    },
+                ],
+                vec![
+                    Cluster {
+                        domain: Domain { start: 0, end: 2 },
+                        stage: Stage::Untested,
+                    },
+                    Cluster {
+                        domain: Domain { start: 2, end: 4 },
+                        stage: Stage::Damaged,
+                    },
+                ],
+            ),
+            (
+                vec![
+                    Cluster {
+                        domain: Domain { start: 0, end: 2 },
+                        stage: Stage::Damaged,
+                    },
+                    Cluster {
+                        domain: Domain { start: 2, end: 4 },
+                        stage: Stage::Untested,
+                    },
+                    Cluster {
+                        domain: Domain { start: 0, end: 2 },
+                        stage: Stage::ForIsolation(0),
+                    },
+                ],
+                vec![
+                    Cluster {
+                        domain: Domain { start: 0, end: 2 },
+                        stage: Stage::ForIsolation(0),
+                    },
+                    Cluster {
+                        domain: Domain { start: 2, end: 4 },
+                        stage: Stage::Untested,
+                    },
+                ],
+            ),
+        ];
+
+        for (map, expected) in cases {
+            let mut mf = MapFile {
+                version: FORMAT_VERSION,
+                sector_size: 1,
+                domain: Domain { start: 0, end: 4 },
+                map,
+                samples: vec![],
+            };
+
+            assert!(mf.has_overlaps(), "Overlaps not detected in {:?}.", mf.map);
+
+            mf.normalize();
+
+            assert!(
+                expected == mf.map && !mf.has_overlaps(),
+                "Expected {:?} after normalizing, got {:?}.",
+                expected, mf.map
+            )
+        }
+    }
+
+    // Test for Domain::from_str()
+    #[test]
+    fn test_domain_from_str() {
+        assert!(" 4..12 ".parse::<Domain>() == Ok(Domain { start: 4, end: 12 }));
+
+        for s in ["4", "12..4", "4..4", "a..12", "4..-12"] {
+            assert!(
+                s.parse::<Domain>().is_err(),
+                "Expected {:?} to be rejected.",
+                s
+            )
+        }
+    }
+
+    // Test for Domain::contains()
+    #[test]
+    fn test_domain_contains() {
+        let domain = Domain { start: 4, end: 8 };
+        let cases = vec![
+            (3, false),
+            (4, true),
+            (7, true),
+            (8, false),
+        ];
+
+        for (sector, expected) in cases {
+            assert!(
+                domain.contains(sector) == expected,
+                "Expected contains({}) to be {} for {:?}.",
+                sector, expected, domain
+            )
+        }
 
-                let domain_end = map_cluster.domain.end;
+        assert!(!Domain { start: 4, end: 4 }.contains(4))
+    }
 
-                // Crop current object.
-                map_cluster.domain.end = new_cluster.domain.start;
-                new_map.push(map_cluster);
+    // Test for Domain::overlaps() and Domain::intersect()
+    #[test]
+    fn test_domain_intersect() {
+        let domain = Domain { start: 4, end: 8 };
+        let cases = vec![
+            // Touching.
+            (Domain { start: 0, end: 4 }, None),
+            (Domain { start: 8, end: 12 }, None),
+            // Nested.
+            (Domain { start: 5, end: 7 }, Some(Domain { start: 5, end: 7 })),
+            (Domain { start: 0, end: 12 }, Some(Domain { start: 4, end: 8 })),
+            // Disjoint.
+            (Domain { start: 10, end: 12 }, None),
+            // Identical.
+            (Domain { start: 4, end: 8 }, Some(Domain { start: 4, end: 8 })),
+            // Partial.
+            (Domain { start: 2, end: 6 }, Some(Domain { start: 4, end: 6 })),
+            // Empty, even within.
+            (Domain { start: 6, end: 6 }, None),
+        ];
 
-                if new_cluster.domain.end < map_cluster.domain.end {
-                    /*
-                    new_cluster is within map_cluster.
+        for (other, expected) in cases {
+            assert!(
+                domain.intersect(&other) == expected && other.intersect(&domain) == expected,
+                "Expected {:?} intersecting {:?} and {:?}.",
+                expected, domain, other
+            );
+            assert!(
+                domain.overlaps(&other) == expected.is_some(),
+                "Expected overlaps to be {} for {:?} and {:?}.",
+                expected.is_some(), domain, other
+            )
+        }
+    }
 
-                    ACTION: Crop & Fracture map_cluster
-                    NOTE: Crop completed above.
-                    */
+    // Test for MapFile::crop()
+    #[test]
+    fn test_crop() {
+        let mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 1,
+            domain: Domain { start: 0, end: 10 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 4 },
+                    stage: Stage::Recovered,
+                },
+                Cluster {
+                    domain: Domain { start: 4, end: 6 },
+                    stage: Stage::Damaged,
+                },
+                Cluster {
+                    domain: Domain { start: 6, end: 10 },
+                    stage: Stage::Untested,
+                },
+            ],
+            samples: vec![],
+        };
 
-                    new_map.push(Cluster {
-                        domain: Domain {
-                            start: new_cluster.domain.end,
-                            end: domain_end,
-                        },
-                        stage: map_cluster.stage.to_owned()
-                    });
-                }
-            } else {
-                /*
-                No overlap.
+        let cases = vec![
+            (
+                Domain { start: 2, end: 8 },
+                Domain { start: 2, end: 8 },
+                vec![
+                    Cluster {
+                        domain: Domain { start: 2, end: 4 },
+                        stage: Stage::Recovered,
+                    },
+                    Cluster {
+                        domain: Domain { start: 4, end: 6 },
+                        stage: Stage::Damaged,
+                    },
+                    Cluster {
+                        domain: Domain { start: 6, end: 8 },
+                        stage: Stage::Untested,
+                    },
+                ],
+            ),
+            (
+                Domain { start: 0, end: 4 },
+                Domain { start: 0, end: 4 },
+                vec![
+                    Cluster {
+                        domain: Domain { start: 0, end: 4 },
+                        stage: Stage::Recovered,
+                    },
+                ],
+            ),
+            // Clamped to the map's domain.
+            (
+                Domain { start: 8, end: 20 },
+                Domain { start: 8, end: 10 },
+                vec![
+                    Cluster {
+                        domain: Domain { start: 8, end: 10 },
+                        stage: Stage::Untested,
+                    },
+                ],
+            ),
+            (
+                Domain { start: 12, end: 20 },
+                Domain { start: 12, end: 12 },
+                vec![],
+            ),
+        ];
 
-                ACTION: Transfer
-                */
+        for (domain, expected_domain, expected) in cases {
+            let cropped = mf.crop(domain);
 
-                new_map.push(map_cluster);
-            }
+            assert!(
+                cropped.domain == expected_domain && cropped.map == expected,
+                "Expected {:?} over {:?} cropping to {:?}, got {:?} over {:?}.",
+                expected, expected_domain, domain, cropped.map, cropped.domain
+            )
         }
-
-        self.map = new_map;
-        self
     }
 
-    /// Get current recovery stage.
-    pub fn get_stage(&self) -> Stage {
-        let mut recover_stage = Stage::Damaged;
-
-        for cluster in self.map.iter() {
-            match cluster.stage {
-                Stage::Untested => return Stage::Untested,
-                Stage::ForIsolation(_) => {
-                    if recover_stage == Stage::Damaged
-                    || cluster.stage < recover_stage {
-                        // Note that recover_stage after first condition is 
-                        // only ever Stage::ForIsolation(_), thus PartialEq,
-                        // PartialOrd are useful for comparing the internal value.
-                        recover_stage = cluster.stage
-                    }
+    // Test for MapFile::rebase()
+    #[test]
+    fn test_rebase() {
+        let mf = MapFile::new(512)
+            .set_domain(Domain { start: 0, end: 10 })
+            .update(Cluster::new(Domain { start: 4, end: 6 }, Stage::Damaged))
+            .to_owned();
+
+        let cases = vec![
+            (Domain { start: 0, end: 10 }, mf.to_owned()),
+            (
+                Domain { start: 3, end: 8 },
+                MapFile {
+                    domain: Domain { start: 0, end: 5 },
+                    map: vec![
+                        Cluster::new(Domain { start: 0, end: 1 }, Stage::Untested),
+                        Cluster::new(Domain { start: 1, end: 3 }, Stage::Damaged),
+                        Cluster::new(Domain { start: 3, end: 5 }, Stage::Untested),
+                    ],
+                    ..mf.to_owned()
                 },
-                Stage::Damaged => (),
-            }
-        }
+            ),
+        ];
 
-        recover_stage
-    }
+        for (domain, expected) in cases {
+            let recieved = mf.rebase(domain);
 
-    /// Get clusters of common stage.
-    pub fn get_clusters(&self, stage: Stage) -> Vec<Cluster> {
-        self.map.iter()
-            .filter_map(|mc| {
-                if mc.stage == stage { Some(mc.to_owned()) } else { None }
-            })
-            .collect()
+            assert!(
+                recieved == expected,
+                "Expected {:?} rebased to {:?}, got {:?}.",
+                expected, domain, recieved
+            )
+        }
     }
 
-    /// Defragments cluster groups.
-    /// I.E. check forwards every cluster from current until stage changes,
-    /// then group at once.
-    fn defrag(&mut self) -> &mut Self {
-        let mut new_map: Vec<Cluster> = vec![];
-
-        // Fetch first cluster.
-        let mut start_cluster = self.map.iter()
-            .find(|c| c.domain.start == 0)
-            .unwrap();
-        
-        // Even though this would be initialized by its first read,
-        // the compiler won't stop whining, and idk how to assert that to it.
-        let mut end_cluster = Cluster::default();
-        let mut new_cluster: Cluster;
-
-        let mut stage_common: bool;
-        let mut is_finished = false;
-
-        while !is_finished {
-            stage_common = true;
+    // Test for MapFile::crop_all()
+    #[test]
+    fn test_crop_all() {
+        let mf = MapFile::new(512)
+            .set_domain(Domain { start: 0, end: 20 })
+            .update(Cluster::new(Domain { start: 4, end: 6 }, Stage::Damaged))
+            .to_owned();
+
+        let cases = vec![
+            (vec![], MapFile { domain: Domain { start: 0, end: 0 }, map: vec![], ..mf.to_owned() }),
+            // Out of order, and overlapping.
+            (
+                vec![
+                    Domain { start: 12, end: 16 },
+                    Domain { start: 2, end: 5 },
+                    Domain { start: 3, end: 8 },
+                ],
+                MapFile {
+                    domain: Domain { start: 2, end: 16 },
+                    map: vec![
+                        Cluster::new(Domain { start: 2, end: 4 }, Stage::Untested),
+                        Cluster::new(Domain { start: 4, end: 6 }, Stage::Damaged),
+                        Cluster::new(Domain { start: 6, end: 8 }, Stage::Untested),
+                        Cluster::new(Domain { start: 12, end: 16 }, Stage::Untested),
+                    ],
+                    ..mf.to_owned()
+                },
+            ),
+        ];
 
-            // Start a new cluster based on the cluster following
-            // the end of last new_cluster.
-            new_cluster = start_cluster.to_owned();
+        for (domains, expected) in cases {
+            let recieved = mf.crop_all(&domains);
 
-            // While stage is common, and not finished,
-            // find each trailing cluster.
-            while stage_common && !is_finished {
-                end_cluster = start_cluster.to_owned();
+            assert!(
+                recieved == expected,
+                "Expected {:?} cropped to {:?}, got {:?}.",
+                expected, domains, recieved
+            )
+        }
+    }
 
-                if end_cluster.domain.end != self.domain.end {
-                    start_cluster = self.map.iter()
-                        .find(|c| end_cluster.domain.end == c.domain.start)
-                        .unwrap();
+    // Test for MapFile::retry()
+    #[test]
+    fn test_retry() {
+        let mut mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 1,
+            domain: Domain { start: 0, end: 10 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 2 },
+                    stage: Stage::Damaged,
+                },
+                Cluster {
+                    domain: Domain { start: 2, end: 4 },
+                    stage: Stage::ForIsolation(1),
+                },
+                Cluster {
+                    domain: Domain { start: 4, end: 8 },
+                    stage: Stage::Damaged,
+                },
+                Cluster {
+                    domain: Domain { start: 8, end: 10 },
+                    stage: Stage::Damaged,
+                },
+            ],
+            samples: vec![],
+        };
 
-                    stage_common = new_cluster.stage == start_cluster.stage
-                } else {
-                    is_finished = true;
-                }
-            }
+        mf.retry(Domain { start: 3, end: 6 })
+            .retry(Domain { start: 8, end: 10 });
 
-            // Set the new ending, encapsulating any clusters of common stage.
-            new_cluster.domain.end = end_cluster.domain.end;
-            new_map.push(new_cluster);
-        }
+        let expected = vec![
+            Cluster {
+                domain: Domain { start: 0, end: 2 },
+                stage: Stage::Damaged,
+            },
+            Cluster {
+                domain: Domain { start: 2, end: 4 },
+                stage: Stage::ForIsolation(1),
+            },
+            Cluster {
+                domain: Domain { start: 4, end: 6 },
+                stage: Stage::Untested,
+            },
+            Cluster {
+                domain: Domain { start: 6, end: 8 },
+                stage: Stage::Damaged,
+            },
+            Cluster {
+                domain: Domain { start: 8, end: 10 },
+                stage: Stage::Untested,
+            },
+        ];
 
-        self.map = new_map;
-        self
+        assert!(
+            expected == mf.map,
+            "Expected {:?} after retrying, got {:?}.",
+            expected, mf.map
+        )
     }
-}
 
+    // Test for MapFile::diff()
+    #[test]
+    fn test_diff() {
+        let old = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 512,
+            domain: Domain { start: 0, end: 12 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 8 },
+                    stage: Stage::Untested,
+                },
+                Cluster {
+                    domain: Domain { start: 8, end: 12 },
+                    stage: Stage::ForIsolation(0),
+                },
+            ],
+            samples: vec![],
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let new = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 512,
+            domain: Domain { start: 0, end: 12 },
+            map: vec![
+                // Unchanged
+                Cluster {
+                    domain: Domain { start: 0, end: 2 },
+                    stage: Stage::Untested,
+                },
+                // Failed copying
+                Cluster {
+                    domain: Domain { start: 2, end: 4 },
+                    stage: Stage::ForIsolation(0),
+                },
+                Cluster {
+                    domain: Domain { start: 4, end: 6 },
+                    stage: Stage::ForIsolation(0),
+                },
+                // Unchanged, across a cluster boundary.
+                Cluster {
+                    domain: Domain { start: 6, end: 8 },
+                    stage: Stage::Untested,
+                },
+                Cluster {
+                    domain: Domain { start: 8, end: 10 },
+                    stage: Stage::ForIsolation(0),
+                },
+                // Newly damaged
+                Cluster {
+                    domain: Domain { start: 10, end: 12 },
+                    stage: Stage::Damaged,
+                },
+            ],
+            samples: vec![],
+        };
 
-    // Test for Cluster::subdivide()
+        let expected = vec![
+            (Domain { start: 2, end: 6 }, Stage::Untested, Stage::ForIsolation(0)),
+            (Domain { start: 10, end: 12 }, Stage::ForIsolation(0), Stage::Damaged),
+        ];
+        let recieved = old.diff(&new).unwrap();
 
-    // Test for MapFile::update()
+        assert!(
+            expected == recieved,
+            "Expected changes {:?}, got {:?}.",
+            expected, recieved
+        );
+        assert!(old.diff(&old) == Ok(vec![]));
+        assert!(old.diff(&MapFile::new(2048)).is_err())
+    }
 
     // Test for MapFile::get_stage()
     #[test]
@@ -309,23 +2600,31 @@ mod tests {
 
 
         let stages = vec![
+            Stage::Recovered,
             Stage::Damaged,
+            Stage::Recovered,
             Stage::ForIsolation(1),
             Stage::ForIsolation(0),
             Stage::Untested,
         ];
 
         mf.map = vec![];
+        let mut expected = Stage::Recovered;
 
         for stage in stages {
             mf.map.push(*Cluster::default().set_stage(stage));
 
+            // Recovered clusters never move the map back from Damaged.
+            if stage < expected {
+                expected = stage;
+            }
+
             mf_stage = mf.get_stage();
 
             assert!(
-                stage == mf_stage,
+                expected == mf_stage,
                 "Expected stage to be {:?}, determined {:?} instead.",
-                stage, mf_stage
+                expected, mf_stage
             )
         }
     }
@@ -333,18 +2632,19 @@ mod tests {
     // Test for MapFile::get_clusters()
     #[test]
     fn test_get_clusters() {
-        let mut mf = MapFile::default();
-
-        mf.map = vec![
-            *Cluster::default().set_stage(Stage::Damaged),
-            *Cluster::default().set_stage(Stage::ForIsolation(0)),
-            *Cluster::default().set_stage(Stage::ForIsolation(1)),
-            Cluster::default(),
-            Cluster::default(),
-            *Cluster::default().set_stage(Stage::ForIsolation(1)),
-            *Cluster::default().set_stage(Stage::ForIsolation(0)),
-            *Cluster::default().set_stage(Stage::Damaged),
-        ];
+        let mf = MapFile {
+            map: vec![
+                *Cluster::default().set_stage(Stage::Damaged),
+                *Cluster::default().set_stage(Stage::ForIsolation(0)),
+                *Cluster::default().set_stage(Stage::ForIsolation(1)),
+                Cluster::default(),
+                Cluster::default(),
+                *Cluster::default().set_stage(Stage::ForIsolation(1)),
+                *Cluster::default().set_stage(Stage::ForIsolation(0)),
+                *Cluster::default().set_stage(Stage::Damaged),
+            ],
+            ..Default::default()
+        };
 
         let stages = vec![
             Stage::Damaged,
@@ -368,10 +2668,270 @@ mod tests {
         }
     }
 
+    // Test for MapFile::validate() and MapFile::repair()
+    #[test]
+    fn test_validate() {
+        let cases = vec![
+            // Clean.
+            ("[
+                (domain: (start: 0, end: 4), stage: Recovered),
+                (domain: (start: 4, end: 6), stage: Damaged),
+                (domain: (start: 6, end: 10), stage: Untested),
+            ]", true),
+            // Gapped, as if killed mid-update.
+            ("[
+                (domain: (start: 0, end: 4), stage: Recovered),
+                (domain: (start: 6, end: 8), stage: Damaged),
+            ]", false),
+            // Overlapping.
+            ("[
+                (domain: (start: 0, end: 6), stage: Recovered),
+                (domain: (start: 4, end: 10), stage: Damaged),
+            ]", false),
+            // Out of order.
+            ("[
+                (domain: (start: 5, end: 10), stage: Recovered),
+                (domain: (start: 0, end: 5), stage: Untested),
+            ]", false),
+            // Past the domain.
+            ("[(domain: (start: 0, end: 12), stage: Untested)]", false),
+            ("[]", false),
+        ];
+
+        for (map, expected) in cases {
+            let mut mf: MapFile = ron::de::from_str(&format!(
+                "(sector_size: 512, domain: (start: 0, end: 10), map: {})",
+                map
+            ))
+            .unwrap();
+            let recieved = mf.validate();
+
+            assert!(
+                recieved.is_ok() == expected,
+                "Expected map {} to be valid: {}, got {:?}.",
+                map, expected, recieved
+            );
+
+            mf.repair();
+
+            assert!(
+                mf.validate().is_ok(),
+                "Expected map {} to be valid once repaired, got {:?}.",
+                map, mf
+            )
+        }
+
+        // Gaps are filled as Untested, keeping the rest as it was.
+        let mut mf: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 10),
+            map: [
+                (domain: (start: 6, end: 8), stage: Damaged),
+                (domain: (start: 0, end: 4), stage: Recovered),
+            ],
+        )").unwrap();
+        mf.repair();
+
+        let expected = vec![
+            Cluster { domain: Domain { start: 0, end: 4 }, stage: Stage::Recovered },
+            Cluster { domain: Domain { start: 4, end: 6 }, stage: Stage::Untested },
+            Cluster { domain: Domain { start: 6, end: 8 }, stage: Stage::Damaged },
+            Cluster { domain: Domain { start: 8, end: 10 }, stage: Stage::Untested },
+        ];
+
+        assert!(mf.map == expected, "Expected gaps filled as {:?}, got {:?}.", expected, mf.map)
+    }
+
+    // Test for MapFile::bad_blocks()
+    #[test]
+    fn test_bad_blocks() {
+        let mf: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 40),
+            map: [
+                (domain: (start: 0, end: 7), stage: Recovered),
+                (domain: (start: 7, end: 9), stage: Damaged),
+                (domain: (start: 9, end: 15), stage: Recovered),
+                (domain: (start: 15, end: 16), stage: Damaged),
+                (domain: (start: 16, end: 24), stage: Untested),
+                (domain: (start: 24, end: 25), stage: Damaged),
+                (domain: (start: 25, end: 40), stage: Recovered),
+            ],
+        )").unwrap();
+
+        let cases = vec![
+            // Sectors 7..9 straddle blocks 0 and 1, as 15 shares block 1.
+            (4096, vec![0, 1, 3]),
+            (512, vec![7, 8, 15, 24]),
+            // Blocks smaller than sectors.
+            (256, vec![14, 15, 16, 17, 30, 31, 48, 49]),
+            (1 << 20, vec![0]),
+        ];
+
+        for (block_size, expected) in cases {
+            let recieved = mf.bad_blocks(block_size);
+
+            assert!(
+                recieved == expected,
+                "Expected bad blocks {:?} of {} bytes, got {:?}.",
+                expected, block_size, recieved
+            )
+        }
+    }
+
+    // Test for MapFile::clusters_with_stage()
+    #[test]
+    fn test_clusters_with_stage() {
+        let mf: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 10),
+            map: [
+                (domain: (start: 0, end: 2), stage: Recovered),
+                (domain: (start: 2, end: 3), stage: ForIsolation(1)),
+                (domain: (start: 3, end: 5), stage: Untested),
+                (domain: (start: 5, end: 6), stage: ForIsolation(1)),
+                (domain: (start: 6, end: 9), stage: Recovered),
+                (domain: (start: 9, end: 10), stage: Damaged),
+            ],
+        )").unwrap();
+
+        let stages = vec![
+            Stage::Recovered,
+            Stage::ForIsolation(1),
+            Stage::ForIsolation(0),
+            Stage::Untested,
+            Stage::Damaged,
+        ];
+
+        for stage in stages {
+            let expected = mf.get_clusters(stage);
+
+            assert!(
+                mf.clusters_with_stage(stage).eq(expected.iter()),
+                "Expected {:?} clusters {:?}, got {:?}.",
+                stage, expected, mf.clusters_with_stage(stage).collect::<Vec<_>>()
+            )
+        }
+    }
+
+    // Test for MapFile::sector_counts() and MapFile::progress()
+    #[test]
+    fn test_sector_counts() {
+        let mf: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 100),
+            map: [
+                (domain: (start: 0, end: 40), stage: Recovered),
+                (domain: (start: 40, end: 50), stage: Damaged),
+                (domain: (start: 50, end: 60), stage: ForIsolation(1)),
+                (domain: (start: 60, end: 90), stage: Untested),
+                (domain: (start: 90, end: 100), stage: Verified),
+            ],
+        )").unwrap();
+
+        let expected = ZoneStats {
+            start: 0,
+            end: 100,
+            untested: 30,
+            for_isolation: 10,
+            damaged: 10,
+            recovered: 50,
+        };
+
+        assert!(
+            mf.sector_counts() == expected && mf.progress() == 0.5,
+            "Expected {:?} and half recovered, got {:?} and {}.",
+            expected, mf.sector_counts(), mf.progress()
+        );
+
+        // A gap, and an overlap, still tally to the domain's length.
+        let mf: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 10),
+            map: [
+                (domain: (start: 0, end: 4), stage: Recovered),
+                (domain: (start: 6, end: 10), stage: Damaged),
+                (domain: (start: 8, end: 10), stage: Recovered),
+            ],
+        )").unwrap();
+
+        let expected = ZoneStats {
+            start: 0,
+            end: 10,
+            untested: 2,
+            damaged: 2,
+            recovered: 6,
+            ..Default::default()
+        };
+
+        assert!(
+            mf.sector_counts() == expected,
+            "Expected {:?} for a gapped map, got {:?}.",
+            expected, mf.sector_counts()
+        );
+        assert!(MapFile::default().set_domain(Domain { start: 0, end: 0 }).progress() == 0.0)
+    }
+
+    // Test for MapFile::zone_stats()
+    #[test]
+    fn test_zone_stats() {
+        let mf = MapFile {
+            version: FORMAT_VERSION,
+            sector_size: 1,
+            domain: Domain { start: 0, end: 12 },
+            map: vec![
+                Cluster {
+                    domain: Domain { start: 0, end: 5 },
+                    stage: Stage::Untested,
+                },
+                Cluster {
+                    domain: Domain { start: 5, end: 6 },
+                    stage: Stage::ForIsolation(0),
+                },
+                Cluster {
+                    domain: Domain { start: 6, end: 10 },
+                    stage: Stage::Damaged,
+                },
+                Cluster {
+                    domain: Domain { start: 10, end: 11 },
+                    stage: Stage::Untested,
+                },
+                Cluster {
+                    domain: Domain { start: 11, end: 12 },
+                    stage: Stage::Recovered,
+                },
+            ],
+            samples: vec![],
+        };
+
+        let expected = vec![
+            ZoneStats {
+                start: 0, end: 4,
+                untested: 4, for_isolation: 0, damaged: 0, recovered: 0,
+            },
+            ZoneStats {
+                start: 4, end: 8,
+                untested: 1, for_isolation: 1, damaged: 2, recovered: 0,
+            },
+            ZoneStats {
+                start: 8, end: 12,
+                untested: 1, for_isolation: 0, damaged: 2, recovered: 1,
+            },
+        ];
+        let recieved = mf.zone_stats(3);
+
+        assert!(
+            expected == recieved,
+            "Expected zone stats {:?}, got {:?}.",
+            expected, recieved
+        )
+    }
+
     // Test for MapFile::defrag()
     #[test]
     fn test_defrag() {
         let mut mf = MapFile {
+            version: FORMAT_VERSION,
             sector_size: 1,
             domain: Domain { start: 0, end: 8 },
             map: vec![
@@ -408,6 +2968,7 @@ mod tests {
                     stage: Stage::Damaged,
                 },
             ],
+            samples: vec![],
         };
 
         let expected = vec![
@@ -433,7 +2994,7 @@ mod tests {
             },
         ];
 
-        mf.defrag();
+        mf.defrag().unwrap();
 
         let recieved = mf.map;
 
@@ -443,4 +3004,57 @@ mod tests {
             expected, recieved
         )
     }
+
+    // Test for MapFile::defrag() on maps which aren't a clean partition from 0
+    #[test]
+    fn test_defrag_irregular() {
+        let cases = vec![
+            // Empty.
+            ("(domain: (start: 0, end: 8), map: [])", Some("[]")),
+            // Starting past sector 0, such as when bounded.
+            (
+                "(domain: (start: 4, end: 12), map: [
+                    (domain: (start: 8, end: 12), stage: Recovered),
+                    (domain: (start: 4, end: 8), stage: Recovered),
+                ])",
+                Some("[(domain: (start: 4, end: 12), stage: Recovered)]"),
+            ),
+            // With a hole, which isn't merged across.
+            (
+                "(domain: (start: 0, end: 12), map: [
+                    (domain: (start: 0, end: 2), stage: Damaged),
+                    (domain: (start: 2, end: 4), stage: Damaged),
+                    (domain: (start: 6, end: 12), stage: Damaged),
+                ])",
+                Some("[
+                    (domain: (start: 0, end: 4), stage: Damaged),
+                    (domain: (start: 6, end: 12), stage: Damaged),
+                ]"),
+            ),
+            // Overlapping.
+            (
+                "(domain: (start: 0, end: 8), map: [
+                    (domain: (start: 0, end: 6), stage: Damaged),
+                    (domain: (start: 4, end: 8), stage: Damaged),
+                ])",
+                None,
+            ),
+        ];
+
+        for (map, expected) in cases {
+            let mut mf: MapFile = ron::de::from_str(
+                &map.replacen('(', "(sector_size: 512, ", 1)
+            ).unwrap();
+            let expected: Option<Vec<Cluster>> = expected
+                .map(|e| ron::de::from_str(e).unwrap());
+
+            let recieved = mf.defrag().ok().map(|mf| mf.map.to_owned());
+
+            assert!(
+                recieved == expected,
+                "Expected {:?} defragging {}, got {:?}.",
+                expected, map, recieved
+            )
+        }
+    }
 }
\ No newline at end of file
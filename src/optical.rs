@@ -0,0 +1,323 @@
+//! Optical drives read with READ CD, which gives C2 error pointers
+//! flagging which sectors of a read are bad, rather than failing it whole.
+
+use std::{fmt, io};
+
+#[cfg(target_os = "linux")]
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+};
+
+use crate::recovery::Pinpoint;
+
+
+/// User data per sector of a data CD, and so per READ CD sector.
+pub const CD_SECTOR_SIZE: usize = 2048;
+
+/// C2 error flags per sector, a bit per byte of the 2352 byte raw sector.
+const C2_LEN: usize = 294;
+
+const INQUIRY: u8 = 0x12;
+const READ_CD: u8 = 0xbe;
+
+/// Peripheral device type of CD and DVD drives, as INQUIRY reports.
+const PERIPHERAL_OPTICAL: u8 = 0x05;
+
+/// SG_IO from scsi/sg.h, which libc doesn't export.
+#[cfg(target_os = "linux")]
+const SG_IO: libc::Ioctl = 0x2285;
+#[cfg(target_os = "linux")]
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+#[cfg(target_os = "linux")]
+const SG_INFO_OK_MASK: libc::c_uint = 0x1;
+
+/// Time for a drive to give up on a command itself, in milliseconds.
+#[cfg(target_os = "linux")]
+const SG_TIMEOUT: libc::c_uint = 30_000;
+
+
+/// Something SCSI commands can be sent to, reading their data into data.
+pub trait ScsiDevice: fmt::Debug {
+    fn execute(&mut self, cdb: &[u8], data: &mut [u8]) -> io::Result<()>;
+}
+
+/// Whether device is a CD or DVD drive.
+pub fn is_optical<D: ScsiDevice>(device: &mut D) -> io::Result<bool> {
+    let mut data = [0u8; 36];
+
+    device.execute(&[INQUIRY, 0, 0, 0, data.len() as u8, 0], &mut data)?;
+    Ok(data[0] & 0x1f == PERIPHERAL_OPTICAL)
+}
+
+/// READ CD of sectors from lba, for user data then C2 error flags.
+fn read_cd_cdb(lba: u32, sectors: u32) -> [u8; 12] {
+    let lba = lba.to_be_bytes();
+    let sectors = sectors.to_be_bytes();
+
+    [
+        READ_CD, 0,
+        lba[0], lba[1], lba[2], lba[3],
+        sectors[1], sectors[2], sectors[3],
+        // User data, and C2 error flags after it.
+        0x10 | 0x02,
+        0, 0,
+    ]
+}
+
+
+/// Reads from an optical drive, flagging sectors with any C2 error.
+#[derive(Debug)]
+pub struct ReadCd<D: ScsiDevice> {
+    device: D,
+}
+
+impl<D: ScsiDevice> ReadCd<D> {
+    pub fn new(device: D) -> Self {
+        ReadCd { device }
+    }
+}
+
+impl<D: ScsiDevice> Pinpoint for ReadCd<D> {
+    fn read_flagged(
+        &mut self,
+        buf: &mut [u8],
+        offset: u64,
+        sector_size: usize,
+    ) -> io::Result<Vec<usize>> {
+        if sector_size != CD_SECTOR_SIZE
+            || !offset.is_multiple_of(CD_SECTOR_SIZE as u64)
+            || !buf.len().is_multiple_of(CD_SECTOR_SIZE)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("READ CD only reads whole {} byte sectors", CD_SECTOR_SIZE),
+            ));
+        }
+
+        let sectors = buf.len() / CD_SECTOR_SIZE;
+        let lba = u32::try_from(offset / CD_SECTOR_SIZE as u64)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Offset is past any disc"))?;
+        let mut data = vec![0u8; sectors * (CD_SECTOR_SIZE + C2_LEN)];
+
+        self.device.execute(&read_cd_cdb(lba, sectors as u32), &mut data)?;
+
+        let mut flagged = vec![];
+
+        for (i, (read, out)) in data
+            .chunks(CD_SECTOR_SIZE + C2_LEN)
+            .zip(buf.chunks_mut(CD_SECTOR_SIZE))
+            .enumerate()
+        {
+            let (user, c2) = read.split_at(CD_SECTOR_SIZE);
+            out.copy_from_slice(user);
+
+            if c2.iter().any(|&b| b != 0) {
+                flagged.push(i);
+            }
+        }
+
+        Ok(flagged)
+    }
+}
+
+
+/// sg_io_hdr from scsi/sg.h, which libc doesn't export.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: libc::c_uchar,
+    mx_sb_len: libc::c_uchar,
+    iovec_count: libc::c_ushort,
+    dxfer_len: libc::c_uint,
+    dxferp: *mut libc::c_void,
+    cmdp: *const libc::c_uchar,
+    sbp: *mut libc::c_uchar,
+    timeout: libc::c_uint,
+    flags: libc::c_uint,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: libc::c_uchar,
+    masked_status: libc::c_uchar,
+    msg_status: libc::c_uchar,
+    sb_len_wr: libc::c_uchar,
+    host_status: libc::c_ushort,
+    driver_status: libc::c_ushort,
+    resid: libc::c_int,
+    duration: libc::c_uint,
+    info: libc::c_uint,
+}
+
+/// A device sent SCSI commands through the SG_IO ioctl,
+/// such as /dev/sr0 or /dev/sg0.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct SgDevice(File);
+
+#[cfg(target_os = "linux")]
+impl SgDevice {
+    /// Open path, without waiting for a disc to be loaded.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map(SgDevice)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ScsiDevice for SgDevice {
+    fn execute(&mut self, cdb: &[u8], data: &mut [u8]) -> io::Result<()> {
+        let mut sense = [0u8; 32];
+        let mut hdr = SgIoHdr {
+            interface_id: 'S' as libc::c_int,
+            dxfer_direction: SG_DXFER_FROM_DEV,
+            cmd_len: cdb.len() as libc::c_uchar,
+            mx_sb_len: sense.len() as libc::c_uchar,
+            iovec_count: 0,
+            dxfer_len: data.len() as libc::c_uint,
+            dxferp: data.as_mut_ptr().cast(),
+            cmdp: cdb.as_ptr(),
+            sbp: sense.as_mut_ptr(),
+            timeout: SG_TIMEOUT,
+            flags: 0,
+            pack_id: 0,
+            usr_ptr: std::ptr::null_mut(),
+            status: 0,
+            masked_status: 0,
+            msg_status: 0,
+            sb_len_wr: 0,
+            host_status: 0,
+            driver_status: 0,
+            resid: 0,
+            duration: 0,
+            info: 0,
+        };
+
+        if unsafe { libc::ioctl(self.0.as_raw_fd(), SG_IO, &mut hdr) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if hdr.info & SG_INFO_OK_MASK != 0 {
+            return Err(io::Error::other(format!(
+                "SCSI command {:#04x} failed, status {:#04x}, host {:#06x}, driver {:#06x}",
+                cdb[0], hdr.status, hdr.host_status, hdr.driver_status,
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Drive answering INQUIRY as device_type, and READ CD from sectors,
+    /// with C2 errors over those in bad.
+    #[derive(Debug)]
+    struct MockDrive {
+        device_type: u8,
+        sectors: Vec<u8>,
+        bad: Vec<usize>,
+        cdbs: Vec<Vec<u8>>,
+    }
+
+    impl ScsiDevice for MockDrive {
+        fn execute(&mut self, cdb: &[u8], data: &mut [u8]) -> io::Result<()> {
+            self.cdbs.push(cdb.to_vec());
+
+            match cdb[0] {
+                INQUIRY => data[0] = self.device_type,
+                READ_CD => {
+                    let lba = u32::from_be_bytes([cdb[2], cdb[3], cdb[4], cdb[5]]) as usize;
+                    let count = u32::from_be_bytes([0, cdb[6], cdb[7], cdb[8]]) as usize;
+
+                    let reads = data.chunks_mut(CD_SECTOR_SIZE + C2_LEN).take(count);
+
+                    for (sector, out) in (lba..).zip(reads) {
+                        let (user, c2) = out.split_at_mut(CD_SECTOR_SIZE);
+
+                        user.copy_from_slice(
+                            &self.sectors[sector * CD_SECTOR_SIZE..(sector + 1) * CD_SECTOR_SIZE],
+                        );
+                        c2.fill(0);
+
+                        if self.bad.contains(&sector) {
+                            c2[C2_LEN / 2] = 0x80;
+                        }
+                    }
+                },
+                op => return Err(io::Error::other(format!("Unexpected command {:#04x}", op))),
+            }
+
+            Ok(())
+        }
+    }
+
+    fn drive(device_type: u8, bad: &[usize]) -> MockDrive {
+        MockDrive {
+            device_type,
+            sectors: (0..8 * CD_SECTOR_SIZE).map(|i| (i % 251) as u8).collect(),
+            bad: bad.to_vec(),
+            cdbs: vec![],
+        }
+    }
+
+    // Test for is_optical()
+    #[test]
+    fn test_is_optical() {
+        let cases = vec![
+            (PERIPHERAL_OPTICAL, true),
+            // Qualifier bits set, still a CD drive.
+            (0x20 | PERIPHERAL_OPTICAL, true),
+            // Disk.
+            (0x00, false),
+        ];
+
+        for (device_type, expected) in cases {
+            let recieved = is_optical(&mut drive(device_type, &[])).unwrap();
+
+            assert!(
+                recieved == expected,
+                "Expected {} for device type {:#04x}, got {}.",
+                expected, device_type, recieved
+            )
+        }
+    }
+
+    // Test for ReadCd::read_flagged()
+    #[test]
+    fn test_read_flagged() {
+        let mut read_cd = ReadCd::new(drive(PERIPHERAL_OPTICAL, &[3, 5]));
+        let mut buf = vec![0u8; 4 * CD_SECTOR_SIZE];
+
+        let flagged = read_cd
+            .read_flagged(&mut buf, 2 * CD_SECTOR_SIZE as u64, CD_SECTOR_SIZE)
+            .unwrap();
+
+        assert!(
+            flagged == vec![1, 3],
+            "Expected sectors 3 and 5 flagged, at 1 and 3 of the read, got {:?}.",
+            flagged
+        );
+        assert!(
+            buf == read_cd.device.sectors[2 * CD_SECTOR_SIZE..6 * CD_SECTOR_SIZE],
+            "Expected the user data of every sector read, flagged or not."
+        );
+        assert!(
+            read_cd.device.cdbs == vec![read_cd_cdb(2, 4).to_vec()],
+            "Expected a single READ CD of 4 sectors at 2, got {:02x?}.",
+            read_cd.device.cdbs
+        );
+        assert!(
+            read_cd.read_flagged(&mut buf, 0, 512).is_err(),
+            "Expected sectors other than 2048 bytes refused."
+        )
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+};
+
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::OpenOptionsExt;
+#[cfg(target_os = "macos")]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::fs::{FileExt, FileTypeExt};
+#[cfg(windows)]
+use std::os::windows::fs::FileExt;
+
+
+/// Open path with options, bypassing the page cache where the platform
+/// allows: O_DIRECT on Linux, F_NOCACHE on macOS, and buffered elsewhere.
+/// Uncached reads and writes need buffers aligned as AlignedBuf provides.
+pub fn open_uncached(options: &mut OpenOptions, path: &Path) -> io::Result<File> {
+    #[cfg(target_os = "linux")]
+    options.custom_flags(libc::O_DIRECT);
+
+    let file = options.open(path)?;
+
+    #[cfg(target_os = "macos")]
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
+/// Read buf.len() bytes of file at offset, or fail.
+#[cfg(unix)]
+pub fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    file.read_exact_at(buf, offset)
+}
+
+/// Read buf.len() bytes of file at offset, or fail.
+/// Moves the file's cursor, which nothing else relies on.
+#[cfg(windows)]
+pub fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            },
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Write all of buf to file at offset, or fail.
+#[cfg(unix)]
+pub fn write_all_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    file.write_all_at(buf, offset)
+}
+
+/// Write all of buf to file at offset, or fail.
+/// Moves the file's cursor, which nothing else relies on.
+#[cfg(windows)]
+pub fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match file.seek_write(buf, offset) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(n) => {
+                buf = &buf[n..];
+                offset += n as u64;
+            },
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether file is a block device, which only Unix has.
+#[cfg(unix)]
+pub fn is_block_device(file: &File) -> io::Result<bool> {
+    Ok(file.metadata()?.file_type().is_block_device())
+}
+
+#[cfg(not(unix))]
+pub fn is_block_device(_file: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::AlignedBuf, testing::scratch_path};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    // Test for open_uncached()
+    #[test]
+    fn test_open_uncached() {
+        let path = scratch_path("uncached");
+
+        let mut file = open_uncached(
+            OpenOptions::new().read(true).write(true).create(true).truncate(true),
+            &path,
+        )
+        .unwrap();
+
+        let mut data = AlignedBuf::new(8192, 4096);
+        data.fill(0xaa);
+        file.write_all(&data).unwrap();
+
+        let mut read = AlignedBuf::new(4096, 4096);
+        file.seek(SeekFrom::Start(4096)).unwrap();
+        file.read_exact(&mut read).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(read.iter().all(|&b| b == 0xaa), "Expected data written to read back.")
+    }
+}
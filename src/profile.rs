@@ -0,0 +1,128 @@
+use clap::{parser::ValueSource, ArgMatches};
+use ron::extensions::Extensions;
+use serde::Deserialize;
+use std::{fs, path::Path, str::FromStr};
+
+use crate::{error::KramerError, Args};
+
+
+/// Recovery options saved for reuse, such as for a model of drive.
+/// Options given on the command line win over the profile's,
+/// which win over the defaults.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub sector_size: Option<u16>,
+    pub cluster_length: Option<u16>,
+    pub brute_passes: Option<usize>,
+    pub max_isolation_level: Option<u8>,
+    /// In milliseconds, as --read-timeout.
+    pub read_timeout: Option<u64>,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    /// Parse a profile written in RON, such as (sector_size: 512).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let profile: Profile = ron::Options::default()
+            .with_default_extension(Extensions::IMPLICIT_SOME)
+            .from_str(s)
+            .map_err(|err| err.to_string())?;
+
+        if profile.max_isolation_level == Some(0) {
+            return Err(String::from("max_isolation_level must be at least 1"));
+        }
+
+        Ok(profile)
+    }
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Self, KramerError> {
+        fs::read_to_string(path)
+            .map_err(|err| KramerError::Profile(err.to_string()))?
+            .parse()
+            .map_err(KramerError::Profile)
+    }
+
+    /// Set options of config which weren't given on the command line,
+    /// as parsed into matches.
+    pub fn apply(&self, config: &mut Args, matches: &ArgMatches) {
+        let is_unset = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let Some(sector_size) = self.sector_size.filter(|_| is_unset("sector_size")) {
+            config.sector_size = sector_size;
+        }
+
+        if let Some(cluster_length) = self.cluster_length.filter(|_| is_unset("cluster_length")) {
+            config.cluster_length = cluster_length;
+        }
+
+        if let Some(brute_passes) = self.brute_passes.filter(|_| is_unset("brute_passes")) {
+            config.brute_passes = brute_passes;
+        }
+
+        if let Some(level) = self.max_isolation_level.filter(|_| is_unset("max_isolation_level")) {
+            config.max_isolation_level = level;
+        }
+
+        if let Some(read_timeout) = self.read_timeout.filter(|_| is_unset("read_timeout")) {
+            config.read_timeout = read_timeout;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{CommandFactory, FromArgMatches};
+
+    // Test for Profile::apply()
+    #[test]
+    fn test_apply() {
+        let profile: Profile = "(
+            sector_size: 512,
+            cluster_length: 64,
+            brute_passes: 5,
+        )".parse().unwrap();
+
+        let matches = Args::command().get_matches_from(["kramer", "-i", "in", "-c", "16"]);
+        let mut config = Args::from_arg_matches(&matches).unwrap();
+        profile.apply(&mut config, &matches);
+
+        // Given on the command line.
+        assert!(config.cluster_length == 16, "Expected -c to win over the profile.");
+        // Only in the profile.
+        assert!(
+            config.sector_size == 512 && config.brute_passes == 5,
+            "Expected the profile to win over defaults."
+        );
+        // In neither.
+        assert!(config.read_timeout == 0 && config.max_isolation_level == 4)
+    }
+
+    // Test for Profile::from_str()
+    #[test]
+    fn test_from_str() {
+        let cases = vec![
+            ("()", true),
+            ("(read_timeout: 500, max_isolation_level: 2)", true),
+            ("(read_timeout: Some(500))", true),
+            ("(cluster_lenght: 64)", false),
+            ("(max_isolation_level: 0)", false),
+            ("(sector_size: 70000)", false),
+        ];
+
+        for (s, expected) in cases {
+            let recieved = s.parse::<Profile>();
+
+            assert!(
+                recieved.is_ok() == expected,
+                "Expected profile {} to parse: {}, got {:?}.",
+                s, expected, recieved
+            )
+        }
+    }
+}
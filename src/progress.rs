@@ -0,0 +1,269 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::mapping::Stage;
+
+
+/// Least time between printed updates, so fast reads don't flood the terminal.
+const PRINT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Length of the progress line left on stderr, or 0 if none is.
+static LINE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Recovery progress, as reported after each cluster.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Progress {
+    /// Sectors recovered, of total in the map.
+    pub recovered: usize,
+    pub total: usize,
+    pub untested: usize,
+    /// Stage being worked on.
+    pub stage: Stage,
+    /// Bytes read per second over the recent window, once anything is read.
+    pub bytes_per_sec: Option<f64>,
+    /// Time left to copy the untested sectors, then isolate the rest,
+    /// each at its own rate, as phased_eta estimates.
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = match self.total {
+            0 => 0.0,
+            n => self.recovered as f64 * 100.0 / n as f64,
+        };
+
+        write!(
+            f,
+            "Recovered {} of {} sectors ({:.2}%), {:?}",
+            self.recovered, self.total, percent, self.stage,
+        )?;
+
+        if let Some(rate) = self.bytes_per_sec {
+            write!(f, ", {:.2} MB/s", rate / 1_000_000.0)?;
+        }
+
+        if let Some(eta) = self.eta {
+            let secs = eta.as_secs();
+
+            write!(f, ", ETA {}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Receives progress during a run.
+pub trait Report: fmt::Debug {
+    fn report(&mut self, progress: &Progress);
+
+    /// Called once the run ends.
+    fn finish(&mut self) {}
+}
+
+/// Reports nothing.
+#[derive(Debug, Default)]
+pub struct NoReport;
+
+impl Report for NoReport {
+    fn report(&mut self, _progress: &Progress) {}
+}
+
+/// Reports on a single line of stderr, rewritten in place.
+#[derive(Debug, Default)]
+pub struct StderrReport {
+    last_print: Option<Instant>,
+    /// Latest progress not yet printed.
+    pending: Option<Progress>,
+}
+
+impl StderrReport {
+    fn print(&mut self, progress: &Progress) {
+        let line = progress.to_string();
+
+        // Trailing spaces clear what's left of a longer previous line.
+        eprint!("\r{}    ", line);
+        let _ = io::stderr().flush();
+        LINE_LEN.store(line.len() + 4, Ordering::Relaxed);
+
+        self.last_print = Some(Instant::now());
+        self.pending = None;
+    }
+}
+
+impl Report for StderrReport {
+    fn report(&mut self, progress: &Progress) {
+        if self.last_print.is_some_and(|t| t.elapsed() < PRINT_INTERVAL) {
+            self.pending = Some(*progress);
+        } else {
+            self.print(progress);
+        }
+    }
+
+    /// Print the final progress, and end the line.
+    fn finish(&mut self) {
+        if let Some(progress) = self.pending {
+            self.print(&progress);
+        }
+
+        if LINE_LEN.swap(0, Ordering::Relaxed) > 0 {
+            eprintln!();
+        }
+    }
+}
+
+
+/// Blank out the progress line, if one is on stderr,
+/// so other output starts on a line of its own.
+pub fn clear_line() {
+    let len = LINE_LEN.swap(0, Ordering::Relaxed);
+
+    if len > 0 {
+        eprint!("\r{}\r", " ".repeat(len));
+    }
+}
+
+
+/// Time left to copy untested bytes at copy_rate, then isolate isolating
+/// bytes at isolate_rate, each in bytes per second. Isolation is far slower,
+/// so is estimated at copy_rate only until its own rate is known.
+/// None while no rate is known for a phase with bytes left.
+pub fn phased_eta(
+    untested: u64,
+    copy_rate: Option<f64>,
+    isolating: u64,
+    isolate_rate: Option<f64>,
+) -> Option<Duration> {
+    let secs = |bytes: u64, rate: Option<f64>| match bytes {
+        0 => Some(0.0),
+        _ => rate.filter(|&rate| rate > 0.0).map(|rate| bytes as f64 / rate),
+    };
+
+    let copy = secs(untested, copy_rate)?;
+    let isolate = secs(isolating, isolate_rate.or(copy_rate))?;
+
+    Some(Duration::from_secs_f64(copy + isolate))
+}
+
+
+/// Bytes read over a sliding window of time.
+#[derive(Debug)]
+pub struct Throughput {
+    window: Duration,
+    started: Instant,
+    reads: VecDeque<(Instant, u64)>,
+}
+
+impl Throughput {
+    pub fn new(window: Duration) -> Self {
+        Throughput {
+            window,
+            started: Instant::now(),
+            reads: VecDeque::new(),
+        }
+    }
+
+    /// Count bytes as read now.
+    pub fn record(&mut self, bytes: u64) {
+        self.record_at(bytes, Instant::now());
+    }
+
+    /// Bytes per second over the window, or since starting if sooner.
+    /// None until anything is read.
+    pub fn bytes_per_sec(&mut self) -> Option<f64> {
+        self.bytes_per_sec_at(Instant::now())
+    }
+
+    fn record_at(&mut self, bytes: u64, now: Instant) {
+        self.reads.push_back((now, bytes));
+        self.expire(now);
+    }
+
+    fn bytes_per_sec_at(&mut self, now: Instant) -> Option<f64> {
+        self.expire(now);
+
+        if self.reads.is_empty() {
+            return None;
+        }
+
+        let since = now.checked_sub(self.window)
+            .map_or(self.started, |t| t.max(self.started));
+        let secs = now.duration_since(since).as_secs_f64();
+        let bytes: u64 = self.reads.iter().map(|(_, b)| b).sum();
+
+        match secs > 0.0 {
+            true => Some(bytes as f64 / secs),
+            false => None,
+        }
+    }
+
+    /// Forget reads older than the window.
+    fn expire(&mut self, now: Instant) {
+        while self.reads.front().is_some_and(|(t, _)| now.duration_since(*t) > self.window) {
+            self.reads.pop_front();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for phased_eta()
+    #[test]
+    fn test_phased_eta() {
+        let cases = vec![
+            // 100 MB copying at 10 MB/s, then 1 MB isolating at 10 kB/s.
+            ((100_000_000, Some(10_000_000.0), 1_000_000, Some(10_000.0)), Some(110.0)),
+            // Isolating at the copy rate, until its own rate is known.
+            ((100_000_000, Some(10_000_000.0), 1_000_000, None), Some(10.1)),
+            ((0, None, 1_000_000, Some(10_000.0)), Some(100.0)),
+            ((0, Some(10_000_000.0), 0, None), Some(0.0)),
+            ((100_000_000, None, 0, Some(10_000.0)), None),
+            ((0, None, 1_000_000, None), None),
+        ];
+
+        for ((untested, copy_rate, isolating, isolate_rate), expected) in cases {
+            let recieved = phased_eta(untested, copy_rate, isolating, isolate_rate);
+
+            assert!(
+                recieved == expected.map(Duration::from_secs_f64),
+                "Expected {:?}s for {} bytes at {:?} then {} at {:?}, got {:?}.",
+                expected, untested, copy_rate, isolating, isolate_rate, recieved
+            )
+        }
+    }
+
+    // Test for Throughput::bytes_per_sec()
+    #[test]
+    fn test_throughput() {
+        let mut throughput = Throughput::new(Duration::from_secs(10));
+        let start = throughput.started;
+
+        assert!(throughput.bytes_per_sec_at(start).is_none());
+
+        throughput.record_at(1_000_000, start + Duration::from_secs(1));
+        throughput.record_at(1_000_000, start + Duration::from_secs(2));
+
+        assert!(
+            throughput.bytes_per_sec_at(start + Duration::from_secs(2)) == Some(1_000_000.0),
+            "Expected 1 MB/s over the first 2 seconds."
+        );
+
+        // The first read has left the window.
+        throughput.record_at(4_000_000, start + Duration::from_secs(12));
+
+        assert!(
+            throughput.bytes_per_sec_at(start + Duration::from_secs(12)) == Some(500_000.0),
+            "Expected 5 MB over the last 10 seconds."
+        )
+    }
+}
@@ -1,110 +1,4154 @@
+use log::{debug, info, trace, warn};
+use serde::Serialize;
 use std::{
-    io::{BufReader, BufWriter},
+    cmp::Reverse,
+    fmt,
+    io::{self, Read, Seek, SeekFrom, Write},
     fs::File,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
     Args,
-    mapping::{Cluster, MapFile, Stage},
+    buffer::AlignedBuf,
+    cache::drop_cached,
+    control::{Command, Control, Response},
+    digest::{digest_ranges, Algorithm},
+    discard::discard_unrecovered,
+    error::KramerError,
+    mapping::{Cluster, Domain, MapFile, MapFormat, SourceSample, Stage, ZoneStats},
+    platform,
+    progress::{phased_eta, NoReport, Progress, Report, Throughput},
+    space::SpaceCheck,
+    throttle::Throttle,
+    timeout::{ReadAt, TimeoutReader},
 };
 
 
+/// Span of recent reads to compute throughput over.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Span of recent clusters to compute each phase's rate over, for the ETA.
+/// Longer than THROUGHPUT_WINDOW, as isolating reads can each take seconds.
+const PHASE_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Clusters per stretch of untested sectors copied before isolating
+/// its failed reads, with interleave set.
+const INTERLEAVE_CLUSTERS: usize = 16;
+
+/// Reads in a row to succeed before doubling their length, with gentle_start.
+const GENTLE_STREAK: usize = 4;
+
+/// Time to wait for a command while paused, before checking for SIGINT.
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+
+/// Error for a read given a sector of only zeros, with zero_is_suspect set.
+fn suspect_zeros() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Read a sector of only zeros, which is suspect")
+}
+
+/// Write all of buf to output at offset, trying once more if that fails.
+/// Writes being positional, the retry overwrites whatever part of the
+/// failed write landed, so a transient failure leaves nothing behind.
+fn write_all_retrying<W: Output>(output: &mut W, buf: &[u8], offset: u64) -> io::Result<()> {
+    output.write_all_at(buf, offset).or_else(|err| {
+        warn!("Failed to write {} bytes at offset {}, retrying: {}", buf.len(), offset, err);
+        output.write_all_at(buf, offset)
+    })
+}
+
+
+/// Where data is recovered from.
+/// Files are read from at offsets directly, without seeking.
+pub trait Input: Read + Seek {
+    /// Read buf.len() bytes at offset, or fail.
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)
+    }
+}
+
+impl Input for File {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        platform::read_exact_at(self, buf, offset)
+    }
+}
+
+impl<T: ReadAt> Input for TimeoutReader<T> {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        ReadAt::read_exact_at(self, buf, offset)
+    }
+}
+
+impl Input for io::Cursor<Vec<u8>> {}
+
+
+/// Reads which, rather than failing whole, flag which of their sectors
+/// are bad, such as optical drives' READ CD with C2 error pointers.
+pub trait Pinpoint: fmt::Debug {
+    /// Read buf.len() bytes at offset, returning the indexes in buf of
+    /// the sectors of sector_size flagged bad.
+    fn read_flagged(
+        &mut self,
+        buf: &mut [u8],
+        offset: u64,
+        sector_size: usize,
+    ) -> io::Result<Vec<usize>>;
+}
+
+
+/// Where recovered data is written, and read back from to verify.
+/// Devices may also support syncing and discarding, which are otherwise no-ops.
+/// Files are read and written at offsets directly, without seeking.
+pub trait Output: Read + Write + Seek {
+    /// Read buf.len() bytes at offset, or fail.
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(buf)
+    }
+
+    /// Write all of buf at offset, or fail.
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(buf)
+    }
+
+    /// Commit written data to storage.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Discard (TRIM) regions of map at any of stages.
+    fn discard_unrecovered(&mut self, _map: &MapFile, _stages: &[Stage]) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Drop written data from any cache, as it won't be read again soon.
+    fn drop_cache(&mut self, _offset: u64, _len: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Leave a hole of len bytes at offset, which reads back as zeros.
+    /// Unsupported by default, in which case zeros are written instead.
+    fn punch_hole(&mut self, _offset: u64, _len: u64) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+impl Output for File {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        platform::read_exact_at(self, buf, offset)
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        platform::write_all_at(self, buf, offset)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_data()
+    }
+
+    /// Discard only if a block device.
+    fn discard_unrecovered(&mut self, map: &MapFile, stages: &[Stage]) -> io::Result<()> {
+        if platform::is_block_device(self)? {
+            discard_unrecovered(self, map, stages)?;
+        }
+
+        Ok(())
+    }
+
+    fn drop_cache(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        drop_cached(self, offset, len)
+    }
+
+    /// Deallocate the range, keeping the file's length.
+    #[cfg(target_os = "linux")]
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+
+        match unsafe {
+            libc::fallocate(self.as_raw_fd(), mode, offset as libc::off_t, len as libc::off_t)
+        } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Output for io::Cursor<Vec<u8>> {}
+
+/// Writes go nowhere, and nothing can be read back.
+impl Output for io::Empty {}
+
+
+/// Totals over the whole map, for reporting how a run went.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct RecoveryStats {
+    pub sectors: usize,
+    pub recovered: usize,
+    pub untested: usize,
+    pub for_isolation: usize,
+    pub damaged: usize,
+    pub total_bytes: u64,
+    pub recovered_bytes: u64,
+    pub damaged_bytes: u64,
+    /// Share of sectors recovered, 0 to 100.
+    pub recovered_percent: f64,
+}
+
+impl fmt::Display for RecoveryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Recovered: {} of {} bytes ({:.2}%), {} of {} sectors",
+            self.recovered_bytes, self.total_bytes, self.recovered_percent,
+            self.recovered, self.sectors,
+        )?;
+        writeln!(f, "Damaged:   {} bytes, {} sectors", self.damaged_bytes, self.damaged)?;
+        write!(
+            f,
+            "Remaining: {} sectors untested, {} for isolation",
+            self.untested, self.for_isolation,
+        )
+    }
+}
+
+
+/// Why a run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum StopReason {
+    /// Everything within bounds was recovered.
+    Complete,
+    /// Nothing is left but damaged sectors, isolated as far as they go.
+    Exhausted,
+    /// Stopped early, such as on SIGINT.
+    Interrupted,
+    /// The output filled up, or hadn't the space left for what's to recover.
+    OutputFull,
+    /// Writing to or reading back from the output failed.
+    OutputFailed,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::Complete => "recovery complete",
+            StopReason::Exhausted => "cannot recover further, only damaged sectors are left",
+            StopReason::Interrupted => "interrupted",
+            StopReason::OutputFull => "output is full",
+            StopReason::OutputFailed => "failed to write the output",
+        })
+    }
+}
+
+
+/// Reads of one length over a run, and how many of them failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ReadSize {
+    /// Sectors per read.
+    pub sectors: usize,
+    pub reads: u64,
+    pub failed: u64,
+}
+
+/// Reads over a run, bucketed by length, shortest first.
+/// Shows, for example, where long reads fail but single sectors read.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadHistogram {
+    sizes: Vec<ReadSize>,
+}
+
+impl ReadHistogram {
+    /// Count a read of sectors, failed unless is_read.
+    pub fn record(&mut self, sectors: usize, is_read: bool) -> &mut Self {
+        let i = match self.sizes.binary_search_by_key(&sectors, |s| s.sectors) {
+            Ok(i) => i,
+            Err(i) => {
+                self.sizes.insert(i, ReadSize { sectors, ..Default::default() });
+                i
+            },
+        };
+
+        self.sizes[i].reads += 1;
+
+        if !is_read {
+            self.sizes[i].failed += 1;
+        }
+
+        self
+    }
+
+    pub fn sizes(&self) -> &[ReadSize] {
+        &self.sizes
+    }
+}
+
+
+/// Length of reads copying untested clusters, which with gentle_start
+/// starts at a sector, doubling each GENTLE_STREAK reads in a row that
+/// succeed until it's max.
+#[derive(Debug)]
+struct Ramp {
+    len: usize,
+    max: usize,
+    streak: usize,
+}
+
+impl Ramp {
+    fn new(len: usize, max: usize) -> Self {
+        Ramp { len: len.min(max), max, streak: 0 }
+    }
+
+    fn record(&mut self, is_read: bool) {
+        if !is_read {
+            self.streak = 0;
+            return;
+        }
+
+        self.streak += 1;
+
+        if self.streak >= GENTLE_STREAK {
+            self.len = self.len.saturating_mul(2).min(self.max);
+            self.streak = 0;
+        }
+    }
+
+    /// Whether reads are as long as they get.
+    fn is_done(&self) -> bool {
+        self.len >= self.max
+    }
+}
+
+
 #[derive(Debug)]
-pub struct Recover {
+pub struct Recover<R: Input = File, W: Output = File> {
+    buf: AlignedBuf,
     buf_capacity: usize,
     config: Args,
-    input: BufReader<File>,
-    output: BufWriter<File>,
+    /// Bounds of recovery, within the map's domain.
+    domain: Domain,
+    /// Ranges within domain to recover, all of it unless set otherwise.
+    ranges: Vec<Domain>,
+    input: R,
+    output: W,
     map: MapFile,
+    /// Sectors per stage over the whole map, kept up to date as clusters
+    /// are mapped, so reporting progress never counts them again.
+    counts: ZoneStats,
+    /// Stage of the pass being run.
     stage: Stage,
+    /// Where to save the map periodically, if anywhere, and in what format.
+    map_path: Option<PathBuf>,
+    map_format: MapFormat,
+    last_save: Instant,
+    /// Free space to check every save_interval seconds during a run, if any.
+    space_check: Option<SpaceCheck>,
+    last_space_check: Instant,
+    /// Bytes of output claimed by writes this run, and bytes copied by them.
+    /// Less is claimed than copied where sparse writes leave holes.
+    bytes_written: u64,
+    bytes_copied: u64,
+    /// Data recovered but not yet written, for output at pending_offset,
+    /// gathered into blocks of write_block_size.
+    pending: Vec<u8>,
+    pending_offset: u64,
+    /// Set to stop recovery between clusters, such as on SIGINT.
+    stop: Option<&'static AtomicBool>,
+    /// Set by a stop command, stopping recovery as stop does.
+    is_stop_commanded: bool,
+    /// Commands to apply between clusters, and where to answer them.
+    control: Option<Control>,
+    /// Why the last run ended, once one has.
+    stop_reason: Option<StopReason>,
+    reporter: Box<dyn Report>,
+    throughput: Throughput,
+    /// Bytes copied or isolated, read or not, for each phase's rate.
+    /// Isolation's starts with its first read, long after copying's.
+    copy_rate: Throughput,
+    isolate_rate: Option<Throughput>,
+    reads: ReadHistogram,
+    /// Paces reads to --rate-limit, if set.
+    throttle: Option<Throttle>,
+    /// Inputs to read untested clusters from in parallel, one per thread.
+    workers: Vec<Box<dyn ReadAt>>,
+    /// Reads failed isolating clusters again, flagging their bad sectors.
+    pinpoint: Option<Box<dyn Pinpoint>>,
 }
 
-impl Recover {
+impl<R: Input, W: Output> Recover<R, W> {
+    /// Fails with BadMap unless map's clusters cover its domain exactly,
+    /// as sectors in no cluster would never be read.
+    /// Repair such maps first if they should be recovered from anyway.
     pub fn new(
         config: Args,
-        input: File,
-        output: File,
+        input: R,
+        output: W,
         map: MapFile,
-    ) -> Self {
-        let stage = map.get_stage();
+    ) -> Result<Self, KramerError> {
+        map.validate()?;
+
+        // Unbounded ends span the map.
+        let domain = Domain {
+            start: config.start.unwrap_or(map.domain.start),
+            end: config.end.unwrap_or(map.domain.end),
+        };
+
+        let counts = map.sector_counts();
+        let stage = map.crop(domain).get_stage();
 
         // Temporarily make buffer length one sector.
         let buf_capacity = config.sector_size as usize;
+        let throttle = Throttle::new(config.rate_limit);
         let mut r = Recover {
+            buf: AlignedBuf::new(buf_capacity, buf_capacity),
             buf_capacity,
             config,
-            input: BufReader::with_capacity(
-                buf_capacity,
-                input,
-            ),
-            output: BufWriter::with_capacity(
-                buf_capacity,
-                output,
-            ),
+            domain,
+            ranges: vec![domain],
+            input,
+            output,
             map,
-            stage: stage,
+            counts,
+            stage,
+            map_path: None,
+            map_format: MapFormat::default(),
+            last_save: Instant::now(),
+            space_check: None,
+            last_space_check: Instant::now(),
+            bytes_written: 0,
+            bytes_copied: 0,
+            pending: vec![],
+            pending_offset: 0,
+            stop: None,
+            is_stop_commanded: false,
+            control: None,
+            stop_reason: None,
+            reporter: Box::new(NoReport),
+            throughput: Throughput::new(THROUGHPUT_WINDOW),
+            copy_rate: Throughput::new(PHASE_RATE_WINDOW),
+            isolate_rate: None,
+            reads: ReadHistogram::default(),
+            throttle,
+            workers: vec![],
+            pinpoint: None,
         };
 
         // Ensure that buffer capacity is adjusted based on progress.
         r.set_buf_capacity();
-        r
+        Ok(r)
+    }
+
+    /// Align reads and writes to align bytes, such as the device's
+    /// logical block size. Defaults to sector_size.
+    pub fn set_buf_alignment(&mut self, align: usize) -> &mut Self {
+        self.buf = AlignedBuf::new(self.buf_capacity, align);
+        self
+    }
+
+    /// Only recover sectors within ranges, as well as within --start and --end.
+    pub fn set_ranges(&mut self, ranges: &[Domain]) -> &mut Self {
+        self.ranges = ranges.iter()
+            .filter_map(|range| range.intersect(&self.domain))
+            .collect();
+        self
+    }
+
+    /// Save the map to path every save_interval seconds during a run.
+    pub fn set_map_path(&mut self, path: PathBuf) -> &mut Self {
+        self.map_path = Some(path);
+        self
+    }
+
+    /// Format to save the map to map_path in. Defaults to RON.
+    pub fn set_map_format(&mut self, format: MapFormat) -> &mut Self {
+        self.map_format = format;
+        self
+    }
+
+    /// Check free space on the output's filesystem every save_interval
+    /// seconds during a run, ending it if the output may not fit.
+    pub fn set_space_check(&mut self, check: SpaceCheck) -> &mut Self {
+        self.space_check = Some(check);
+        self
+    }
+
+    /// Stop recovery once stop is set.
+    pub fn set_stop_flag(&mut self, stop: &'static AtomicBool) -> &mut Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Report progress to reporter after each cluster.
+    pub fn set_reporter(&mut self, reporter: Box<dyn Report>) -> &mut Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Read untested clusters in parallel, a thread per worker,
+    /// each reading distinct clusters from its own handle on the input.
+    /// Isolation is always one read at a time, as parallel reads only
+    /// wear failing drives down faster.
+    pub fn set_workers(&mut self, workers: Vec<Box<dyn ReadAt>>) -> &mut Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Read clusters failing to isolate again through pinpoint, so only
+    /// the sectors it flags are isolated further, each on its own.
+    /// Unused with sweep, which splits failed reads up as it goes.
+    pub fn set_pinpoint(&mut self, pinpoint: Box<dyn Pinpoint>) -> &mut Self {
+        self.pinpoint = Some(pinpoint);
+        self
+    }
+
+    /// Apply commands from control between clusters, answering each,
+    /// so a program running recovery can pause, pace or stop it.
+    pub fn set_control(&mut self, control: Control) -> &mut Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// Whether a stop was requested, ending the run early.
+    pub fn is_stopped(&self) -> bool {
+        self.is_stop_commanded || self.stop.is_some_and(|stop| stop.load(Ordering::SeqCst))
+    }
+
+    /// Current rescue map.
+    pub fn map(&self) -> &MapFile {
+        &self.map
+    }
+
+    /// Output being recovered to.
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Reads attempted this run, by length.
+    pub fn read_histogram(&self) -> &ReadHistogram {
+        &self.reads
     }
 
     /// Recover media.
-    pub fn run(&mut self) -> &mut Self {
-        let mut is_finished = false;
+    /// Only clusters within domain are read; the rest of the map is left as is.
+    ///
+    /// Once the stop flag is set, the cluster being copied is finished and
+    /// the output synced, then run returns early with the map up to date,
+    /// ready to be saved and resumed from.
+    ///
+    /// With verify set, recovered clusters are verified once recovery can go
+    /// no further. Clusters failing verification are isolated again.
+    /// With verify_after_pass set, those recovered by each pass are verified
+    /// as soon as it finishes instead, so are isolated again by the next.
+    ///
+    /// Failing to write to the output, even once retried, or to read back
+    /// from it ends the run with the error. The map is left up to date with
+    /// every cluster written before it, ready to be saved.
+    ///
+    /// However it ends, why is kept, as stop_reason() gives.
+    ///
+    /// With sample_source set, sectors of the input are hashed before the
+    /// run and again after it, warning of any which changed in between.
+    pub fn run(&mut self) -> io::Result<&mut Self> {
+        let mut is_verified = !self.config.verify;
+
+        self.sample_source();
 
-        while !is_finished {
-            match self.map.get_stage() {
-                Stage::Untested => { self.copy_untested(); },
-                Stage::ForIsolation(level) => { self.copy_isolate(level); },
-                Stage::Damaged => {
-                    println!("Cannot recover further.");
+        let outcome = loop {
+            if self.is_stopped() {
+                break Ok(StopReason::Interrupted);
+            }
+
+            let before = self.targets();
+            let stage = before.get_stage();
+            info!("Next pass at stage {:?}", stage);
+            self.stage = stage;
 
-                    is_finished = true
+            let pass = match stage {
+                Stage::Untested | Stage::ForIsolation(_) if self.config.sweep => self.copy_sweep(),
+                Stage::Untested => self.copy_untested(),
+                Stage::ForIsolation(level) => self.copy_isolate(level),
+                // Only verify once per run, so reads which never match
+                // can't keep recovery going forever.
+                Stage::Damaged | Stage::Recovered if !is_verified => {
+                    is_verified = true;
+                    self.verify_pass()
                 },
+                Stage::Damaged => break Ok(StopReason::Exhausted),
+                Stage::Recovered | Stage::Verified => break Ok(StopReason::Complete),
+            };
+
+            if let Err(err) = pass {
+                break Err(err);
+            }
+
+            let is_copy = matches!(stage, Stage::Untested | Stage::ForIsolation(_));
+
+            if is_copy && self.config.verify_after_pass && !self.is_stopped() {
+                if let Err(err) = self.verify_recovered_since(&before) {
+                    break Err(err);
+                }
+            }
+        };
+
+        // Whatever's pending is written even if the run failed, so it's kept.
+        let flushed = self.flush_writes();
+        let outcome = outcome.and_then(|reason| flushed.map(|_| reason));
+
+        // End the progress line first, at the stage the run ended at.
+        self.report_progress();
+        self.reporter.finish();
+        self.check_samples();
+
+        let reason = match outcome {
+            Ok(reason) => reason,
+            Err(err) => {
+                self.stop_reason = Some(match err.kind() {
+                    io::ErrorKind::StorageFull => StopReason::OutputFull,
+                    _ => StopReason::OutputFailed,
+                });
+
+                // Keep what was written before the failure, such as the
+                // output filling up, so the run can be resumed once fixed.
+                if let Err(err) = self.output.sync() {
+                    warn!("Failed to sync output file: {:?}", err);
+                }
+
+                return Err(err);
+            },
+        };
+
+        info!("Stopped: {}", reason);
+        self.stop_reason = Some(reason);
+
+        if self.config.discard_unrecovered {
+            self.discard_unrecovered();
+        }
+
+        if let Err(err) = self.output.sync() {
+            warn!("Failed to sync output file: {:?}", err);
+        }
+
+        Ok(self)
+    }
+
+    /// Copy sectors which image, an earlier rescue's output, recovered
+    /// per its map, to the output, mapping them as Recovered so they're
+    /// never read from the input. Only sectors within domain which aren't
+    /// recovered already are copied. map must be of the same sector size.
+    pub fn prefill<I: Input>(&mut self, image: &mut I, map: &MapFile) -> io::Result<&mut Self> {
+        if map.sector_size != self.map.sector_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Map of prefilled image has {} byte sectors, not {}",
+                map.sector_size, self.map.sector_size,
+            )));
+        }
+
+        let is_recovered = |stage: Stage| matches!(stage, Stage::Recovered | Stage::Verified);
+        let sector_size = self.map.sector_size as usize;
+        let cluster_length = self.config.cluster_length as usize;
+        let mut prefilled = 0;
+
+        let regions: Vec<(Domain, Stage)> = self.targets()
+            .diff(&map.crop(self.domain))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&(_, old, new)| !is_recovered(old) && is_recovered(new))
+            .map(|(domain, old, _)| (domain, old))
+            .collect();
+
+        for (domain, from) in regions {
+            for mut cluster in Cluster::new(domain, from).subdivide(cluster_length) {
+                let len = cluster.len_bytes(self.map.sector_size);
+
+                image.read_exact_at(
+                    &mut self.buf[..len],
+                    (cluster.domain().start * sector_size) as u64,
+                )?;
+                self.write_cluster(cluster)?;
+
+                cluster.set_stage(Stage::Recovered);
+                self.update_map(cluster, from);
+                prefilled += cluster.domain().len();
             }
         }
 
-        self
+        self.flush_writes()?;
+
+        info!("Copied {} sectors recovered in an earlier rescue", prefilled);
+        Ok(self)
+    }
+
+    /// Clusters the next pass of run would read, in order, without
+    /// reading or writing anything. Empty if recovery can go no further.
+    pub fn plan(&self) -> Vec<Cluster> {
+        match self.targets().get_stage() {
+            Stage::Untested | Stage::ForIsolation(_) if self.config.sweep => self.sweep_clusters(),
+            Stage::Untested => self.pending_clusters(
+                Stage::Untested,
+                self.config.cluster_length as usize,
+            ),
+            Stage::ForIsolation(level) => self.pending_clusters(
+                Stage::ForIsolation(level),
+                self.isolate_len(level),
+            ),
+            Stage::Damaged | Stage::Recovered if self.config.verify => self.pending_clusters(
+                Stage::Recovered,
+                self.config.cluster_length as usize,
+            ),
+            Stage::Damaged | Stage::Recovered | Stage::Verified => vec![],
+        }
+    }
+
+    /// Sectors recovered so far, read throughput, and time left
+    /// to copy what's untested, then isolate the rest, at each phase's rate.
+    pub fn progress(&mut self) -> Progress {
+        let stats = self.summary();
+        let sector_size = self.map.sector_size as u64;
+
+        Progress {
+            recovered: stats.recovered,
+            total: stats.sectors,
+            untested: stats.untested,
+            stage: self.stage,
+            bytes_per_sec: self.throughput.bytes_per_sec(),
+            eta: phased_eta(
+                stats.untested as u64 * sector_size,
+                self.copy_rate.bytes_per_sec(),
+                stats.for_isolation as u64 * sector_size,
+                self.isolate_rate.as_mut().and_then(|rate| rate.bytes_per_sec()),
+            ),
+        }
+    }
+
+    /// Why the last run ended, or None before any has.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    /// Sector and byte totals per stage over the whole map.
+    pub fn summary(&self) -> RecoveryStats {
+        let sector_size = self.map.sector_size as u64;
+        let mut stats = RecoveryStats {
+            sectors: self.map.domain.len(),
+            ..Default::default()
+        };
+
+        stats.recovered = self.counts.recovered;
+        stats.untested = self.counts.untested;
+        stats.for_isolation = self.counts.for_isolation;
+        stats.damaged = self.counts.damaged;
+
+        stats.total_bytes = stats.sectors as u64 * sector_size;
+        stats.recovered_bytes = stats.recovered as u64 * sector_size;
+        stats.damaged_bytes = stats.damaged as u64 * sector_size;
+        stats.recovered_percent = match stats.sectors {
+            0 => 0.0,
+            n => stats.recovered as f64 * 100.0 / n as f64,
+        };
+
+        stats
     }
 
     /// Attempt to copy all untested blocks.
-    fn copy_untested(&mut self) -> &mut Self {
+    /// Clusters which fail to read are pushed to ForIsolation(0).
+    ///
+    /// With skip_size set, each failed read skips the clusters following it,
+    /// starting at cluster_length sectors and doubling while reads keep
+    /// failing, up to skip_size. This quickly gets past large bad regions.
+    /// Skipped clusters are left Untested until everything else is read.
+    ///
+    /// With gentle_start set, clusters are read in shorter pieces at first,
+    /// as Ramp lengthens them.
+    fn copy_untested(&mut self) -> io::Result<&mut Self> {
+        let cluster_length = self.config.cluster_length as usize;
+        let untested = self.pending_clusters(Stage::Untested, cluster_length);
 
-        let mut untested: Vec<Cluster> = vec![];
+        if !self.workers.is_empty() {
+            return self.copy_untested_parallel(untested);
+        }
 
-        for cluster in self.map.get_clusters(Stage::Untested).iter_mut() {
-            untested.append(&mut cluster.subdivide(self.map.sector_size as usize));
+        if self.config.interleave {
+            return self.copy_interleaved(untested);
         }
 
-        todo!("Read and save data.");
+        let max_skip = self.config.skip_size
+            .map_or(0, |bytes| (bytes / self.map.sector_size as u64) as usize);
+        let mut stride = cluster_length.min(max_skip);
+        let mut skip: usize = 0;
+        let mut deferred: Vec<Cluster> = vec![];
+        let mut ramp = match self.config.gentle_start {
+            true => Ramp::new(1, cluster_length),
+            false => Ramp::new(cluster_length, cluster_length),
+        };
+
+        for cluster in untested {
+            if self.is_stopped() {
+                break;
+            }
 
-        self
+            if skip > 0 {
+                skip = skip.saturating_sub(cluster.domain().len());
+                deferred.push(cluster);
+                continue;
+            }
+
+            if self.copy_ramped(cluster, &mut ramp)? {
+                stride = cluster_length.min(max_skip);
+            } else {
+                skip = stride;
+                stride = stride.saturating_mul(2).min(max_skip);
+            }
+        }
+
+        for cluster in deferred {
+            if self.is_stopped() {
+                break;
+            }
+
+            self.copy_ramped(cluster, &mut ramp)?;
+        }
+
+        Ok(self)
     }
 
-    /// Attempt to copy blocks via isolation at pass level.
-    fn copy_isolate(&mut self, level: u8) -> &mut Self {
+    /// Copy a single untested cluster in reads of ramp's length,
+    /// returning whether all of them succeeded.
+    fn copy_ramped(&mut self, cluster: Cluster, ramp: &mut Ramp) -> io::Result<bool> {
+        if ramp.is_done() {
+            return self.copy_untested_cluster(cluster);
+        }
 
-        todo!();
+        let domain = cluster.domain();
+        let mut start = domain.start;
+        let mut is_read = true;
 
-        self
+        while start < domain.end && !self.is_stopped() {
+            let end = start.saturating_add(ramp.len).min(domain.end);
+            let piece = Cluster::new(Domain { start, end }, Stage::Untested);
+            let is_piece_read = self.copy_untested_cluster(piece)?;
+
+            ramp.record(is_piece_read);
+            is_read &= is_piece_read;
+            start = end;
+        }
+
+        Ok(is_read)
     }
 
-    /// Set buffer capacities as cluster length in bytes.
-    /// Varies depending on the recovery stage.
-    fn set_buf_capacity(&mut self) -> &mut Self {
-        self.buf_capacity = (self.config.sector_size * self.config.cluster_length) as usize;
+    /// Copy untested clusters, with reads spread across workers.
+    /// Data is written and mapped on this thread, as each read completes.
+    /// Reads stop once writing one fails.
+    fn copy_untested_parallel(&mut self, untested: Vec<Cluster>) -> io::Result<&mut Self> {
+        let sector_size = self.map.sector_size as usize;
+        let input_offset = self.input_offset();
+        let workers = std::mem::take(&mut self.workers);
+        // Shared, so workers between them read no faster than one would.
+        let throttle = Mutex::new(self.throttle.take());
+        let stop = self.stop;
+        let next = AtomicUsize::new(0);
 
-        self
+        let result = thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+
+            for worker in workers.iter() {
+                let (tx, untested, next, throttle) = (tx.clone(), &untested, &next, &throttle);
+
+                scope.spawn(move || {
+                    while !stop.is_some_and(|stop| stop.load(Ordering::SeqCst)) {
+                        let Some(&cluster) = untested.get(next.fetch_add(1, Ordering::SeqCst))
+                        else {
+                            break;
+                        };
+
+                        let mut data = vec![0u8; cluster.domain().len() * sector_size];
+
+                        if let Some(throttle) = throttle.lock().unwrap().as_mut() {
+                            throttle.take(data.len() as u64);
+                        }
+
+                        let result = worker.read_exact_at(
+                            &mut data,
+                            input_offset + (cluster.domain().start * sector_size) as u64,
+                        );
+
+                        // Nobody is listening if the run is over.
+                        if tx.send((cluster, result.map(|_| data))).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+
+            drop(tx);
+
+            for (cluster, result) in rx {
+                let result = result.and_then(|data| match self.is_suspect(&data) {
+                    true => Err(suspect_zeros()),
+                    false => Ok(data),
+                });
+
+                let is_read = match result {
+                    Ok(data) => {
+                        if self.buf.len() < data.len() {
+                            self.buf = AlignedBuf::new(data.len(), self.buf.align());
+                        }
+
+                        self.buf[..data.len()].copy_from_slice(&data);
+                        self.throughput.record(data.len() as u64);
+                        true
+                    },
+                    Err(err) => {
+                        warn!("Failed to read sectors {}: {}", self.shown(cluster.domain()), err);
+                        false
+                    },
+                };
+
+                self.reads.record(cluster.domain().len(), is_read);
+
+                // Dropping rx stops the workers at their next read.
+                self.map_untested_cluster(cluster, is_read)?;
+            }
+
+            Ok(())
+        });
+
+        self.workers = workers;
+        self.throttle = throttle.into_inner().unwrap();
+        result.map(|_| self)
     }
-}
 
+    /// Copy untested clusters a stretch of INTERLEAVE_CLUSTERS at a time,
+    /// isolating those failing to read at level 0 before the next stretch.
+    /// Whatever still fails is left to the isolation passes after.
+    fn copy_interleaved(&mut self, untested: Vec<Cluster>) -> io::Result<&mut Self> {
+        let stretch = (self.config.cluster_length as usize * INTERLEAVE_CLUSTERS).max(1);
+        let isolate_len = self.isolate_len(0);
 
-#[cfg(test)]
-#[allow(unused)]
-mod tests {
-    use super::*;
+        let stretches = untested.chunk_by(|a, b| {
+            a.domain().start / stretch == b.domain().start / stretch
+        });
 
-    // Test for Recover::set_buf_capacity
-}
\ No newline at end of file
+        for clusters in stretches {
+            let mut failed: Vec<Cluster> = vec![];
+
+            for &cluster in clusters {
+                if self.is_stopped() {
+                    return Ok(self);
+                }
+
+                if !self.copy_untested_cluster(cluster)? {
+                    failed.extend(cluster.to_owned().subdivide(isolate_len));
+                }
+            }
+
+            self.sort_clusters(&mut failed);
+            self.isolate_clusters(0, failed)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Copy a single untested cluster, returning whether it was read.
+    fn copy_untested_cluster(&mut self, cluster: Cluster) -> io::Result<bool> {
+        let is_read = self.read_cluster(cluster).is_ok();
+
+        self.map_untested_cluster(cluster, is_read)?;
+        Ok(is_read)
+    }
+
+    /// Write an untested cluster read into buf, and map it as Recovered.
+    /// If it wasn't read, push it to ForIsolation(0).
+    fn map_untested_cluster(&mut self, mut cluster: Cluster, is_read: bool) -> io::Result<()> {
+        if is_read {
+            self.write_cluster(cluster)?;
+            cluster.set_stage(Stage::Recovered);
+        } else {
+            debug!("Sectors {} left for isolation", self.shown(cluster.domain()));
+            cluster.set_stage(Stage::ForIsolation(0));
+        }
+
+        self.copy_rate.record(cluster.len_bytes(self.map.sector_size) as u64);
+        self.update_map(cluster, Stage::Untested);
+        self.autosave()?;
+        self.report_progress();
+        self.check_space()?;
+        self.apply_commands()
+    }
+
+    /// Attempt to copy blocks via isolation at pass level.
+    /// Clusters are split into reads of cluster_length / 2^(level + 1)
+    /// sectors, each tried up to brute_passes times. Reads which still fail
+    /// move to the next level, or are marked Damaged once a single sector,
+    /// which they always are by max_isolation_level.
+    fn copy_isolate(&mut self, level: u8) -> io::Result<&mut Self> {
+        let isolated = self.pending_clusters(
+            Stage::ForIsolation(level),
+            self.isolate_len(level),
+        );
+
+        self.isolate_clusters(level, isolated)
+    }
+
+    /// Attempt to copy clusters mapped ForIsolation(level), in order,
+    /// as copy_isolate does.
+    fn isolate_clusters(&mut self, level: u8, clusters: Vec<Cluster>) -> io::Result<&mut Self> {
+        for cluster in clusters {
+            if self.is_stopped() {
+                break;
+            }
+
+            self.isolate_cluster(level, cluster)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Attempt to copy a single cluster mapped ForIsolation(level),
+    /// returning the stage it's mapped as after, or with pinpoint set,
+    /// that of its flagged sectors.
+    fn isolate_cluster(&mut self, level: u8, mut cluster: Cluster) -> io::Result<Stage> {
+        // Started before the first read, so it's timed too.
+        self.isolate_rate.get_or_insert_with(|| Throughput::new(PHASE_RATE_WINDOW));
+
+        let is_read = (0..self.config.brute_passes.max(1))
+            .any(|_| self.read_cluster(cluster).is_ok());
+        let flagged = match is_read || self.config.sweep {
+            true => None,
+            false => self.read_pinpointed(cluster),
+        };
+
+        if let Some(flagged) = flagged {
+            let stage = self.map_pinpointed(level, cluster, &flagged)?;
+            cluster.set_stage(stage);
+        } else {
+            if is_read {
+                self.write_cluster(cluster)?;
+                cluster.set_stage(Stage::Recovered);
+            } else if cluster.domain().len() <= 1 {
+                let stage = self.failed_sector_stage(level);
+
+                if stage == Stage::Damaged {
+                    self.fill_damaged(cluster)?;
+                    info!("Sector {} is damaged", self.shown(cluster.domain()).start);
+                }
+
+                cluster.set_stage(stage);
+            } else {
+                cluster.set_stage(Stage::ForIsolation(level).escalate());
+            }
+
+            self.update_map(cluster, Stage::ForIsolation(level));
+        }
+
+        if let Some(rate) = self.isolate_rate.as_mut() {
+            rate.record(cluster.len_bytes(self.map.sector_size) as u64);
+        }
+
+        self.autosave()?;
+        self.report_progress();
+        self.check_space()?;
+        self.apply_commands()?;
+
+        Ok(cluster.stage())
+    }
+
+    /// Read a cluster into buf through pinpoint, if set, returning the
+    /// indexes of its sectors flagged bad. None if unset, or if even
+    /// pinpoint fails to read it.
+    fn read_pinpointed(&mut self, cluster: Cluster) -> Option<Vec<usize>> {
+        let sector_size = self.map.sector_size as usize;
+        let offset = self.input_offset() + (cluster.domain().start * sector_size) as u64;
+        let len = cluster.len_bytes(self.map.sector_size);
+
+        let result = self.pinpoint.as_mut()?
+            .read_flagged(&mut self.buf[..len], offset, sector_size);
+        let shown = self.shown(cluster.domain());
+
+        match result {
+            Ok(flagged) => {
+                debug!("{} of sectors {} flagged bad", flagged.len(), shown);
+                Some(flagged)
+            },
+            Err(err) => {
+                warn!("Failed to read sectors {} flagging bad ones: {}", shown, err);
+                None
+            },
+        }
+    }
+
+    /// Map a cluster mapped ForIsolation(level) and read by pinpoint into
+    /// buf, writing and mapping Recovered the sectors not flagged. Those
+    /// flagged are isolated a sector at a time, or mapped Damaged if they
+    /// already were. Returns the stage they're mapped as.
+    fn map_pinpointed(
+        &mut self,
+        level: u8,
+        cluster: Cluster,
+        flagged: &[usize],
+    ) -> io::Result<Stage> {
+        let sector_size = self.map.sector_size as usize;
+        let start = cluster.domain().start;
+        // Past level, as isolate_len(level) > 1 means level isn't the last.
+        // At the last, clusters are single sectors.
+        let bad_stage = match self.isolate_len(level) {
+            1 => self.failed_sector_stage(level),
+            _ => Stage::ForIsolation(self.config.max_isolation_level - 1),
+        };
+
+        let indexes: Vec<usize> = (0..cluster.domain().len()).collect();
+        let runs = indexes.chunk_by(|a, b| flagged.contains(a) == flagged.contains(b));
+
+        for run in runs {
+            let (first, last) = (run[0], run[run.len() - 1]);
+            let mut piece = Cluster::new(
+                Domain { start: start + first, end: start + last + 1 },
+                Stage::Recovered,
+            );
+
+            // Pieces are written from the start of buf.
+            self.buf.copy_within(first * sector_size..(last + 1) * sector_size, 0);
+
+            if flagged.contains(&first) {
+                if bad_stage == Stage::Damaged {
+                    self.fill_damaged(piece)?;
+                    info!("Sectors {} are damaged", self.shown(piece.domain()));
+                }
+
+                piece.set_stage(bad_stage);
+            } else {
+                self.write_cluster(piece)?;
+            }
+
+            self.update_map(piece, Stage::ForIsolation(level));
+        }
+
+        Ok(match flagged.is_empty() {
+            true => Stage::Recovered,
+            false => bad_stage,
+        })
+    }
+
+    /// Copy everything untested or isolating within domain in a single pass
+    /// from its start to its end, so the input is never read backwards.
+    /// Clusters which fail to read are isolated there and then, depth first:
+    /// each read of the next level is finished with before the one after it.
+    fn copy_sweep(&mut self) -> io::Result<&mut Self> {
+        for cluster in self.sweep_clusters() {
+            if self.is_stopped() {
+                break;
+            }
+
+            self.sweep_cluster(cluster)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Clusters copy_sweep starts from, in order, at their mapped stages.
+    fn sweep_clusters(&self) -> Vec<Cluster> {
+        let mut pending: Vec<Cluster> = self.targets().map
+            .into_iter()
+            .flat_map(|mut cluster| match cluster.stage() {
+                Stage::Untested => cluster.subdivide(self.config.cluster_length as usize),
+                Stage::ForIsolation(level) => cluster.subdivide(self.isolate_len(level)),
+                _ => vec![],
+            })
+            .collect();
+
+        pending.sort_by_key(|c| c.domain().start);
+        pending
+    }
+
+    /// Copy a cluster at its mapped stage, then each read of the next
+    /// isolation level it's split into if it fails, in order.
+    fn sweep_cluster(&mut self, mut cluster: Cluster) -> io::Result<()> {
+        let stage = match cluster.stage() {
+            Stage::Untested => match self.copy_untested_cluster(cluster)? {
+                true => Stage::Recovered,
+                false => Stage::ForIsolation(0),
+            },
+            Stage::ForIsolation(level) => self.isolate_cluster(level, cluster)?,
+            stage => stage,
+        };
+
+        if let Stage::ForIsolation(level) = stage {
+            for piece in cluster.set_stage(stage).subdivide(self.isolate_len(level)) {
+                if self.is_stopped() {
+                    break;
+                }
+
+                self.sweep_cluster(piece)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update the map with a cluster read at stage from,
+    /// moving its sectors' counts to its new stage.
+    fn update_map(&mut self, cluster: Cluster, from: Stage) -> &mut Self {
+        let len = cluster.domain()
+            .intersect(&self.map.domain)
+            .map_or(0, |d| d.len());
+
+        *self.counts.count_mut(from) -= len;
+        *self.counts.count_mut(cluster.stage()) += len;
+        self.map.update(cluster);
+
+        debug_assert_eq!(self.counts, self.map.sector_counts());
+        self
+    }
+
+    /// Pass the latest progress to the reporter.
+    fn report_progress(&mut self) -> &mut Self {
+        let progress = self.progress();
+        self.reporter.report(&progress);
+
+        self
+    }
+
+    /// Save the map if save_interval has passed since the last save.
+    /// Failures to save are reported, but never stop the run.
+    /// Pending writes are written first, so the map never claims sectors
+    /// recovered which aren't in the output; failing that stops the run.
+    fn autosave(&mut self) -> io::Result<()> {
+        let interval = Duration::from_secs(self.config.save_interval);
+
+        if self.map_path.is_some() && self.last_save.elapsed() >= interval {
+            self.flush_writes()?;
+
+            if let Err(err) = self.save_map() {
+                warn!("Failed to save mapping file, continuing: {:?}", err);
+            }
+
+            self.last_save = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Save the map to map_path, if set, in map_format.
+    fn save_map(&self) -> io::Result<()> {
+        match &self.map_path {
+            Some(path) => self.map.save_with_backup(
+                path,
+                self.map_format,
+                !self.config.no_map_backup,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the commands sent through control since the last cluster,
+    /// answering each. Once paused, waits for resume or stop, or SIGINT.
+    /// Pending writes are written before a checkpoint saves the map;
+    /// failing that stops the run.
+    fn apply_commands(&mut self) -> io::Result<()> {
+        let mut is_paused = false;
+
+        loop {
+            let Some(control) = self.control.as_ref() else {
+                return Ok(());
+            };
+
+            let received = match is_paused {
+                true => match control.commands.recv_timeout(PAUSE_POLL) {
+                    Ok(received) => Some(received),
+                    Err(mpsc::RecvTimeoutError::Timeout) => None,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        warn!("Commands ended while paused, resuming");
+                        return Ok(());
+                    },
+                },
+                false => control.commands.try_recv().ok(),
+            };
+
+            let received = match received {
+                Some(received) => received,
+                None if is_paused && !self.is_stopped() => continue,
+                None => return Ok(()),
+            };
+
+            let result = match received {
+                Ok(Command::Pause) => {
+                    info!("Paused");
+                    is_paused = true;
+                    Ok(())
+                },
+                Ok(Command::Resume) => {
+                    info!("Resumed");
+                    is_paused = false;
+                    Ok(())
+                },
+                Ok(Command::SetRate { bps }) => {
+                    self.throttle = Throttle::new(bps);
+                    Ok(())
+                },
+                Ok(Command::Checkpoint) if self.map_path.is_some() => {
+                    self.flush_writes()?;
+                    self.last_save = Instant::now();
+                    self.save_map().map_err(|err| err.to_string())
+                },
+                Ok(Command::Checkpoint) => Err(String::from("No map to save")),
+                Ok(Command::Stop) => {
+                    self.is_stop_commanded = true;
+                    is_paused = false;
+                    Ok(())
+                },
+                Err(ref err) => Err(err.to_owned()),
+            };
+
+            let response = Response::new(received.ok(), result);
+
+            if let Some(control) = self.control.as_mut() {
+                if let Err(err) = response.write_line(&mut control.responses) {
+                    warn!("Failed to answer command: {}", err);
+                }
+            }
+        }
+    }
+
+    /// Check free space if save_interval has passed since the last check,
+    /// failing with StorageFull if what's left to recover may not fit.
+    fn check_space(&mut self) -> io::Result<()> {
+        let interval = Duration::from_secs(self.config.save_interval);
+
+        if let Some(check) = &self.space_check {
+            if self.last_space_check.elapsed() >= interval {
+                let remaining = (self.counts.untested + self.counts.for_isolation) as u64
+                    * self.map.sector_size as u64;
+
+                self.last_space_check = Instant::now();
+                check.check(self.bytes_written, self.bytes_copied, remaining)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clusters at stage within ranges, split into reads of at most
+    /// len sectors, in the order to read them.
+    fn pending_clusters(&self, stage: Stage, len: usize) -> Vec<Cluster> {
+        let mut pending: Vec<Cluster> = self.targets()
+            .clusters_with_stage(stage)
+            .flat_map(|cluster| cluster.to_owned().subdivide(len))
+            .collect();
+
+        self.sort_clusters(&mut pending);
+        pending
+    }
+
+    /// Map of only the clusters within ranges, to be recovered.
+    fn targets(&self) -> MapFile {
+        self.map.crop_all(&self.ranges)
+    }
+
+    /// Sectors per read at isolation pass level,
+    /// halving cluster_length each level down to a single sector.
+    /// The last level before max_isolation_level reads single sectors.
+    fn isolate_len(&self, level: u8) -> usize {
+        if level.saturating_add(1) >= self.config.max_isolation_level {
+            return 1;
+        }
+
+        (self.config.cluster_length as usize)
+            .checked_shr(level as u32 + 1)
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    /// Stage to map a single sector as after failing to read it at level.
+    /// Each failure moves it a level past the first to read single sectors,
+    /// so its level counts how many times in a row it's failed. Once that's
+    /// confirm_damaged, it's Damaged.
+    fn failed_sector_stage(&self, level: u8) -> Stage {
+        let first = (0..=u8::MAX)
+            .find(|&level| self.isolate_len(level) == 1)
+            .unwrap_or(u8::MAX);
+        // Sectors read alone before then, such as at the end of the
+        // domain, fail for the first time at any level.
+        let level = level.max(first);
+        let failures = (level - first) as usize + 1;
+
+        match failures >= self.config.confirm_damaged as usize {
+            true => Stage::Damaged,
+            false => Stage::ForIsolation(level).escalate(),
+        }
+    }
+
+    /// Sort clusters into the order to read them,
+    /// from the end of the domain backwards if reversed.
+    fn sort_clusters(&self, clusters: &mut [Cluster]) {
+        if self.config.reverse {
+            clusters.sort_by_key(|c| Reverse(c.domain().start));
+        } else {
+            clusters.sort_by_key(|c| c.domain().start);
+        }
+    }
+
+    /// Re-read all Recovered clusters, comparing against output.
+    /// Clusters reading the same become Verified. Those reading differently,
+    /// or failing to read, are pushed back to ForIsolation(0).
+    /// Verified clusters are skipped, so an interrupted pass resumes.
+    pub fn verify_pass(&mut self) -> io::Result<&mut Self> {
+        let recovered = self.pending_clusters(
+            Stage::Recovered,
+            self.config.cluster_length as usize,
+        );
+
+        self.verify_clusters(recovered)
+    }
+
+    /// Verify clusters mapped Recovered since before, as verify_pass does.
+    fn verify_recovered_since(&mut self, before: &MapFile) -> io::Result<&mut Self> {
+        let cluster_length = self.config.cluster_length as usize;
+        let mut recovered: Vec<Cluster> = before.diff(&self.targets())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|&(_, _, stage)| stage == Stage::Recovered)
+            .flat_map(|(domain, _, stage)| Cluster::new(domain, stage).subdivide(cluster_length))
+            .collect();
+
+        self.sort_clusters(&mut recovered);
+        self.verify_clusters(recovered)
+    }
+
+    /// Re-read Recovered clusters, in order, as verify_pass does.
+    fn verify_clusters(&mut self, clusters: Vec<Cluster>) -> io::Result<&mut Self> {
+        for mut cluster in clusters {
+            if self.is_stopped() {
+                break;
+            }
+
+            let is_same = match self.read_cluster(cluster) {
+                Ok(_) => self.output_matches(cluster)?,
+                Err(_) => false,
+            };
+
+            if is_same {
+                cluster.set_stage(Stage::Verified);
+            } else {
+                cluster.set_stage(Stage::ForIsolation(0));
+            }
+
+            self.update_map(cluster, Stage::Recovered);
+            self.autosave()?;
+            self.report_progress();
+            self.check_space()?;
+            self.apply_commands()?;
+        }
+
+        Ok(self)
+    }
+
+    /// Whether output holds the same data as buf for a cluster.
+    fn output_matches(&mut self, cluster: Cluster) -> io::Result<bool> {
+        self.flush_writes()?;
+
+        let sector_size = self.map.sector_size as usize;
+        let len = cluster.len_bytes(self.map.sector_size);
+        let mut written = AlignedBuf::new(len, self.buf.align());
+
+        self.output.read_exact_at(&mut written, (cluster.domain().start * sector_size) as u64)?;
+
+        Ok(written[..] == self.buf[..len])
+    }
+
+    /// Domain as logged, counting from the start of the recovered domain
+    /// with a relative offset_base.
+    fn shown(&self, domain: Domain) -> Domain {
+        let origin = self.config.offset_base.origin(self.domain.start);
+
+        Domain {
+            start: domain.start.saturating_sub(origin),
+            end: domain.end.saturating_sub(origin),
+        }
+    }
+
+    /// Offset in input of the first sector of the map and output.
+    fn input_offset(&self) -> u64 {
+        self.config.input_offset as u64 * self.map.sector_size as u64
+    }
+
+    /// Read a cluster in full from input into buf.
+    fn read_cluster(&mut self, cluster: Cluster) -> io::Result<()> {
+        let sector_size = self.map.sector_size as usize;
+        let offset = self.input_offset() + (cluster.domain().start * sector_size) as u64;
+        let len = cluster.len_bytes(self.map.sector_size);
+
+        // Only outgrown if the map's sector size differs from config.
+        if self.buf.len() < len {
+            self.buf = AlignedBuf::new(len, self.buf.align());
+        }
+
+        if let Some(throttle) = self.throttle.as_mut() {
+            throttle.take(len as u64);
+        }
+
+        trace!("Reading sectors {}", self.shown(cluster.domain()));
+
+        let result = self.input.read_exact_at(&mut self.buf[..len], offset)
+            .and_then(|_| match self.is_suspect(&self.buf[..len]) {
+                true => Err(suspect_zeros()),
+                false => Ok(()),
+            });
+        self.reads.record(cluster.domain().len(), result.is_ok());
+
+        if let Err(err) = result {
+            warn!("Failed to read sectors {}: {}", self.shown(cluster.domain()), err);
+            return Err(err);
+        }
+
+        self.throughput.record(len as u64);
+        Ok(())
+    }
+
+    /// SHA-256 of a sector of the input, as hex.
+    fn hash_sector(&mut self, sector: usize) -> io::Result<String> {
+        let sector_size = self.map.sector_size as usize;
+        let offset = self.input_offset() + (sector * sector_size) as u64;
+
+        if self.buf.len() < sector_size {
+            self.buf = AlignedBuf::new(sector_size, self.buf.align());
+        }
+
+        self.input.read_exact_at(&mut self.buf[..sector_size], offset)?;
+
+        digest_ranges(
+            &mut io::Cursor::new(&self.buf[..sector_size]),
+            &[std::ops::Range { start: 0, end: sector_size as u64 }],
+            Algorithm::Sha256,
+        )
+    }
+
+    /// Hash sample_source sectors spread evenly over domain, keeping them
+    /// as the map's samples in place of any an earlier run took.
+    /// Sectors which fail to read aren't sampled.
+    fn sample_source(&mut self) -> &mut Self {
+        let count = match self.config.sample_source {
+            Some(count) => count.min(self.domain.len()),
+            None => return self,
+        };
+        let mut samples = Vec::with_capacity(count);
+
+        for i in 0..count {
+            let sector = self.domain.start + i * self.domain.len() / count;
+
+            match self.hash_sector(sector) {
+                Ok(before) => samples.push(SourceSample { sector, before, after: None }),
+                Err(err) => debug!("Not sampling unreadable sector {}: {}", sector, err),
+            }
+        }
+
+        self.map.samples = samples;
+        self
+    }
+
+    /// Hash the map's samples again, warning of any sector which reads
+    /// differently than before the run, or no longer reads at all.
+    fn check_samples(&mut self) -> &mut Self {
+        if self.config.sample_source.is_none() {
+            return self;
+        }
+
+        for i in 0..self.map.samples.len() {
+            let sector = self.map.samples[i].sector;
+            let shown = self.shown(Domain { start: sector, end: sector + 1 });
+
+            match self.hash_sector(sector) {
+                Ok(after) => self.map.samples[i].after = Some(after),
+                Err(err) => warn!("Failed to read sampled sectors {} again: {}", shown, err),
+            }
+
+            if self.map.samples[i].is_changed() {
+                warn!(
+                    "Sampled sectors {} read differently after recovery than before, \
+                    so the source changed during it and the output may be inconsistent",
+                    shown
+                );
+            }
+        }
+
+        self
+    }
+
+    /// Whether data read should be distrusted, holding a sector of nothing
+    /// but zeros with zero_is_suspect set. Isolation narrows such reads
+    /// down to the zeroed sectors, so the rest are still recovered.
+    fn is_suspect(&self, data: &[u8]) -> bool {
+        self.config.zero_is_suspect && data
+            .chunks(self.map.sector_size as usize)
+            .any(|sector| sector.iter().all(|&b| b == 0))
+    }
+
+    /// Write a cluster's data from buf to output,
+    /// at the same offset read from input.
+    ///
+    /// If sparse, runs of all-zero sectors are left as holes instead,
+    /// falling back to writing them where the output can't make holes.
+    fn write_cluster(&mut self, cluster: Cluster) -> io::Result<()> {
+        let sector_size = self.map.sector_size as usize;
+        let len = cluster.len_bytes(self.map.sector_size);
+        let offset = (cluster.domain().start * sector_size) as u64;
+
+        if self.config.sparse {
+            self.write_sparse(offset, len)?;
+        } else if let Some(block_size) = self.config.write_block_size {
+            self.write_batched(offset, len, block_size)?;
+        } else {
+            write_all_retrying(&mut self.output, &self.buf[..len], offset)?;
+            self.bytes_written += len as u64;
+        }
+
+        self.bytes_copied += len as u64;
+
+        // Only advice, so failing to take it doesn't matter.
+        let _ = self.output.drop_cache(offset, len as u64);
+
+        Ok(())
+    }
+
+    /// Add len bytes of buf, for output at offset, to those pending.
+    /// Once they reach a multiple of block_size, they're written up to the
+    /// last multiple reached, and the rest kept pending. Only contiguous
+    /// data is kept pending, so writing elsewhere writes it first.
+    fn write_batched(&mut self, offset: u64, len: usize, block_size: u64) -> io::Result<()> {
+        if offset != self.pending_offset + self.pending.len() as u64 {
+            self.flush_writes()?;
+            self.pending_offset = offset;
+        }
+
+        self.pending.extend_from_slice(&self.buf[..len]);
+
+        let end = self.pending_offset + self.pending.len() as u64;
+        let boundary = end - end % block_size;
+
+        if boundary > self.pending_offset {
+            self.write_pending((boundary - self.pending_offset) as usize)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write everything pending to output.
+    fn flush_writes(&mut self) -> io::Result<()> {
+        self.write_pending(self.pending.len())
+    }
+
+    /// Write the first len bytes pending to output.
+    /// If that fails, whatever was pending is mapped Untested again,
+    /// so it's read again rather than left claimed but unwritten.
+    fn write_pending(&mut self, len: usize) -> io::Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+
+        let result = write_all_retrying(&mut self.output, &self.pending[..len], self.pending_offset);
+
+        if let Err(err) = result {
+            let sector_size = self.map.sector_size as u64;
+            let unwritten = Domain {
+                start: (self.pending_offset / sector_size) as usize,
+                end: ((self.pending_offset + self.pending.len() as u64) / sector_size) as usize,
+            };
+
+            for cluster in self.map.crop(unwritten).map {
+                self.update_map(Cluster::new(cluster.domain(), Stage::Untested), cluster.stage());
+            }
+
+            self.pending.clear();
+            return Err(err);
+        }
+
+        self.pending.drain(..len);
+        self.pending_offset += len as u64;
+        self.bytes_written += len as u64;
+
+        Ok(())
+    }
+
+    /// Write len bytes of buf to output at offset,
+    /// leaving runs of all-zero sectors as holes where possible.
+    fn write_sparse(&mut self, offset: u64, len: usize) -> io::Result<()> {
+        let sector_size = self.map.sector_size as usize;
+        let is_zero = |sector: &[u8]| sector.iter().all(|&b| b == 0);
+        let mut start = 0;
+
+        while start < len {
+            let run_is_zero = is_zero(&self.buf[start..start + sector_size]);
+            let mut end = start + sector_size;
+
+            while end < len && is_zero(&self.buf[end..end + sector_size]) == run_is_zero {
+                end += sector_size;
+            }
+
+            let is_hole = run_is_zero && self.output
+                .punch_hole(offset + start as u64, (end - start) as u64)
+                .is_ok();
+
+            if !is_hole {
+                write_all_retrying(&mut self.output, &self.buf[start..end], offset + start as u64)?;
+                self.bytes_written += (end - start) as u64;
+            }
+
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    /// Write fill_pattern over a damaged cluster's range of output, if set.
+    fn fill_damaged(&mut self, cluster: Cluster) -> io::Result<()> {
+        if let Some(pattern) = &self.config.fill_pattern {
+            let len = cluster.len_bytes(self.map.sector_size);
+
+            pattern.fill(&mut self.buf[..len]);
+            self.write_cluster(cluster)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discard untested and damaged regions of the output,
+    /// where the output supports it.
+    /// Damaged regions filled with fill_pattern are kept.
+    fn discard_unrecovered(&mut self) -> &mut Self {
+        let stages: &[Stage] = match self.config.fill_pattern {
+            Some(_) => &[Stage::Untested],
+            None => &[Stage::Untested, Stage::Damaged],
+        };
+
+        if let Err(err) = self.output.discard_unrecovered(&self.map, stages) {
+            warn!("Failed to discard unrecovered regions: {:?}", err);
+        }
+
+        self
+    }
+
+    /// Set buffer capacities as cluster length in bytes.
+    /// Varies depending on the recovery stage.
+    fn set_buf_capacity(&mut self) -> &mut Self {
+        // Widened before multiplying, as the product of two u16s needs 32 bits.
+        self.buf_capacity = (self.config.sector_size as usize)
+            .checked_mul(self.config.cluster_length as usize)
+            .expect("Cluster is too large to buffer.");
+        self.buf = AlignedBuf::new(self.buf_capacity, self.buf.align());
+
+        self
+    }
+}
+
+
+#[cfg(test)]
+#[allow(unused)]
+mod tests {
+    use super::*;
+    use crate::{mapping::Domain, space::{allocated_len, FreeSpace}, testing::scratch_path};
+    use clap::Parser;
+    use std::{fs::{self, OpenOptions}, path::PathBuf};
+
+    /// Source of sectors bytes, differing sector to sector so misplaced
+    /// copies show, and a new map of them all Untested.
+    fn fixture(sector_size: usize, sectors: usize) -> (Vec<u8>, MapFile) {
+        let source = (0..sectors * sector_size)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let map = MapFile::new(sector_size as u16)
+            .set_domain(Domain { start: 0, end: sectors })
+            .to_owned();
+
+        (source, map)
+    }
+
+    // Test for Recover::set_buf_capacity
+    #[test]
+    fn test_set_buf_capacity() {
+        let cases = vec![
+            ("512", "8"),
+            // Each product overflows a u16.
+            ("512", "128"),
+            ("2048", "128"),
+            ("4096", "1024"),
+        ];
+
+        for (sector_size, cluster_length) in cases {
+            let config = Args::parse_from([
+                "kramer", "-i", "buf", "-s", sector_size, "-c", cluster_length,
+            ]);
+            let expected = config.sector_size as usize * config.cluster_length as usize;
+
+            let recover = Recover::new(
+                config,
+                io::Cursor::new(vec![]),
+                io::Cursor::new(vec![]),
+                MapFile::new(sector_size.parse().unwrap()),
+            ).unwrap();
+
+            assert!(
+                recover.buf_capacity == expected && recover.buf.len() == expected,
+                "Expected a {} byte buffer for {} sectors of {} bytes, got {}.",
+                expected, cluster_length, sector_size, recover.buf_capacity
+            )
+        }
+    }
+
+    // Test for Recover::new
+    #[test]
+    fn test_new_invalid_map() {
+        let cases = vec![
+            "(sector_size: 512, domain: (start: 0, end: 8), map: [\
+                (domain: (start: 0, end: 4), stage: Untested)])",
+            "(sector_size: 512, domain: (start: 0, end: 8), map: [\
+                (domain: (start: 0, end: 6), stage: Untested), \
+                (domain: (start: 4, end: 8), stage: Recovered)])",
+        ];
+
+        for map in cases {
+            let recieved = Recover::new(
+                Args::parse_from(["kramer", "-i", "invalid"]),
+                io::Cursor::new(vec![]),
+                io::Cursor::new(vec![]),
+                ron::de::from_str::<MapFile>(map).unwrap(),
+            );
+
+            assert!(
+                matches!(recieved, Err(KramerError::BadMap(_))),
+                "Expected map {} refused, got {:?}.",
+                map, recieved.map(|r| r.map)
+            )
+        }
+    }
+
+    /// In memory input, failing any read which touches a bad byte range.
+    #[derive(Debug)]
+    struct FaultyCursor {
+        data: io::Cursor<Vec<u8>>,
+        bad: Vec<std::ops::Range<u64>>,
+    }
+
+    impl FaultyCursor {
+        /// Cursor over data, with bad given in sectors.
+        fn new(data: Vec<u8>, sector_size: usize, bad: &[std::ops::Range<usize>]) -> Self {
+            FaultyCursor {
+                data: io::Cursor::new(data),
+                bad: bad.iter()
+                    .map(|r| (r.start * sector_size) as u64..(r.end * sector_size) as u64)
+                    .collect(),
+            }
+        }
+    }
+
+    impl Read for FaultyCursor {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.data.position();
+            let end = start + buf.len() as u64;
+
+            if self.bad.iter().any(|bad| start < bad.end && bad.start < end) {
+                return Err(io::Error::other("Bad sector"));
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for FaultyCursor {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for FaultyCursor {}
+
+    // Test for Recover::copy_isolate
+    #[test]
+    fn test_copy_isolate() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let cases = vec![
+            vec![10..13],
+            // Across cluster boundaries, and at the very end.
+            vec![0..1, 7..9, 31..32],
+            // A whole cluster.
+            vec![16..24],
+        ];
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        for bad in cases {
+            let config = Args::parse_from([
+                "kramer",
+                "-i", "faulty",
+                "-s", "512",
+                "-c", "8",
+            ]);
+
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                config,
+                FaultyCursor::new(source.to_owned(), sector_size, &bad),
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            let is_bad = |sector: usize| bad.iter().any(|r| r.contains(&sector));
+
+            let damaged: Vec<usize> = recover.map()
+                .get_domains(Stage::Damaged)
+                .iter()
+                .flat_map(|d| d.start..d.end)
+                .collect();
+            let recovered: Vec<usize> = recover.map()
+                .get_domains(Stage::Recovered)
+                .iter()
+                .flat_map(|d| d.start..d.end)
+                .collect();
+            let copied = recover.output().get_ref();
+
+            assert!(
+                damaged.iter().copied().eq((0..sectors).filter(|&s| is_bad(s))),
+                "Expected sectors {:?} damaged, got {:?}.",
+                bad, damaged
+            );
+            assert!(
+                recovered.iter().copied().eq((0..sectors).filter(|&s| !is_bad(s))),
+                "Expected all but {:?} recovered, got {:?}.",
+                bad, recovered
+            );
+
+            for sector in 0..sectors {
+                let range = sector * sector_size..(sector + 1) * sector_size;
+
+                assert!(
+                    is_bad(sector) || copied[range.to_owned()] == source[range],
+                    "Sector {} differs from source, with {:?} bad.",
+                    sector, bad
+                )
+            }
+        }
+    }
+
+    /// Pinpoint over data, flagging sectors in bad.
+    #[derive(Debug)]
+    struct MockPinpoint {
+        data: Vec<u8>,
+        bad: Vec<std::ops::Range<usize>>,
+    }
+
+    impl Pinpoint for MockPinpoint {
+        fn read_flagged(
+            &mut self,
+            buf: &mut [u8],
+            offset: u64,
+            sector_size: usize,
+        ) -> io::Result<Vec<usize>> {
+            let first = offset as usize / sector_size;
+
+            buf.copy_from_slice(&self.data[offset as usize..offset as usize + buf.len()]);
+
+            Ok((0..buf.len() / sector_size)
+                .filter(|i| self.bad.iter().any(|r| r.contains(&(first + i))))
+                .collect())
+        }
+    }
+
+    // Test for Recover::run() with a Pinpoint set
+    #[test]
+    fn test_isolate_pinpointed() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+        let bad = vec![10..11, 13..14];
+
+        let (source, map) = fixture(sector_size, sectors);
+        let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "pinpoint", "-s", "512", "-c", "8", "-b", "1"]),
+            LoggingReader {
+                inner: FaultyCursor::new(source.to_owned(), sector_size, &bad),
+                reads: std::rc::Rc::clone(&reads),
+            },
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover
+            .set_pinpoint(Box::new(MockPinpoint { data: source.to_owned(), bad }))
+            .run()
+            .unwrap();
+
+        // Halves of the failed cluster, then each flagged sector alone,
+        // never the levels between.
+        let expected: Vec<u64> = [0, 8, 16, 24, 8, 12, 10, 13]
+            .iter()
+            .map(|&s| (s * sector_size) as u64)
+            .collect();
+
+        assert!(
+            *reads.borrow() == expected,
+            "Expected reads at {:?}, got {:?}.",
+            expected, reads.borrow()
+        );
+        assert!(
+            recover.map().get_domains(Stage::Damaged) == vec![
+                Domain { start: 10, end: 11 },
+                Domain { start: 13, end: 14 },
+            ],
+            "Expected only the flagged sectors damaged, got {:?}.",
+            recover.map()
+        );
+
+        for sector in (0..sectors).filter(|&s| s != 10 && s != 13) {
+            let range = sector * sector_size..(sector + 1) * sector_size;
+
+            assert!(
+                recover.output().get_ref()[range.to_owned()] == source[range],
+                "Expected sector {} copied, flagged or not.",
+                sector
+            )
+        }
+    }
+
+    // Test for Recover::run() with --zero-is-suspect
+    #[test]
+    fn test_zero_is_suspect() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        // A drive giving zeros for sectors 10 to 13, rather than failing.
+        let (mut source, _) = fixture(sector_size, sectors);
+        source[10 * sector_size..13 * sector_size].fill(0);
+
+        let cases = vec![
+            (false, vec![], vec![Domain { start: 0, end: 32 }]),
+            (
+                true,
+                vec![Domain { start: 10, end: 13 }],
+                vec![Domain { start: 0, end: 10 }, Domain { start: 13, end: 32 }],
+            ),
+        ];
+
+        for (is_suspect, damaged, recovered) in cases {
+            let mut args = vec!["kramer", "-i", "zeros", "-s", "512", "-c", "8"];
+
+            if is_suspect {
+                args.push("--zero-is-suspect");
+            }
+
+            let (_, map) = fixture(sector_size, sectors);
+            let mut recover = Recover::new(
+                Args::parse_from(args),
+                FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                io::Cursor::new(vec![0xffu8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            let map = recover.map();
+
+            assert!(
+                map.get_domains(Stage::Damaged) == damaged
+                && map.get_domains(Stage::Recovered) == recovered,
+                "Expected {:?} damaged and {:?} recovered with zero_is_suspect {}, got {:?}.",
+                damaged, recovered, is_suspect, map
+            );
+            // Zeros never read as data are never written either.
+            assert!(
+                !is_suspect
+                || recover.output().get_ref()[10 * sector_size..13 * sector_size]
+                    .iter()
+                    .all(|&b| b == 0xff),
+                "Expected suspect zeros left unwritten."
+            )
+        }
+    }
+
+    // Test for Recover::read_histogram()
+    #[test]
+    fn test_read_histogram() {
+        let sector_size: usize = 512;
+        let config = Args::parse_from([
+            "kramer",
+            "-i", "faulty",
+            "-s", "512",
+            "-c", "8",
+            "-b", "1",
+        ]);
+
+        let (source, map) = fixture(sector_size, 32);
+        let mut recover = Recover::new(
+            config,
+            FaultyCursor::new(source.to_owned(), sector_size, &[10..11, 26..27]),
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        // Each pass halves the failed read before it, reading the good half.
+        let expected = vec![
+            ReadSize { sectors: 1, reads: 4, failed: 2 },
+            ReadSize { sectors: 2, reads: 4, failed: 2 },
+            ReadSize { sectors: 4, reads: 4, failed: 2 },
+            ReadSize { sectors: 8, reads: 4, failed: 2 },
+        ];
+        let recieved = recover.read_histogram().sizes();
+
+        assert!(
+            recieved == expected,
+            "Expected reads {:?}, got {:?}.",
+            expected, recieved
+        )
+    }
+
+    /// Logger keeping every record, for tests to look through.
+    struct CaptureLog(std::sync::Mutex<Vec<(log::Level, String)>>);
+
+    impl log::Log for CaptureLog {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE_LOG: CaptureLog = CaptureLog(std::sync::Mutex::new(vec![]));
+
+    /// Records logged so far, by every test, once capturing.
+    fn captured_logs() -> Vec<(log::Level, String)> {
+        static INIT: std::sync::Once = std::sync::Once::new();
+
+        INIT.call_once(|| {
+            log::set_logger(&CAPTURE_LOG).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+
+        CAPTURE_LOG.0.lock().unwrap().to_owned()
+    }
+
+    // Test for read errors in Recover::run() being logged
+    #[test]
+    fn test_read_error_logged() {
+        let sector_size: usize = 512;
+        let sectors: usize = 77;
+
+        // Start capturing before the run.
+        captured_logs();
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        Recover::new(
+            Args::parse_from(["kramer", "-i", "logged", "-s", "512", "-c", "4"]),
+            FaultyCursor::new(vec![0xaa; sectors * sector_size], sector_size, &[std::ops::Range { start: 61, end: 62 }]),
+            io::Cursor::new(vec![0u8; sectors * sector_size]),
+            map,
+        ).unwrap().run().unwrap();
+
+        let logs = captured_logs();
+
+        // Once reading the whole cluster, then the isolated bad sector.
+        for range in ["60..64", "61..62"] {
+            assert!(
+                logs.iter().any(|(level, msg)| *level == log::Level::Warn
+                    && msg.contains(&format!("sectors {}:", range))),
+                "Expected a warning for failing to read sectors {}, got {:?}.",
+                range, logs
+            )
+        }
+    }
+
+    /// In memory input, overwriting a sector before its nth read.
+    #[derive(Debug)]
+    struct ChangingReader {
+        data: io::Cursor<Vec<u8>>,
+        reads: usize,
+        nth: usize,
+        changed: std::ops::Range<usize>,
+    }
+
+    impl Read for ChangingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+
+            if self.reads == self.nth {
+                self.data.get_mut()[self.changed.to_owned()].fill(0x55);
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for ChangingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for ChangingReader {}
+
+    // Test for Recover::run() with --sample-source
+    #[test]
+    fn test_sample_source() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        // Start capturing before the run.
+        captured_logs();
+
+        // Sectors 0, 8, 16 and 24 are sampled, then clusters read from 0,
+        // sector 16 changing as 8..16 is read.
+        let input = ChangingReader {
+            data: io::Cursor::new(vec![0xaa; sectors * sector_size]),
+            reads: 0,
+            nth: 6,
+            changed: 16 * sector_size..17 * sector_size,
+        };
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from([
+                "kramer", "-i", "sample_source", "-s", "512", "-c", "8", "--sample-source", "4",
+            ]),
+            input,
+            io::Cursor::new(vec![]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let logs = captured_logs();
+        let samples = &recover.map().samples;
+        let changed: Vec<usize> = samples.iter()
+            .filter(|s| s.is_changed())
+            .map(|s| s.sector)
+            .collect();
+
+        assert!(
+            samples.iter().map(|s| s.sector).eq([0, 8, 16, 24])
+            && samples.iter().all(|s| s.after.is_some())
+            && changed == vec![16],
+            "Expected sectors 0, 8, 16 and 24 sampled, only 16 changed, got {:?}.",
+            samples
+        );
+        assert!(
+            logs.iter().any(|(level, msg)| *level == log::Level::Warn
+                && msg.contains("Sampled sectors 16..17 read differently")),
+            "Expected a warning that sector 16 changed, got {:?}.",
+            logs
+        );
+        assert!(
+            !logs.iter().any(|(_, msg)| msg.contains("Sampled sectors 8..9 read differently")),
+            "Expected no warning for unchanged sector 8."
+        )
+    }
+
+    // Test for Recover::copy_isolate() with --max-isolation-level
+    #[test]
+    fn test_max_isolation_level() {
+        let sector_size: usize = 512;
+        let sectors: usize = 64;
+
+        let cases = vec![
+            ("1", 1),
+            ("3", 3),
+            // Clusters of 64 sectors are single sectors by level 5 anyway.
+            ("10", 6),
+        ];
+
+        for (max_level, expected) in cases {
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                Args::parse_from([
+                    "kramer", "-i", "isolate", "-s", "512", "-c", "64", "-b", "1",
+                    "--max-isolation-level", max_level,
+                ]),
+                FaultyCursor::new(
+                    vec![0xaa; sectors * sector_size],
+                    sector_size,
+                    &[std::ops::Range { start: 20, end: 21 }],
+                ),
+                io::Cursor::new(vec![0u8; sectors * sector_size]),
+                map,
+            ).unwrap();
+            recover.copy_untested().unwrap();
+
+            let mut rounds = 0;
+
+            while let Stage::ForIsolation(level) = recover.map().get_stage() {
+                recover.copy_isolate(level).unwrap();
+                rounds += 1;
+            }
+
+            assert!(
+                rounds == expected
+                && recover.map().get_domains(Stage::Damaged) == vec![Domain { start: 20, end: 21 }],
+                "Expected sector 20 damaged after {} rounds with --max-isolation-level {}, \
+                got {} rounds and {:?}.",
+                expected, max_level, rounds, recover.map()
+            )
+        }
+    }
+
+    /// In memory input, failing any read which touches sector bad,
+    /// except reads of it alone after the first fails of them.
+    #[derive(Debug)]
+    struct IntermittentReader {
+        data: io::Cursor<Vec<u8>>,
+        sector_size: u64,
+        bad: u64,
+        fails: usize,
+        alone: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl Read for IntermittentReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.data.position() / self.sector_size;
+            let end = start + (buf.len() as u64).div_ceil(self.sector_size);
+
+            if (start..end).contains(&self.bad) {
+                if end - start > 1 {
+                    return Err(io::Error::other("Bad sector"));
+                }
+
+                self.alone.set(self.alone.get() + 1);
+
+                if self.alone.get() <= self.fails {
+                    return Err(io::Error::other("Bad sector, for now"));
+                }
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for IntermittentReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for IntermittentReader {}
+
+    // Test for Recover::copy_isolate() with --confirm-damaged
+    #[test]
+    fn test_confirm_damaged() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let cases = vec![
+            ("1", usize::MAX, Stage::Damaged),
+            ("3", usize::MAX, Stage::Damaged),
+            // Read on the third try, so never confirmed damaged.
+            ("3", 2, Stage::Recovered),
+            ("2", 2, Stage::Damaged),
+        ];
+
+        for (confirm, fails, expected) in cases {
+            let (_, map) = fixture(sector_size, sectors);
+            let alone = std::rc::Rc::new(std::cell::Cell::new(0));
+
+            let mut recover = Recover::new(
+                Args::parse_from([
+                    "kramer", "-i", "confirm", "-s", "512", "-c", "8", "-b", "1",
+                    "--confirm-damaged", confirm,
+                ]),
+                IntermittentReader {
+                    data: io::Cursor::new(vec![0xaa; sectors * sector_size]),
+                    sector_size: sector_size as u64,
+                    bad: 20,
+                    fails,
+                    alone: std::rc::Rc::clone(&alone),
+                },
+                io::Cursor::new(vec![0u8; sectors * sector_size]),
+                map,
+            ).unwrap();
+            recover.copy_untested().unwrap();
+
+            // Failures of sector 20 alone by the time it was mapped damaged.
+            let damaged = vec![Domain { start: 20, end: 21 }];
+            let mut confirmed_after = None;
+
+            while let Stage::ForIsolation(level) = recover.map().get_stage() {
+                recover.copy_isolate(level).unwrap();
+
+                let is_damaged = recover.map().get_domains(Stage::Damaged) == damaged;
+
+                if confirmed_after.is_none() && is_damaged {
+                    confirmed_after = Some(alone.get());
+                }
+            }
+
+            let recieved = recover.map().get_stage();
+            let limit: usize = confirm.parse().unwrap();
+
+            assert!(
+                recieved == expected
+                && recover.map().crop(Domain { start: 20, end: 21 }).get_stage() == expected
+                && match expected {
+                    Stage::Damaged => confirmed_after == Some(limit),
+                    _ => confirmed_after.is_none(),
+                },
+                "Expected sector 20 {:?} with --confirm-damaged {}, failing {} times, \
+                got {:?} after {:?} failures.",
+                expected, confirm, fails, recover.map(), confirmed_after
+            )
+        }
+    }
+
+    /// In memory input, counting reads where another thread can see.
+    #[derive(Debug)]
+    struct CountingReader {
+        data: io::Cursor<Vec<u8>>,
+        reads: std::sync::Arc<AtomicUsize>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for CountingReader {}
+
+    /// Writer into a buffer which can be read while it's written to.
+    struct SharedWriter(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Test for Recover::run() paused and resumed through set_control()
+    #[test]
+    fn test_control_pause() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let reads = std::sync::Arc::new(AtomicUsize::new(0));
+        let responses = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let (tx, rx) = mpsc::channel();
+
+        // Applied once the first cluster is copied.
+        tx.send(Ok(Command::Pause)).unwrap();
+        tx.send(Err(String::from("Invalid command \"eject\""))).unwrap();
+
+        // Reads made while paused, before resuming.
+        let paused = {
+            let (reads, tx) = (std::sync::Arc::clone(&reads), tx.clone());
+
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                let paused = reads.load(Ordering::SeqCst);
+
+                tx.send(Ok(Command::Resume)).unwrap();
+                paused
+            })
+        };
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "control", "-s", "512", "-c", "8"]),
+            CountingReader {
+                data: io::Cursor::new(vec![0xaa; sectors * sector_size]),
+                reads: std::sync::Arc::clone(&reads),
+            },
+            io::Cursor::new(vec![]),
+            map,
+        ).unwrap();
+        recover.set_control(Control {
+            commands: rx,
+            responses: Box::new(SharedWriter(std::rc::Rc::clone(&responses))),
+        });
+        recover.run().unwrap();
+
+        let paused = paused.join().unwrap();
+        let responses = String::from_utf8(responses.take()).unwrap();
+        let expected = "{\"ok\":true,\"cmd\":\"pause\"}\n\
+            {\"ok\":false,\"error\":\"Invalid command \\\"eject\\\"\"}\n\
+            {\"ok\":true,\"cmd\":\"resume\"}\n";
+
+        assert!(
+            paused == 1 && reads.load(Ordering::SeqCst) == 4,
+            "Expected only the first of 4 clusters read while paused, got {} reads.",
+            paused
+        );
+        assert!(
+            recover.stop_reason() == Some(StopReason::Complete)
+            && recover.map().get_stage() == Stage::Recovered,
+            "Expected the run to go on once resumed, got {:?}.",
+            recover.map()
+        );
+        assert!(
+            responses == expected,
+            "Expected each command answered in turn, got {:?}.",
+            responses
+        )
+    }
+
+    // Test for Recover::run() with --fill-pattern
+    #[test]
+    fn test_fill_pattern() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+        let bad = vec![3..4, 10..13];
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from([
+                "kramer", "-i", "fill", "-s", "512", "-c", "8", "--fill-pattern", "0xC0FFEE",
+            ]),
+            FaultyCursor::new(vec![0xaa; sectors * sector_size], sector_size, &bad),
+            io::Cursor::new(vec![0x11; sectors * sector_size]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let copied = recover.output().get_ref();
+
+        // Each damaged sector starts the pattern over, cut short at its end.
+        let filled: Vec<u8> = [0xc0, 0xff, 0xee].iter()
+            .copied()
+            .cycle()
+            .take(sector_size)
+            .collect();
+
+        for sector in 0..sectors {
+            let data = &copied[sector * sector_size..(sector + 1) * sector_size];
+            let is_bad = bad.iter().any(|r| r.contains(&sector));
+
+            assert!(
+                if is_bad { *data == filled[..] } else { data.iter().all(|&b| b == 0xaa) },
+                "Sector {} has unexpected data, with {:?} bad.",
+                sector, bad
+            )
+        }
+    }
+
+    /// In memory output, zeroing discarded regions as a TRIMmed device may.
+    #[derive(Debug)]
+    struct TrimmedCursor(io::Cursor<Vec<u8>>);
+
+    impl Read for TrimmedCursor {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+
+    impl Write for TrimmedCursor {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Seek for TrimmedCursor {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    impl crate::discard::Discard for TrimmedCursor {
+        fn discard(&mut self, offset: u64, len: u64) -> io::Result<()> {
+            self.0.get_mut()[offset as usize..(offset + len) as usize].fill(0);
+            Ok(())
+        }
+    }
+
+    impl Output for TrimmedCursor {
+        fn discard_unrecovered(&mut self, map: &MapFile, stages: &[Stage]) -> io::Result<()> {
+            discard_unrecovered(self, map, stages)
+        }
+    }
+
+    // Test for Recover::discard_unrecovered() with a fill pattern
+    #[test]
+    fn test_fill_pattern_discard() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+        let bad = vec![3..4, 11..12];
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        // Sectors past --end are left untested, so discarded.
+        let mut recover = Recover::new(
+            Args::parse_from([
+                "kramer", "-i", "fill", "-s", "512", "-c", "8", "--end", "24",
+                "--fill-pattern", "FF", "--discard-unrecovered",
+            ]),
+            FaultyCursor::new(vec![0xaa; sectors * sector_size], sector_size, &bad),
+            TrimmedCursor(io::Cursor::new(vec![0x11; sectors * sector_size])),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let copied = recover.output().0.get_ref();
+
+        for sector in [3, 11] {
+            assert!(
+                copied[sector * sector_size..(sector + 1) * sector_size].iter().all(|&b| b == 0xff),
+                "Expected filled damaged sector {} kept.",
+                sector
+            )
+        }
+
+
+        assert!(
+            copied[24 * sector_size..].iter().all(|&b| b == 0),
+            "Expected the untested sectors discarded."
+        )
+    }
+
+    /// Records the offset of every read, before passing it on.
+    #[derive(Debug)]
+    struct LoggingReader {
+        inner: FaultyCursor,
+        reads: std::rc::Rc<std::cell::RefCell<Vec<u64>>>,
+    }
+
+    impl Read for LoggingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.borrow_mut().push(self.inner.data.position());
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for LoggingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl Input for LoggingReader {}
+
+    /// In memory input, for workers to share.
+    #[derive(Debug)]
+    struct SharedData(std::sync::Arc<Vec<u8>>);
+
+    impl ReadAt for SharedData {
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            let data = self.0
+                .get(offset as usize..offset as usize + buf.len())
+                .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    // Test for Recover::copy_untested() with workers
+    #[test]
+    fn test_copy_untested_parallel() {
+        let sector_size: usize = 512;
+        let sectors: usize = 1027;
+
+        let (source, _) = fixture(sector_size, sectors);
+        let shared = std::sync::Arc::new(source.to_owned());
+
+        let mut results = vec![];
+
+        for threads in [1, 4] {
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                Args::parse_from(["kramer", "-i", "parallel", "-s", "512", "-c", "8"]),
+                io::Cursor::new(source.to_owned()),
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+
+            if threads > 1 {
+                let workers: Vec<Box<dyn ReadAt>> = (0..threads)
+                    .map(|_| Box::new(SharedData(std::sync::Arc::clone(&shared))) as _)
+                    .collect();
+
+                recover.set_workers(workers);
+            }
+
+            recover.run().unwrap();
+
+            results.push((recover.map().to_owned(), recover.output().get_ref().to_owned()));
+        }
+
+        let (single_map, single) = &results[0];
+        let (parallel_map, parallel) = &results[1];
+
+        assert!(
+            parallel_map == single_map,
+            "Expected the same map in parallel, got {:?} against {:?}.",
+            parallel_map, single_map
+        );
+        assert!(
+            *parallel == source && parallel == single,
+            "Expected the same output in parallel."
+        )
+    }
+
+    // Test for Recover::copy_untested() with --skip-size
+    #[test]
+    fn test_copy_untested_skip() {
+        let sector_size: usize = 512;
+        let sectors: usize = 2048;
+        let bad = vec![64..1984, 2040..2041];
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        let mut results = vec![];
+
+        for skip in [None, Some("64K")] {
+            let mut argv = vec!["kramer", "-i", "skip", "-s", "512", "-c", "8"];
+            argv.extend(skip.iter().flat_map(|size| ["--skip-size", size]));
+
+            let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                Args::parse_from(&argv),
+                LoggingReader {
+                    inner: FaultyCursor::new(source.to_owned(), sector_size, &bad),
+                    reads: std::rc::Rc::clone(&reads),
+                },
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            // Reads until the first past the bad region.
+            let past = (bad[0].end * sector_size) as u64;
+            let reads_to_cross = reads.borrow()
+                .iter()
+                .position(|&offset| offset >= past)
+                .unwrap();
+
+            results.push((
+                reads_to_cross,
+                recover.map().to_owned(),
+                recover.output().get_ref().to_owned(),
+            ));
+        }
+
+        let (naive_reads, naive_map, naive_output) = &results[0];
+        let (skip_reads, skip_map, skip_output) = &results[1];
+
+        assert!(
+            skip_reads * 8 < *naive_reads && *naive_reads < bad[0].len(),
+            "Expected far fewer reads to cross the bad region skipping, got {} against {}.",
+            skip_reads, naive_reads
+        );
+        assert!(
+            skip_map == naive_map && skip_output == naive_output,
+            "Expected the same result skipping, got {:?}.",
+            skip_map
+        )
+    }
+
+    // Test for Recover::copy_untested() with --gentle-start
+    #[test]
+    fn test_copy_untested_gentle_start() {
+        let sector_size: usize = 512;
+        let sectors: usize = 48;
+
+        let (source, map) = fixture(sector_size, sectors);
+        let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "gentle", "-s", "512", "-c", "8", "--gentle-start"]),
+            LoggingReader {
+                inner: FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                reads: std::rc::Rc::clone(&reads),
+            },
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        // Reads are sequential, so each runs to where the next starts.
+        let mut offsets: Vec<usize> = reads.borrow()
+            .iter()
+            .map(|&offset| offset as usize / sector_size)
+            .collect();
+        offsets.push(sectors);
+
+        let recieved: Vec<usize> = offsets.windows(2).map(|w| w[1] - w[0]).collect();
+        // Doubling every four reads, the last of four sectors
+        // finishing the cluster it started in.
+        let expected = vec![1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 8, 8];
+
+        assert!(
+            recieved == expected,
+            "Expected reads of {:?} sectors, got {:?}.",
+            expected, recieved
+        );
+        assert!(
+            *recover.output().get_ref() == source,
+            "Expected everything copied, read gently or not."
+        )
+    }
+
+    // Test for Recover::copy_untested() with --interleave
+    #[test]
+    fn test_copy_interleaved() {
+        let sector_size: usize = 512;
+        let sectors: usize = 256;
+        let bad = vec![10..11, 200..201];
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        let mut results = vec![];
+
+        for interleave in [false, true] {
+            let mut argv = vec!["kramer", "-i", "interleave", "-s", "512", "-c", "8"];
+            argv.extend(interleave.then_some("--interleave"));
+
+            let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                Args::parse_from(&argv),
+                LoggingReader {
+                    inner: FaultyCursor::new(source.to_owned(), sector_size, &bad),
+                    reads: std::rc::Rc::clone(&reads),
+                },
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            // Sector 12 is only read on its own isolating the cluster at 8,
+            // and 128 starts the second stretch of 16 clusters.
+            let position = |sector: usize| reads.borrow()
+                .iter()
+                .position(|&offset| offset == (sector * sector_size) as u64)
+                .unwrap();
+
+            results.push((
+                position(12) < position(128),
+                recover.map().to_owned(),
+                recover.output().get_ref().to_owned(),
+            ));
+        }
+
+        let (phased_first, phased_map, phased_output) = &results[0];
+        let (interleaved_first, interleaved_map, interleaved_output) = &results[1];
+
+        assert!(
+            !phased_first && *interleaved_first,
+            "Expected sectors near the first error recovered before the next stretch \
+            only interleaved."
+        );
+        assert!(
+            interleaved_map == phased_map && interleaved_output == phased_output,
+            "Expected the same result interleaved, got {:?}.",
+            interleaved_map
+        )
+    }
+
+    // Test for Recover::prefill()
+    #[test]
+    fn test_prefill() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+        let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        // An earlier rescue, which gave up on sectors 8 to 16.
+        let prefill_map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 8), stage: Recovered),
+                (domain: (start: 8, end: 16), stage: Damaged),
+                (domain: (start: 16, end: 24), stage: Verified),
+                (domain: (start: 24, end: 32), stage: Untested),
+            ],
+        )").unwrap();
+        let mut image = io::Cursor::new(source.to_owned());
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "prefill", "-s", "512", "-c", "8"]),
+            LoggingReader {
+                inner: FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                reads: std::rc::Rc::clone(&reads),
+            },
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.prefill(&mut image, &prefill_map).unwrap().run().unwrap();
+
+        let is_prefilled = |offset: &u64| {
+            let sector = *offset as usize / sector_size;
+            sector < 8 || (16..24).contains(&sector)
+        };
+
+        assert!(
+            !reads.borrow().iter().any(is_prefilled),
+            "Expected prefilled sectors never read from the input, got reads at {:?}.",
+            reads.borrow()
+        );
+        assert!(
+            recover.map().get_domains(Stage::Recovered) == vec![Domain { start: 0, end: 32 }]
+            && *recover.output().get_ref() == source,
+            "Expected everything recovered, got {:?}.",
+            recover.map()
+        )
+    }
+
+    // Test for Recover::run() after Recover::set_ranges()
+    #[test]
+    fn test_run_ranges() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+        let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let ranges = [Domain { start: 20, end: 24 }, Domain { start: 4, end: 8 }];
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "ranges", "-s", "512", "-c", "8"]),
+            LoggingReader {
+                inner: FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                reads: std::rc::Rc::clone(&reads),
+            },
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.set_ranges(&ranges).run().unwrap();
+
+        let in_ranges = |offset: &u64| {
+            let sector = *offset as usize / sector_size;
+            ranges.iter().any(|r| r.contains(sector))
+        };
+
+        assert!(
+            reads.borrow().iter().all(in_ranges),
+            "Expected only sectors in {:?} read, got reads at {:?}.",
+            ranges, reads.borrow()
+        );
+        assert!(
+            recover.map().get_domains(Stage::Recovered) == vec![ranges[1], ranges[0]]
+            && recover.map().get_domains(Stage::Untested) == vec![
+                Domain { start: 0, end: 4 },
+                Domain { start: 8, end: 20 },
+                Domain { start: 24, end: 32 },
+            ],
+            "Expected only {:?} recovered, got {:?}.",
+            ranges, recover.map()
+        )
+    }
+
+    // Test for Recover::run() with --sweep
+    #[test]
+    fn test_copy_sweep() {
+        let sector_size: usize = 512;
+        let sectors: usize = 64;
+        let bad = vec![10..11, 13..15, 44..45];
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        let mut results = vec![];
+
+        for sweep in [false, true] {
+            let mut argv = vec!["kramer", "-i", "sweep", "-s", "512", "-c", "8"];
+            argv.extend(sweep.then_some("--sweep"));
+
+            let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let (_, mut map) = fixture(sector_size, sectors);
+
+            // Left isolating by an earlier run.
+            map.update(Cluster::new(Domain { start: 40, end: 48 }, Stage::ForIsolation(1)));
+
+            let mut recover = Recover::new(
+                Args::parse_from(&argv),
+                LoggingReader {
+                    inner: FaultyCursor::new(source.to_owned(), sector_size, &bad),
+                    reads: std::rc::Rc::clone(&reads),
+                },
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            results.push((
+                reads.borrow().is_sorted(),
+                recover.map().to_owned(),
+                recover.output().get_ref().to_owned(),
+            ));
+        }
+
+        let (phased_forward, phased_map, phased_output) = &results[0];
+        let (sweep_forward, sweep_map, sweep_output) = &results[1];
+
+        assert!(
+            !phased_forward && *sweep_forward,
+            "Expected the input never read backwards only sweeping."
+        );
+        assert!(
+            sweep_map == phased_map && sweep_output == phased_output,
+            "Expected the same result sweeping, got {:?}.",
+            sweep_map
+        )
+    }
+
+    /// Reader which, on its nth read, loads the map saved at map_path.
+    struct SnoopReader {
+        data: io::Cursor<Vec<u8>>,
+        map_path: PathBuf,
+        reads: usize,
+        nth: usize,
+        snapshot: std::rc::Rc<std::cell::RefCell<Option<MapFile>>>,
+    }
+
+    impl Read for SnoopReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+
+            if self.reads == self.nth {
+                *self.snapshot.borrow_mut() = Some(
+                    MapFile::try_from(File::open(&self.map_path).unwrap()).unwrap()
+                );
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for SnoopReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for SnoopReader {}
+
+    // Test for Recover::plan()
+    #[test]
+    fn test_plan() {
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 12), stage: Recovered),
+                (domain: (start: 12, end: 16), stage: ForIsolation(0)),
+                (domain: (start: 16, end: 32), stage: Damaged),
+            ],
+        )").unwrap();
+
+        let cases = vec![
+            (vec!["kramer", "-i", "plan", "-s", "512", "-c", "4"], vec![12..14, 14..16]),
+            (vec!["kramer", "-i", "plan", "-s", "512", "-c", "4", "--end", "8"], vec![]),
+            (
+                vec!["kramer", "-i", "plan", "-s", "512", "-c", "4", "--end", "8", "--verify"],
+                vec![0..4, 4..8],
+            ),
+        ];
+
+        for (argv, expected) in cases {
+            let recover = Recover::new(
+                Args::parse_from(&argv),
+                io::Cursor::new(vec![]),
+                io::Cursor::new(vec![]),
+                map.to_owned(),
+            ).unwrap();
+            let plan: Vec<std::ops::Range<usize>> = recover.plan()
+                .iter()
+                .map(|c| c.domain().start..c.domain().end)
+                .collect();
+
+            assert!(
+                plan == expected,
+                "Expected {:?} planned for {:?}, got {:?}.",
+                expected, argv, plan
+            )
+        }
+    }
+
+    /// Keeps every progress reported.
+    #[derive(Debug)]
+    struct RecordReport(std::rc::Rc<std::cell::RefCell<Vec<Progress>>>);
+
+    impl Report for RecordReport {
+        fn report(&mut self, progress: &Progress) {
+            self.0.borrow_mut().push(*progress);
+        }
+    }
+
+    // Test for Recover::progress()
+    #[test]
+    fn test_progress() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let reports = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "progress", "-s", "512", "-c", "8"]),
+            FaultyCursor::new(vec![0xaa; sectors * sector_size], sector_size, &[4..5, 20..21]),
+            io::Cursor::new(vec![0u8; sectors * sector_size]),
+            map,
+        ).unwrap();
+        recover
+            .set_reporter(Box::new(RecordReport(std::rc::Rc::clone(&reports))))
+            .run().unwrap();
+
+        let reports = reports.borrow();
+        let first = reports.first().unwrap();
+        let last = reports.last().unwrap();
+
+        assert!(
+            (first.recovered, first.total, first.untested) == (0, sectors, 24)
+            && first.stage == Stage::Untested,
+            "Expected the first cluster failed at the first report, got {:?}.",
+            first
+        );
+        assert!(
+            (last.recovered, last.total, last.untested) == (sectors - 2, sectors, 0)
+            && last.stage == Stage::Damaged,
+            "Expected all but the bad sectors recovered, got {:?}.",
+            last
+        );
+        // Failed reads don't count towards throughput.
+        assert!(
+            first.bytes_per_sec.is_none() && last.bytes_per_sec.is_some(),
+            "Expected throughput only once reads succeed, got {:?} then {:?}.",
+            first.bytes_per_sec, last.bytes_per_sec
+        )
+    }
+
+    // Test for Recover::autosave()
+    #[test]
+    fn test_autosave() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let map_path = scratch_path("autosave.map");
+
+        let output = io::Cursor::new(vec![]);
+        let snapshot = std::rc::Rc::new(std::cell::RefCell::new(None));
+
+        let input = SnoopReader {
+            data: io::Cursor::new(vec![0xaa; sectors * sector_size]),
+            map_path: map_path.to_owned(),
+            reads: 0,
+            nth: 3,
+            snapshot: std::rc::Rc::clone(&snapshot),
+        };
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from([
+                "kramer", "-i", "autosave", "-s", "512", "-c", "8", "--save-interval", "0",
+            ]),
+            input,
+            output,
+            map,
+        ).unwrap();
+        recover.set_map_path(map_path.to_owned()).run().unwrap();
+
+        fs::remove_file(&map_path).unwrap();
+        fs::remove_file(scratch_path("autosave.map.bak")).unwrap();
+
+        // Two clusters of 8 sectors were copied before the third read.
+        let expected: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 16), stage: Recovered),
+                (domain: (start: 16, end: 32), stage: Untested),
+            ],
+        )").unwrap();
+        let recieved = snapshot.borrow().to_owned();
+
+        assert!(
+            recieved.as_ref() == Some(&expected),
+            "Expected {:?} saved partway through, got {:?}.",
+            expected, recieved
+        )
+    }
+
+    /// Reader which sets stop on its nth read.
+    struct StoppingReader {
+        data: io::Cursor<Vec<u8>>,
+        reads: usize,
+        nth: usize,
+        stop: &'static AtomicBool,
+    }
+
+    impl Read for StoppingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+
+            if self.reads == self.nth {
+                self.stop.store(true, Ordering::SeqCst);
+            }
+
+            self.data.read(buf)
+        }
+    }
+
+    impl Seek for StoppingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for StoppingReader {}
+
+    // Test for Recover::run() stopping when flagged
+    #[test]
+    fn test_run_stop() {
+        static STOP: AtomicBool = AtomicBool::new(false);
+
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let input = StoppingReader {
+            data: io::Cursor::new(vec![0xaa; sectors * sector_size]),
+            reads: 0,
+            nth: 2,
+            stop: &STOP,
+        };
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "stop", "-s", "512", "-c", "8"]),
+            input,
+            io::Cursor::new(vec![]),
+            map,
+        ).unwrap();
+        recover.set_stop_flag(&STOP).run().unwrap();
+
+        // The cluster being read when flagged is still finished.
+        let expected: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 16), stage: Recovered),
+                (domain: (start: 16, end: 32), stage: Untested),
+            ],
+        )").unwrap();
+
+        assert!(recover.is_stopped());
+        assert!(
+            recover.stop_reason() == Some(StopReason::Interrupted),
+            "Expected the run interrupted, got {:?}.",
+            recover.stop_reason()
+        );
+        assert!(
+            *recover.map() == expected,
+            "Expected {:?} after stopping, got {:?}.",
+            expected, recover.map()
+        )
+    }
+
+    /// Reader over data, corrupting the second read of any offset in flaky.
+    #[derive(Debug)]
+    struct FlakyReader {
+        data: io::Cursor<Vec<u8>>,
+        flaky: std::ops::Range<u64>,
+        reads: std::collections::HashMap<u64, usize>,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let start = self.data.position();
+            let reads = self.reads.entry(start).or_insert(0);
+            *reads += 1;
+
+            let len = self.data.read(buf)?;
+
+            if *reads == 2 && self.flaky.contains(&start) {
+                buf[..len].iter_mut().for_each(|b| *b = !*b);
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl Seek for FlakyReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Input for FlakyReader {}
+
+    // Test for Recover::verify_pass()
+    #[test]
+    fn test_verify_pass() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+
+        let output = io::Cursor::new(vec![0u8; source.len()]);
+
+        let input = FlakyReader {
+            data: io::Cursor::new(source.to_owned()),
+            flaky: (8 * sector_size) as u64..(16 * sector_size) as u64,
+            reads: Default::default(),
+        };
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "flaky", "-s", "512", "-c", "8"]),
+            input,
+            output,
+            map,
+        ).unwrap();
+        recover.run().unwrap().verify_pass().unwrap();
+
+        let expected: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [
+                (domain: (start: 0, end: 8), stage: Verified),
+                (domain: (start: 8, end: 16), stage: ForIsolation(0)),
+                (domain: (start: 16, end: 32), stage: Verified),
+            ],
+        )").unwrap();
+
+        assert!(
+            *recover.map() == expected,
+            "Expected {:?} after verifying, got {:?}.",
+            expected, recover.map()
+        )
+    }
+
+    // Test for Recover::run() resuming an interrupted --verify
+    #[test]
+    fn test_verify_resume() {
+        static STOP: AtomicBool = AtomicBool::new(false);
+
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, _) = fixture(sector_size, sectors);
+        let recovered: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 32),
+            map: [(domain: (start: 0, end: 32), stage: Recovered)],
+        )").unwrap();
+        let config = || Args::parse_from(["kramer", "-i", "verify", "-s", "512", "-c", "8", "--verify"]);
+
+        // Interrupted while verifying the second cluster.
+        let mut recover = Recover::new(
+            config(),
+            StoppingReader {
+                data: io::Cursor::new(source.to_owned()),
+                reads: 0,
+                nth: 2,
+                stop: &STOP,
+            },
+            io::Cursor::new(source.to_owned()),
+            recovered,
+        ).unwrap();
+        recover.set_stop_flag(&STOP).run().unwrap();
+
+        // As saved on SIGINT, and loaded to resume.
+        let saved: MapFile = ron::de::from_str(&ron::ser::to_string(recover.map()).unwrap()).unwrap();
+
+        assert!(
+            saved.get_domains(Stage::Verified) == vec![Domain { start: 0, end: 16 }],
+            "Expected the clusters verified before stopping saved as such, got {:?}.",
+            saved
+        );
+
+        let reads = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let mut recover = Recover::new(
+            config(),
+            LoggingReader {
+                inner: FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                reads: std::rc::Rc::clone(&reads),
+            },
+            io::Cursor::new(source.to_owned()),
+            saved,
+        ).unwrap();
+        recover.run().unwrap();
+
+        assert!(
+            *reads.borrow() == vec![(16 * sector_size) as u64, (24 * sector_size) as u64],
+            "Expected only clusters left unverified read on resuming, got reads at {:?}.",
+            reads.borrow()
+        );
+        assert!(
+            recover.map().get_domains(Stage::Verified) == vec![Domain { start: 0, end: 32 }],
+            "Expected everything verified once resumed, got {:?}.",
+            recover.map()
+        )
+    }
+
+    // Test for Recover::run() with --verify-after-pass
+    #[test]
+    fn test_verify_after_pass() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+        let reports = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        let input = FlakyReader {
+            data: io::Cursor::new(source.to_owned()),
+            flaky: (8 * sector_size) as u64..(16 * sector_size) as u64,
+            reads: Default::default(),
+        };
+
+        let mut recover = Recover::new(
+            Args::parse_from([
+                "kramer",
+                "-i", "flaky",
+                "-s", "512",
+                "-c", "8",
+                "--verify-after-pass",
+            ]),
+            input,
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.set_reporter(Box::new(RecordReport(std::rc::Rc::clone(&reports))));
+        recover.run().unwrap();
+
+        // Every read succeeds, so only the mismatch requeues sectors 8 to 16.
+        let isolated = reports.borrow()
+            .iter()
+            .any(|p| p.stage == Stage::ForIsolation(0));
+
+        assert!(isolated, "Expected the mismatched cluster isolated by the next pass.");
+        assert!(
+            recover.map().get_domains(Stage::Verified) == vec![Domain { start: 0, end: 32 }]
+            && *recover.output().get_ref() == source,
+            "Expected everything copied and verified, got {:?}.",
+            recover.map()
+        )
+    }
+
+    // Test for Recover::summary()
+    #[test]
+    fn test_summary() {
+        let map: MapFile = ron::de::from_str("(
+            sector_size: 512,
+            domain: (start: 0, end: 16),
+            map: [
+                (domain: (start: 0, end: 6), stage: Recovered),
+                (domain: (start: 6, end: 8), stage: Untested),
+                (domain: (start: 8, end: 9), stage: ForIsolation(2)),
+                (domain: (start: 9, end: 12), stage: Damaged),
+                (domain: (start: 12, end: 16), stage: Recovered),
+            ],
+        )").unwrap();
+
+        let recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "summary", "-s", "512", "-c", "8"]),
+            io::Cursor::new(vec![]),
+            io::Cursor::new(vec![]),
+            map,
+        ).unwrap();
+        let recieved = recover.summary();
+
+        let expected = RecoveryStats {
+            sectors: 16,
+            recovered: 10,
+            untested: 2,
+            for_isolation: 1,
+            damaged: 3,
+            total_bytes: 16 * 512,
+            recovered_bytes: 10 * 512,
+            damaged_bytes: 3 * 512,
+            recovered_percent: 62.5,
+        };
+
+        assert!(
+            expected == recieved,
+            "Expected {:?}, got {:?}.",
+            expected, recieved
+        )
+    }
+
+    // Test for Recover::run() within --start and --end
+    #[test]
+    fn test_run_bounded() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let source: Vec<u8> = (0..sectors * sector_size)
+            .map(|i| (i % 251) as u8 | 1)
+            .collect();
+
+        let output = io::Cursor::new(vec![0u8; source.len()]);
+
+        let config = Args::parse_from([
+            "kramer",
+            "-i", "bounded",
+            "-s", "512",
+            "-c", "8",
+            "--start", "5",
+            "--end", "21",
+        ]);
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let input = io::Cursor::new(source.to_owned());
+
+        let mut recover = Recover::new(config, input, output, map).unwrap();
+        recover.run().unwrap();
+
+        let recovered = recover.map().get_domains(Stage::Recovered);
+        let untested = recover.map().get_domains(Stage::Untested);
+        let copied = recover.output().get_ref();
+
+        assert!(
+            recovered.iter().flat_map(|d| d.start..d.end).eq(5..21),
+            "Expected sectors 5..21 recovered, got {:?}.",
+            recovered
+        );
+        assert!(
+            untested == vec![Domain { start: 0, end: 5 }, Domain { start: 21, end: 32 }],
+            "Expected sectors outside of bounds left untested, got {:?}.",
+            untested
+        );
+        let bytes = 5 * sector_size..21 * sector_size;
+
+        assert!(
+            copied[..bytes.start].iter().all(|&b| b == 0)
+            && copied[bytes.to_owned()] == source[bytes.to_owned()]
+            && copied[bytes.end..].iter().all(|&b| b == 0),
+            "Expected only sectors 5..21 copied to output."
+        )
+    }
+
+    // Test for Recover::run() with --input-offset
+    #[test]
+    fn test_run_input_offset() {
+        let sector_size: usize = 512;
+        let sectors: usize = 64;
+        // A partition of 24 sectors, 16 sectors into the image.
+        let partition = 16 * sector_size..40 * sector_size;
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        let config = Args::parse_from([
+            "kramer",
+            "-i", "image",
+            "-s", "512",
+            "-c", "8",
+            "--input-offset", "16",
+        ]);
+
+        let map = MapFile::new(sector_size as u16)
+            .set_domain(Domain { start: 0, end: partition.len() / sector_size })
+            .to_owned();
+
+        let mut recover = Recover::new(
+            config,
+            io::Cursor::new(source.to_owned()),
+            io::Cursor::new(vec![0u8; partition.len()]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        assert!(
+            recover.map().get_stage() == Stage::Recovered,
+            "Expected the partition recovered, got {:?}.",
+            recover.map()
+        );
+        assert!(
+            *recover.output().get_ref() == source[partition],
+            "Expected the output to be the partition, from byte zero."
+        )
+    }
+
+    // Test for Recover::run() with --rate-limit
+    #[test]
+    fn test_run_rate_limit() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+        let bytes_per_sec = 64 * 1024;
+
+        let (source, map) = fixture(sector_size, sectors);
+
+        let config = Args::parse_from([
+            "kramer",
+            "-i", "paced",
+            "-s", "512",
+            "-c", "8",
+            "--rate-limit", "64K",
+        ]);
+
+        let start = Instant::now();
+        // A bad sector, so isolation is paced too.
+        let mut recover = Recover::new(
+            config,
+            FaultyCursor::new(source.to_owned(), sector_size, &[std::ops::Range { start: 10, end: 11 }]),
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        // Every sector is read at least once, and the bad cluster again.
+        let expected = Duration::from_secs_f64(
+            ((sectors + 8) * sector_size) as f64 / bytes_per_sec as f64
+        );
+
+        assert!(
+            start.elapsed() >= expected,
+            "Expected reads paced to take at least {:?}, took {:?}.",
+            expected, start.elapsed()
+        );
+        assert!(
+            recover.map().get_domains(Stage::Damaged) == vec![Domain { start: 10, end: 11 }],
+            "Expected only the bad sector damaged, got {:?}.",
+            recover.map()
+        )
+    }
+
+    // Test for Recover::run() with --reverse
+    #[test]
+    fn test_run_reverse() {
+        let sector_size: usize = 512;
+        let sectors: usize = 45;
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        let mut results: Vec<(MapFile, Vec<u8>)> = vec![];
+
+        for reverse in [false, true] {
+            let mut argv = vec!["kramer", "-i", "reverse", "-s", "512", "-c", "8"];
+            if reverse {
+                argv.push("--reverse");
+            }
+
+            let (_, map) = fixture(sector_size, sectors);
+
+            let mut recover = Recover::new(
+                Args::parse_from(argv),
+                io::Cursor::new(source.to_owned()),
+                io::Cursor::new(vec![0u8; source.len()]),
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            results.push((recover.map().to_owned(), recover.output().get_ref().to_owned()));
+        }
+
+        let (forward_map, forward) = &results[0];
+        let (reverse_map, reversed) = &results[1];
+
+        assert!(
+            forward_map == reverse_map,
+            "Expected the same map either direction, got {:?} forward and {:?} reversed.",
+            forward_map, reverse_map
+        );
+        assert!(
+            *forward == source && reversed == forward,
+            "Expected the same output either direction."
+        )
+    }
+
+    // Test for Input and Output of a File, at offsets
+    #[test]
+    fn test_file_at_offsets() {
+        let path = scratch_path("at_offsets.img");
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &data).unwrap();
+
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let (mut a, mut b) = (vec![0u8; 512], vec![0u8; 512]);
+
+        // Interleaved, so each would read the wrong data if it relied on the last.
+        for _ in 0..2 {
+            Input::read_exact_at(&mut file, &mut a, 2048).unwrap();
+            Input::read_exact_at(&mut file, &mut b, 512).unwrap();
+
+            assert!(
+                a == data[2048..2560] && b == data[512..1024],
+                "Expected interleaved reads to each match their offset."
+            )
+        }
+
+        Output::write_all_at(&mut file, &[0xff; 512], 1024).unwrap();
+        Output::read_exact_at(&mut file, &mut a, 1024).unwrap();
+
+        let pos = file.stream_position().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(a == [0xff; 512], "Expected to read back what was written.");
+        assert!(pos == 0, "Expected the file never seeked, but it's at {}.", pos)
+    }
+
+    // Test for Recover::write_cluster() with --sparse
+    // Holes are only punched on Linux.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_write_sparse() {
+        let sector_size: usize = 512;
+        let sectors: usize = 4096;
+
+        let output_path = scratch_path("write_sparse.iso");
+
+        // Data at either end of a 2 MiB run of zeros.
+        let mut source = vec![0u8; sectors * sector_size];
+        source[..3 * sector_size].fill(0xaa);
+        source[(sectors - 1) * sector_size..].fill(0x55);
+
+        let output = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&output_path)
+            .unwrap();
+        // As main autoextends it.
+        output.set_len(source.len() as u64).unwrap();
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "sparse", "-s", "512", "-c", "64", "--sparse"]),
+            io::Cursor::new(source.to_owned()),
+            output,
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let allocated = allocated_len(recover.output()).unwrap();
+        let copied = fs::read(&output_path).unwrap();
+
+        fs::remove_file(&output_path).unwrap();
+
+        assert!(
+            allocated < source.len() as u64,
+            "Expected fewer than {} bytes allocated, got {}.",
+            source.len(), allocated
+        );
+        assert!(
+            recover.map().get_domains(Stage::Recovered) == vec![Domain { start: 0, end: sectors }],
+            "Expected zeros recovered too, got {:?}.",
+            recover.map()
+        );
+        assert!(copied == source, "Output differs from source.")
+    }
+
+    /// In memory output, failing writes past limit bytes, as a full disk does.
+    #[derive(Debug)]
+    struct FullOutput {
+        data: io::Cursor<Vec<u8>>,
+        limit: u64,
+        is_synced: bool,
+    }
+
+    impl Read for FullOutput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for FullOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.data.position() + buf.len() as u64 > self.limit {
+                return Err(io::Error::from(io::ErrorKind::StorageFull));
+            }
+
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FullOutput {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Output for FullOutput {
+        fn sync(&mut self) -> io::Result<()> {
+            self.is_synced = true;
+            Ok(())
+        }
+    }
+
+    // Test for Recover::run() failing to write the output
+    #[test]
+    fn test_run_output_full() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+
+        // Room for the first two clusters of eight sectors.
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "full", "-s", "512", "-c", "8"]),
+            io::Cursor::new(source.to_owned()),
+            FullOutput {
+                data: io::Cursor::new(vec![0u8; source.len()]),
+                limit: (20 * sector_size) as u64,
+                is_synced: false,
+            },
+            map,
+        ).unwrap();
+        let result = recover.run().map(|_| ());
+
+        assert!(
+            result.as_ref().map_err(|e| e.kind()) == Err(io::ErrorKind::StorageFull),
+            "Expected the run to end with the write error, got {:?}.",
+            result
+        );
+        assert!(
+            recover.map().get_domains(Stage::Recovered) == vec![Domain { start: 0, end: 16 }]
+            && recover.map().get_domains(Stage::Untested) == vec![Domain { start: 16, end: 32 }],
+            "Expected the map up to date with what was written, got {:?}.",
+            recover.map()
+        );
+        assert!(recover.output().is_synced, "Expected what was written synced.");
+        assert!(
+            recover.stop_reason() == Some(StopReason::OutputFull),
+            "Expected the run to end with the output full, got {:?}.",
+            recover.stop_reason()
+        )
+    }
+
+    /// In memory output, whose next write fails after garbling
+    /// what it was to write, while fail_writes is set.
+    #[derive(Debug)]
+    struct FlakyOutput {
+        data: io::Cursor<Vec<u8>>,
+        fail_writes: usize,
+    }
+
+    impl Read for FlakyOutput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for FlakyOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.fail_writes > 0 {
+                self.fail_writes -= 1;
+                self.data.write_all(&vec![0xee; buf.len() / 2])?;
+                return Err(io::Error::other("Transient write failure"));
+            }
+
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for FlakyOutput {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Output for FlakyOutput {}
+
+    // Test for Recover::run() retrying a failed write
+    #[test]
+    fn test_run_output_flaky() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let cases = vec![
+            // Once retried, the write lands over what the failed one left.
+            (1, None, true),
+            // Failing again, the run ends with the error.
+            (2, None, false),
+            // Clusters gathered into the failed write are read again.
+            (1, Some("8192"), true),
+            (2, Some("8192"), false),
+        ];
+
+        let (source, _) = fixture(sector_size, sectors);
+
+        for (fail_writes, block_size, is_ok) in cases {
+            let mut args = vec!["kramer", "-i", "flaky", "-s", "512", "-c", "8"];
+
+            if let Some(block_size) = block_size {
+                args.extend(["--write-block-size", block_size]);
+            }
+
+            let (_, map) = fixture(sector_size, sectors);
+            let mut recover = Recover::new(
+                Args::parse_from(args),
+                io::Cursor::new(source.to_owned()),
+                FlakyOutput { data: io::Cursor::new(vec![0u8; source.len()]), fail_writes },
+                map,
+            ).unwrap();
+            let result = recover.run().map(|_| ());
+
+            assert!(
+                result.is_ok() == is_ok,
+                "Expected the run {} with {} failed writes, got {:?}.",
+                if is_ok { "to finish" } else { "to fail" }, fail_writes, result
+            );
+
+            // Nothing is mapped which wasn't written.
+            let recovered = recover.map().get_domains(Stage::Recovered);
+            let output = recover.output().data.get_ref();
+
+            assert!(
+                recovered.iter().all(|d| {
+                    let range = d.start * sector_size..d.end * sector_size;
+                    output[range.to_owned()] == source[range]
+                })
+                && (!is_ok || recovered == vec![Domain { start: 0, end: 32 }]),
+                "Expected recovered sectors written intact with {} failed writes, got {:?}.",
+                fail_writes, recover.map()
+            )
+        }
+    }
+
+    /// In memory output, logging the offset and length of each write.
+    #[derive(Debug)]
+    struct LoggingOutput {
+        data: io::Cursor<Vec<u8>>,
+        writes: Vec<(u64, usize)>,
+    }
+
+    impl Read for LoggingOutput {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.data.read(buf)
+        }
+    }
+
+    impl Write for LoggingOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes.push((self.data.position(), buf.len()));
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for LoggingOutput {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
+
+    impl Output for LoggingOutput {}
+
+    // Test for Recover::run() with --write-block-size
+    #[test]
+    fn test_write_block_size() {
+        let sector_size: usize = 512;
+        // Not a whole number of blocks, so the last is partial.
+        let sectors: usize = 45;
+
+        let (source, _) = fixture(sector_size, sectors);
+        let run = |block_size: Option<&str>| {
+            let mut args = vec![
+                "kramer", "-i", "blocks", "-s", "512", "-c", "8",
+                "--fill-pattern", "0xC0FFEE", "--verify",
+            ];
+
+            if let Some(block_size) = block_size {
+                args.extend(["--write-block-size", block_size]);
+            }
+
+            let (_, map) = fixture(sector_size, sectors);
+            let mut recover = Recover::new(
+                Args::parse_from(args),
+                FaultyCursor::new(source.to_owned(), sector_size, &[3..5, 20..21]),
+                LoggingOutput { data: io::Cursor::new(vec![0u8; source.len()]), writes: vec![] },
+                map,
+            ).unwrap();
+            recover.run().unwrap();
+
+            (recover.output().data.get_ref().to_owned(), recover.output().writes.to_owned())
+        };
+
+        let (expected, _) = run(None);
+
+        for block_size in [512, 1536, 4096, 16384, 1 << 20] {
+            let (recieved, writes) = run(Some(&block_size.to_string()));
+
+            assert!(
+                recieved == expected,
+                "Expected the same output with a write block size of {}.",
+                block_size
+            );
+            // Writes only stop short of a block boundary where the data
+            // gathered does, with the next write elsewhere, or none.
+            let ends_early = |(i, &(offset, len)): (usize, &(u64, usize))| {
+                let end = offset + len as u64;
+                !end.is_multiple_of(block_size)
+                    && writes.get(i + 1).is_some_and(|&(next, _)| next == end)
+            };
+
+            assert!(
+                !writes.iter().enumerate().any(ends_early),
+                "Expected writes ending at multiples of {}, got {:?}.",
+                block_size, writes
+            )
+        }
+    }
+
+    // Test for Recover::stop_reason()
+    #[test]
+    fn test_stop_reason() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, _) = fixture(sector_size, sectors);
+        let run = |input: FaultyCursor, output: FlakyOutput| {
+            let (_, map) = fixture(sector_size, sectors);
+            let mut recover = Recover::new(
+                Args::parse_from(["kramer", "-i", "stop_reason", "-s", "512", "-c", "8"]),
+                input,
+                output,
+                map,
+            ).unwrap();
+            let _ = recover.run();
+
+            recover.stop_reason()
+        };
+        let output = |fail_writes| FlakyOutput {
+            data: io::Cursor::new(vec![0u8; source.len()]),
+            fail_writes,
+        };
+
+        let cases = vec![
+            (FaultyCursor::new(source.to_owned(), sector_size, &[]), output(0), StopReason::Complete),
+            (
+                FaultyCursor::new(source.to_owned(), sector_size, &[3..5, 20..21]),
+                output(0),
+                StopReason::Exhausted,
+            ),
+            (
+                FaultyCursor::new(source.to_owned(), sector_size, &[]),
+                output(2),
+                StopReason::OutputFailed,
+            ),
+        ];
+
+        for (input, output, expected) in cases {
+            let recieved = run(input, output);
+
+            assert!(
+                recieved == Some(expected),
+                "Expected the run to end as {:?}, got {:?}.",
+                expected, recieved
+            )
+        }
+    }
+
+    // Test for Recover::run() copying a file end to end
+    #[test]
+    fn test_run_file_copy() {
+        let sector_size: usize = 512;
+        // Not a whole number of clusters.
+        let sectors: usize = 77;
+
+        let source: Vec<u8> = (0..sectors * sector_size)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        input.write_all(&source).unwrap();
+        let output = tempfile::NamedTempFile::new().unwrap();
+
+        let (_, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "copy", "-s", "512", "-c", "8"]),
+            input.reopen().unwrap(),
+            output.reopen().unwrap(),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let copied = fs::read(output.path()).unwrap();
+
+        assert!(
+            recover.map().get_stage() == Stage::Recovered,
+            "Expected everything recovered, got {:?}.",
+            recover.map()
+        );
+        assert!(copied == source, "Expected the output byte-identical to the input.")
+    }
+
+    /// Reports a fixed amount of free space.
+    #[derive(Debug)]
+    struct FixedSpace(u64);
+
+    impl FreeSpace for FixedSpace {
+        fn available(&self, _path: &std::path::Path) -> io::Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    // Test for Recover::run() running short of free space
+    #[test]
+    fn test_run_space_check() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+
+        let (source, map) = fixture(sector_size, sectors);
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "space", "-s", "512", "-c", "8", "--save-interval", "0"]),
+            io::Cursor::new(source.to_owned()),
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        ).unwrap();
+        // Room for all but the last of the four clusters.
+        recover.set_space_check(SpaceCheck {
+            fs: Box::new(FixedSpace((24 * sector_size) as u64 - 1)),
+            path: PathBuf::from("space.iso"),
+            needed: Some(source.len() as u64),
+            min_free: 0,
+        });
+        let result = recover.run().map(|_| ());
+
+        assert!(
+            result.as_ref().map_err(|e| e.kind()) == Err(io::ErrorKind::StorageFull),
+            "Expected the run to end short of space, got {:?}.",
+            result
+        );
+        assert!(
+            recover.map().get_domains(Stage::Recovered) == vec![Domain { start: 0, end: 8 }],
+            "Expected the run to end after the first cluster, got {:?}.",
+            recover.map()
+        )
+    }
+}
@@ -0,0 +1,312 @@
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{mapping::{MapFile, Stage}, recovery::Output};
+
+
+/// Output split across segments of split_size bytes each, read and written
+/// as one stream. Reads and writes crossing a boundary are cut short there,
+/// so read_exact and write_all carry them on into the next segment.
+#[derive(Debug)]
+pub struct Segmented<F> {
+    segments: Vec<F>,
+    split_size: u64,
+    pos: u64,
+}
+
+impl<F: Read + Write + Seek> Segmented<F> {
+    /// Only the last segment may be shorter than split_size.
+    pub fn new(segments: Vec<F>, split_size: u64) -> Self {
+        assert!(split_size > 0, "Segments must be at least a byte long.");
+
+        Segmented {
+            segments,
+            split_size,
+            pos: 0,
+        }
+    }
+
+    pub fn segments(&self) -> &[F] {
+        &self.segments
+    }
+
+    /// Segment holding offset, the offset within it,
+    /// and the bytes left in it from there.
+    fn locate(&self, offset: u64) -> Option<(usize, u64, u64)> {
+        let index = usize::try_from(offset / self.split_size).ok()
+            .filter(|&i| i < self.segments.len())?;
+        let within = offset % self.split_size;
+
+        Some((index, within, self.split_size - within))
+    }
+
+    /// Call f with each segment overlapping len bytes at offset,
+    /// and the offset and length of the overlap within it.
+    fn each_part<T>(&mut self, offset: u64, len: u64, mut f: T) -> io::Result<()>
+    where
+        T: FnMut(&mut F, u64, u64) -> io::Result<()>,
+    {
+        let end = offset.saturating_add(len);
+        let mut pos = offset;
+
+        while pos < end {
+            let Some((index, within, left)) = self.locate(pos) else {
+                break;
+            };
+            let len = left.min(end - pos);
+
+            f(&mut self.segments[index], within, len)?;
+            pos += len;
+        }
+
+        Ok(())
+    }
+}
+
+impl<F: Read + Write + Seek> Read for Segmented<F> {
+    /// Reads past the last segment read nothing.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((index, within, left)) = self.locate(self.pos) else {
+            return Ok(0);
+        };
+        let len = (buf.len() as u64).min(left) as usize;
+        let segment = &mut self.segments[index];
+
+        segment.seek(SeekFrom::Start(within))?;
+        let n = segment.read(&mut buf[..len])?;
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F: Read + Write + Seek> Write for Segmented<F> {
+    /// Writes past the last segment write nothing.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some((index, within, left)) = self.locate(self.pos) else {
+            return Ok(0);
+        };
+        let len = (buf.len() as u64).min(left) as usize;
+        let segment = &mut self.segments[index];
+
+        segment.seek(SeekFrom::Start(within))?;
+        let n = segment.write(&buf[..len])?;
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.segments.iter_mut().try_for_each(|s| s.flush())
+    }
+}
+
+impl<F: Read + Write + Seek> Seek for Segmented<F> {
+    /// The end is that of the last segment, as if every other were full.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+                .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?,
+            SeekFrom::End(offset) => {
+                let full = self.segments.len().saturating_sub(1) as u64 * self.split_size;
+                let last = match self.segments.last_mut() {
+                    Some(segment) => segment.seek(SeekFrom::End(0))?,
+                    None => 0,
+                };
+
+                (full + last).checked_add_signed(offset)
+                    .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?
+            },
+        };
+
+        Ok(self.pos)
+    }
+}
+
+impl<F: Output> Output for Segmented<F> {
+    fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let mut done = 0;
+
+        self.each_part(offset, buf.len() as u64, |segment, offset, len| {
+            segment.read_exact_at(&mut buf[done..done + len as usize], offset)?;
+            done += len as usize;
+            Ok(())
+        })?;
+
+        match done == buf.len() {
+            true => Ok(()),
+            false => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    fn write_all_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut done = 0;
+
+        self.each_part(offset, buf.len() as u64, |segment, offset, len| {
+            segment.write_all_at(&buf[done..done + len as usize], offset)?;
+            done += len as usize;
+            Ok(())
+        })?;
+
+        match done == buf.len() {
+            true => Ok(()),
+            false => Err(io::Error::from(io::ErrorKind::WriteZero)),
+        }
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.segments.iter_mut().try_for_each(|s| s.sync())
+    }
+
+    /// Offsets in map only line up with an unsplit output.
+    /// Segments are regular files, which aren't discarded anyway.
+    fn discard_unrecovered(&mut self, map: &MapFile, stages: &[Stage]) -> io::Result<()> {
+        match self.segments.as_mut_slice() {
+            [segment] => segment.discard_unrecovered(map, stages),
+            _ => Ok(()),
+        }
+    }
+
+    fn drop_cache(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        self.each_part(offset, len, |segment, offset, len| segment.drop_cache(offset, len))
+    }
+
+    fn punch_hole(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        self.each_part(offset, len, |segment, offset, len| segment.punch_hole(offset, len))
+    }
+}
+
+
+/// Paths of the segments of output, for len bytes split into split_size.
+/// Unsplit, the output is its own only segment.
+/// Split, segments take numbered extensions, such as out.000 and out.001.
+pub fn segment_paths(output: &Path, len: u64, split_size: Option<u64>) -> Vec<PathBuf> {
+    match split_size {
+        Some(split_size) => (0..len.div_ceil(split_size).max(1))
+            .map(|i| output.with_extension(format!("{:03}", i)))
+            .collect(),
+        None => vec![output.to_owned()],
+    }
+}
+
+/// Length of segment index of len bytes split into split_size.
+pub fn segment_len(index: usize, len: u64, split_size: Option<u64>) -> u64 {
+    let split_size = split_size.unwrap_or(u64::MAX);
+
+    len.saturating_sub(index as u64 * split_size).min(split_size)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Args,
+        mapping::{Domain, Stage},
+        recovery::Recover,
+    };
+    use clap::Parser;
+
+    // Test for Segmented
+    #[test]
+    fn test_segmented() {
+        let data: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut output = Segmented::new(
+            (0..4).map(|_| io::Cursor::new(vec![])).collect(),
+            300,
+        );
+
+        // Across every boundary, with write_all carrying on in each segment.
+        output.write_all(&data).unwrap();
+
+        let lens: Vec<usize> = output.segments().iter().map(|s| s.get_ref().len()).collect();
+
+        assert!(lens == vec![300, 300, 300, 100], "Got segment lengths {:?}.", lens);
+        assert!(output.seek(SeekFrom::End(0)).unwrap() == 1000);
+
+        let mut buf = vec![0u8; 200];
+        output.seek(SeekFrom::Start(550)).unwrap();
+        output.read_exact(&mut buf).unwrap();
+
+        assert!(buf == data[550..750], "Expected a read across a boundary to match.");
+
+        // Nothing past the last segment.
+        output.seek(SeekFrom::Start(1200)).unwrap();
+
+        assert!(output.write(&data).unwrap() == 0);
+        assert!(output.read(&mut buf).unwrap() == 0)
+    }
+
+    // Test for Recover::run() into a Segmented output
+    #[test]
+    fn test_run_segmented() {
+        let sector_size: usize = 512;
+        let sectors: usize = 40;
+        // Three sectors, so every cluster of eight straddles a boundary.
+        let split_size = 3 * sector_size as u64;
+        let len = (sectors * sector_size) as u64;
+
+        let source: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let segments = segment_paths(Path::new("out.iso"), len, Some(split_size))
+            .iter()
+            .enumerate()
+            .map(|(i, _)| io::Cursor::new(vec![0u8; segment_len(i, len, Some(split_size)) as usize]))
+            .collect();
+
+        let map = MapFile::new(sector_size as u16)
+            .set_domain(Domain { start: 0, end: sectors })
+            .to_owned();
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "split", "-s", "512", "-c", "8"]),
+            io::Cursor::new(source.to_owned()),
+            Segmented::new(segments, split_size),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        let joined: Vec<u8> = recover.output()
+            .segments()
+            .iter()
+            .flat_map(|s| s.get_ref().to_owned())
+            .collect();
+
+        assert!(
+            recover.map().get_stage() == Stage::Recovered,
+            "Expected everything recovered, got {:?}.",
+            recover.map()
+        );
+        assert!(
+            recover.output().segments().len() == 14 && joined == source,
+            "Expected 14 segments joined to match the source."
+        )
+    }
+
+    // Test for segment_paths() and segment_len()
+    #[test]
+    fn test_segment_paths() {
+        let cases = vec![
+            (10, None, vec!["out.iso"], vec![10]),
+            (10, Some(4), vec!["out.000", "out.001", "out.002"], vec![4, 4, 2]),
+            (8, Some(4), vec!["out.000", "out.001"], vec![4, 4]),
+            (0, Some(4), vec!["out.000"], vec![0]),
+        ];
+
+        for (len, split_size, paths, lens) in cases {
+            let recieved = segment_paths(Path::new("out.iso"), len, split_size);
+            let recieved_lens: Vec<u64> = (0..recieved.len())
+                .map(|i| segment_len(i, len, split_size))
+                .collect();
+
+            assert!(
+                recieved.iter().map(|p| p.to_str().unwrap()).eq(paths.to_owned())
+                && recieved_lens == lens,
+                "Expected {:?} of {:?} bytes for {} split into {:?}, got {:?} of {:?}.",
+                paths, lens, len, split_size, recieved, recieved_lens
+            )
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::{io, sync::atomic::AtomicBool};
+
+#[cfg(unix)]
+use std::sync::atomic::Ordering;
+
+
+/// Exit status for a forced exit, as a shell reports death by SIGINT.
+#[cfg(unix)]
+const EXIT_SIGINT: libc::c_int = 130;
+
+
+/// Set on the first SIGINT.
+#[cfg(unix)]
+static STOP: AtomicBool = AtomicBool::new(false);
+
+
+/// Request a stop on the first SIGINT, and exit immediately on the second.
+/// Only async-signal-safe calls are allowed in here.
+#[cfg(unix)]
+extern "C" fn handle_sigint(_signal: libc::c_int) {
+    if STOP.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(EXIT_SIGINT) }
+    }
+}
+
+/// Install the SIGINT handler, returning the flag it sets.
+/// Interrupted reads and writes are restarted, so a SIGINT only stops
+/// recovery where the flag is checked.
+#[cfg(unix)]
+pub fn stop_on_sigint() -> io::Result<&'static AtomicBool> {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    action.sa_sigaction = handle_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    action.sa_flags = libc::SA_RESTART;
+
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+    match unsafe { libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut()) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(&STOP),
+    }
+}
+
+/// Only handled on Unix, so a SIGINT (Ctrl-C) kills recovery elsewhere.
+#[cfg(not(unix))]
+pub fn stop_on_sigint() -> io::Result<&'static AtomicBool> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    // Test for stop_on_sigint()
+    #[test]
+    fn test_stop_on_sigint() {
+        let stop = stop_on_sigint().unwrap();
+
+        assert!(!stop.load(Ordering::SeqCst));
+
+        // Only once, as a second SIGINT would exit the test run.
+        unsafe { libc::raise(libc::SIGINT) };
+
+        assert!(stop.load(Ordering::SeqCst), "Expected SIGINT to set the stop flag.")
+    }
+}
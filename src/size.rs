@@ -0,0 +1,109 @@
+/// Parse a size in bytes, such as 512, 4K or 1MiB.
+/// K, M and G are binary multiples, as are KiB, MiB and GiB.
+/// KB, MB and GB are decimal. Suffixes are case insensitive.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, suffix) = s.split_at(split);
+
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        _ => return Err(format!("Unknown size suffix {:?} in {:?}", suffix, s)),
+    };
+
+    n.parse::<u64>()
+        .map_err(|err| format!("Invalid size {:?}: {}", s, err))?
+        .checked_mul(multiplier)
+        .ok_or(format!("Size {:?} is too large", s))
+}
+
+/// parse_size(), for sizes which must fit in a u16, such as sector sizes.
+pub fn parse_size_u16(s: &str) -> Result<u16, String> {
+    u16::try_from(parse_size(s)?)
+        .map_err(|_| format!("Size {:?} is larger than {} bytes", s, u16::MAX))
+}
+
+/// Number of sectors in a cluster of bytes.
+/// bytes must be a non-zero whole number of sectors.
+pub fn cluster_length_from_size(bytes: u64, sector_size: u16) -> Result<u16, String> {
+    if bytes == 0 || sector_size == 0 || !bytes.is_multiple_of(sector_size as u64) {
+        return Err(format!(
+            "Cluster size of {} bytes isn't a whole number of {} byte sectors",
+            bytes, sector_size,
+        ));
+    }
+
+    u16::try_from(bytes / sector_size as u64).map_err(|_| format!(
+        "Cluster size of {} bytes is more than {} sectors",
+        bytes, u16::MAX,
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for parse_size()
+    #[test]
+    fn test_parse_size() {
+        let cases = vec![
+            ("512", Ok(512)),
+            ("4K", Ok(4096)),
+            ("4k", Ok(4096)),
+            ("1MiB", Ok(1 << 20)),
+            ("2M", Ok(2 << 20)),
+            ("1G", Ok(1 << 30)),
+            ("4KB", Ok(4000)),
+            ("4096B", Ok(4096)),
+            ("", Err(())),
+            ("K", Err(())),
+            ("4X", Err(())),
+            ("-4K", Err(())),
+            ("99999999999G", Err(())),
+        ];
+
+        for (s, expected) in cases {
+            let recieved = parse_size(s);
+
+            assert!(
+                recieved.to_owned().map_err(|_| ()) == expected,
+                "Expected {:?} for {:?}, got {:?}.",
+                expected, s, recieved
+            )
+        }
+
+        assert!(parse_size_u16("4K") == Ok(4096));
+        assert!(parse_size_u16("64K").is_err())
+    }
+
+    // Test for cluster_length_from_size()
+    #[test]
+    fn test_cluster_length_from_size() {
+        let cases = vec![
+            ("256K", 2048, Ok(128)),
+            ("1MiB", 512, Ok(2048)),
+            ("2048", 2048, Ok(1)),
+            ("3K", 2048, Err(())),
+            ("0", 2048, Err(())),
+            ("2048", 0, Err(())),
+            ("1G", 512, Err(())),
+        ];
+
+        for (s, sector_size, expected) in cases {
+            let recieved = cluster_length_from_size(parse_size(s).unwrap(), sector_size);
+
+            assert!(
+                recieved.to_owned().map_err(|_| ()) == expected,
+                "Expected {:?} for {:?} with {} byte sectors, got {:?}.",
+                expected, s, sector_size, recieved
+            )
+        }
+    }
+}
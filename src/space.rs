@@ -0,0 +1,202 @@
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::{
+    ffi::CString,
+    mem::MaybeUninit,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+};
+
+
+/// Reports free space on the filesystem holding a path.
+pub trait FreeSpace: fmt::Debug {
+    /// Bytes available to unprivileged users.
+    fn available(&self, path: &Path) -> io::Result<u64>;
+}
+
+/// Queries the filesystem with statvfs(3).
+/// Elsewhere than Unix, free space is unknown, so never short.
+#[derive(Debug)]
+pub struct Statvfs;
+
+impl FreeSpace for Statvfs {
+    #[cfg(unix)]
+    fn available(&self, path: &Path) -> io::Result<u64> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+        match unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => {
+                let stat = unsafe { stat.assume_init() };
+
+                // Field widths vary by platform.
+                #[allow(clippy::unnecessary_cast)]
+                Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+            },
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn available(&self, _path: &Path) -> io::Result<u64> {
+        Ok(u64::MAX)
+    }
+}
+
+
+/// Free space check of the output's filesystem, repeated during a run.
+#[derive(Debug)]
+pub struct SpaceCheck {
+    pub fs: Box<dyn FreeSpace>,
+    /// Path on the output's filesystem.
+    pub path: PathBuf,
+    /// Bytes the output had yet to claim as the run started.
+    /// None if sparse, where zeros claim nothing, so it's estimated instead.
+    pub needed: Option<u64>,
+    pub min_free: u64,
+}
+
+impl SpaceCheck {
+    /// Ensure the output's filesystem can take what's left to claim,
+    /// with written bytes claimed of copied bytes written so far,
+    /// and remaining bytes left to recover.
+    ///
+    /// Sparse output is expected to keep growing at the rate it has so far,
+    /// so nothing is expected of it until something is copied.
+    pub fn check(&self, written: u64, copied: u64, remaining: u64) -> io::Result<()> {
+        let needed = match (self.needed, copied) {
+            (Some(needed), _) => needed.saturating_sub(written),
+            (None, 0) => 0,
+            (None, copied) => {
+                (remaining as u128 * written as u128 / copied as u128).min(u64::MAX as u128) as u64
+            },
+        };
+
+        check_free_space(self.fs.as_ref(), &self.path, needed, self.min_free)
+    }
+}
+
+
+/// Bytes of storage allocated to file, which is less than its length
+/// where sparse. Elsewhere than Unix, taken to be its length.
+#[cfg(unix)]
+pub fn allocated_len(file: &File) -> io::Result<u64> {
+    // Blocks are 512 bytes, whatever the filesystem's block size.
+    Ok(file.metadata()?.blocks() * 512)
+}
+
+#[cfg(not(unix))]
+pub fn allocated_len(file: &File) -> io::Result<u64> {
+    Ok(file.metadata()?.len())
+}
+
+
+/// Ensure the filesystem holding path can take another needed bytes,
+/// keeping min_free bytes spare.
+pub fn check_free_space<F: FreeSpace + ?Sized>(
+    fs: &F,
+    path: &Path,
+    needed: u64,
+    min_free: u64,
+) -> io::Result<()> {
+    let available = fs.available(path)?;
+
+    if available < needed.saturating_add(min_free) {
+        return Err(io::Error::new(
+            io::ErrorKind::StorageFull,
+            format!(
+                "{} bytes free for output, but {} more are needed with {} kept spare",
+                available, needed, min_free,
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports a fixed amount of free space.
+    #[derive(Debug)]
+    struct MockFs(u64);
+
+    impl FreeSpace for MockFs {
+        fn available(&self, _path: &Path) -> io::Result<u64> {
+            Ok(self.0)
+        }
+    }
+
+    // Test for Statvfs
+    #[test]
+    fn test_statvfs() {
+        assert!(Statvfs.available(&std::env::temp_dir()).is_ok())
+    }
+
+    // Test for check_free_space()
+    #[test]
+    fn test_check_free_space() {
+        let path = Path::new("out.iso");
+
+        let cases = vec![
+            (4096, 4096, 0, true),
+            (4095, 4096, 0, false),
+            (8192, 4096, 4096, true),
+            (8191, 4096, 4096, false),
+        ];
+
+        for (available, needed, min_free, expected) in cases {
+            let result = check_free_space(&MockFs(available), path, needed, min_free);
+
+            assert!(
+                result.is_ok() == expected,
+                "Expected {} free bytes to be {} for {} needed and {} spare, got {:?}.",
+                available,
+                if expected { "accepted" } else { "rejected" },
+                needed, min_free, result
+            )
+        }
+    }
+
+    // Test for SpaceCheck::check()
+    #[test]
+    fn test_space_check() {
+        let cases = vec![
+            // Needed, written, copied, remaining, and whether there's room
+            // in 4096 free bytes.
+            (Some(8192), 0, 0, 0, false),
+            (Some(8192), 4096, 4096, 4096, true),
+            (Some(8192), 2048, 2048, 6144, false),
+            // Sparse, writing half of what's copied.
+            (None, 2048, 4096, 8192, true),
+            (None, 2048, 4096, 8194, false),
+            // Sparse, before anything is copied.
+            (None, 0, 0, 1 << 20, true),
+        ];
+
+        for (needed, written, copied, remaining, expected) in cases {
+            let check = SpaceCheck {
+                fs: Box::new(MockFs(4096)),
+                path: PathBuf::from("out.iso"),
+                needed,
+                min_free: 0,
+            };
+            let result = check.check(written, copied, remaining);
+
+            assert!(
+                result.is_ok() == expected,
+                "Expected room: {} for {:?} needed with {} of {} copied bytes written \
+                and {} remaining, got {:?}.",
+                expected, needed, written, copied, remaining, result
+            )
+        }
+    }
+}
@@ -0,0 +1,10 @@
+//! Helpers shared between tests.
+
+use std::path::PathBuf;
+
+
+/// Path for a scratch file, unique to this test run.
+pub fn scratch_path(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("kramer-{}-{}", std::process::id(), name))
+}
@@ -0,0 +1,84 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+
+/// Paces reads to at most bytes_per_sec, as a token bucket.
+/// The bucket fills at bytes_per_sec, holding at most a second's worth,
+/// so time spent on slow or failed reads doesn't allow a burst after.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_sec: u64,
+    /// Bytes which may be read without waiting.
+    tokens: f64,
+    last_fill: Instant,
+}
+
+impl Throttle {
+    /// None if bytes_per_sec is 0, which is unlimited.
+    pub fn new(bytes_per_sec: u64) -> Option<Self> {
+        match bytes_per_sec {
+            0 => None,
+            _ => Some(Throttle {
+                bytes_per_sec,
+                tokens: 0.0,
+                last_fill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take bytes from the bucket before reading them,
+    /// sleeping until it holds enough.
+    pub fn take(&mut self, bytes: u64) {
+        let rate = self.bytes_per_sec as f64;
+        let now = Instant::now();
+
+        self.tokens = (self.tokens + now.duration_since(self.last_fill).as_secs_f64() * rate)
+            .min(rate);
+        self.last_fill = now;
+        self.tokens -= bytes as f64;
+
+        // Reads larger than the bucket wait for all of it.
+        if self.tokens < 0.0 {
+            thread::sleep(Duration::from_secs_f64(-self.tokens / rate));
+
+            self.tokens = 0.0;
+            self.last_fill = Instant::now();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test for Throttle::take()
+    #[test]
+    fn test_take() {
+        assert!(Throttle::new(0).is_none(), "Expected a rate of 0 to be unlimited.");
+
+        let cases = vec![
+            // Bytes per second, bytes per read, and reads.
+            (100_000, 5_000, 10),
+            // Reads larger than the bucket.
+            (10_000, 15_000, 2),
+        ];
+
+        for (bytes_per_sec, bytes, reads) in cases {
+            let mut throttle = Throttle::new(bytes_per_sec).unwrap();
+            let expected = Duration::from_secs_f64((bytes * reads) as f64 / bytes_per_sec as f64);
+
+            let start = Instant::now();
+            (0..reads).for_each(|_| throttle.take(bytes));
+            let recieved = start.elapsed();
+
+            assert!(
+                recieved >= expected,
+                "Expected {} reads of {} bytes at {} bytes/s to take at least {:?}, took {:?}.",
+                reads, bytes, bytes_per_sec, expected, recieved
+            )
+        }
+    }
+}
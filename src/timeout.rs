@@ -0,0 +1,243 @@
+use log::warn;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{buffer::AlignedBuf, platform};
+
+
+/// Something which can be read from at any offset, from any thread.
+pub trait ReadAt: fmt::Debug + Send + Sync + 'static {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
+
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        platform::read_exact_at(self, buf, offset)
+    }
+}
+
+
+/// Reader which gives up on reads taking longer than timeout.
+///
+/// With a timeout, each read runs on its own thread, so a read hung by a
+/// failing drive is left behind rather than blocking every read after it.
+/// Reads go through a buffer aligned to align, as O_DIRECT requires.
+#[derive(Debug)]
+pub struct TimeoutReader<T: ReadAt> {
+    inner: Arc<T>,
+    pos: u64,
+    timeout: Option<Duration>,
+    align: usize,
+}
+
+impl<T: ReadAt> TimeoutReader<T> {
+    /// Without a timeout, reads are made directly, and may block forever.
+    pub fn new(inner: T, timeout: Option<Duration>, align: usize) -> Self {
+        TimeoutReader {
+            inner: Arc::new(inner),
+            pos: 0,
+            timeout,
+            align,
+        }
+    }
+
+    /// Read buf.len() bytes at offset, or fail with TimedOut.
+    fn read_timeout(&self, buf: &mut [u8], offset: u64, timeout: Duration) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let inner = Arc::clone(&self.inner);
+        let mut aligned = AlignedBuf::new(buf.len(), self.align);
+
+        thread::spawn(move || {
+            let result = inner.read_exact_at(&mut aligned, offset);
+
+            // Nobody is listening if the read timed out.
+            let _ = tx.send(result.map(|_| aligned));
+        });
+
+        match rx.recv_timeout(timeout) {
+            Ok(result) => {
+                buf.copy_from_slice(&result?);
+                Ok(())
+            },
+            Err(_) => {
+                warn!(
+                    "Read of {} bytes at offset {} timed out after {:?}.",
+                    buf.len(), offset, timeout,
+                );
+
+                Err(io::Error::from(io::ErrorKind::TimedOut))
+            },
+        }
+    }
+}
+
+impl<T: ReadAt> ReadAt for TimeoutReader<T> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match self.timeout {
+            Some(timeout) => self.read_timeout(buf, offset, timeout),
+            None => {
+                let mut aligned = AlignedBuf::new(buf.len(), self.align);
+
+                self.inner.read_exact_at(&mut aligned, offset)?;
+                buf.copy_from_slice(&aligned);
+                Ok(())
+            },
+        }
+    }
+}
+
+impl<T: ReadAt> Read for TimeoutReader<T> {
+    /// Reads all of buf, or fails.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.read_exact_at(buf, self.pos)?;
+
+        self.pos += buf.len() as u64;
+        Ok(buf.len())
+    }
+}
+
+impl<T: ReadAt> Seek for TimeoutReader<T> {
+    /// Seeking from the end is unsupported, as the length isn't known.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = match pos {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset)
+                .ok_or(io::Error::from(io::ErrorKind::InvalidInput))?,
+            SeekFrom::End(_) => return Err(io::Error::from(io::ErrorKind::Unsupported)),
+        };
+
+        Ok(self.pos)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Args,
+        mapping::{Domain, MapFile, Stage},
+        recovery::Recover,
+    };
+    use clap::Parser;
+    use std::{ops::Range, time::Instant};
+
+    /// Data whose reads touching slow sleep for delay first.
+    #[derive(Debug)]
+    struct SlowData {
+        data: Vec<u8>,
+        slow: Range<u64>,
+        delay: Duration,
+    }
+
+    impl ReadAt for SlowData {
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            let end = offset + buf.len() as u64;
+
+            if offset < self.slow.end && self.slow.start < end {
+                thread::sleep(self.delay);
+            }
+
+            let data = self.data
+                .get(offset as usize..end as usize)
+                .ok_or(io::Error::from(io::ErrorKind::UnexpectedEof))?;
+
+            buf.copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    // Test for TimeoutReader
+    #[test]
+    fn test_timeout_reader() {
+        let data: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let delay = Duration::from_secs(2);
+
+        let mut reader = TimeoutReader::new(
+            SlowData { data: data.to_owned(), slow: 1024..1536, delay },
+            Some(Duration::from_millis(20)),
+            512,
+        );
+        let mut buf = vec![0u8; 512];
+
+        let start = Instant::now();
+        reader.seek(SeekFrom::Start(1024)).unwrap();
+        let slow = reader.read_exact(&mut buf);
+
+        assert!(
+            slow.as_ref().map_err(|e| e.kind()) == Err(io::ErrorKind::TimedOut)
+            && start.elapsed() < delay,
+            "Expected the slow read to time out, got {:?} after {:?}.",
+            slow, start.elapsed()
+        );
+
+        // Reads after a hung read aren't held up by it.
+        reader.seek(SeekFrom::Start(2048)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+
+        assert!(
+            buf == data[2048..2560] && start.elapsed() < delay,
+            "Expected the next read to succeed without waiting."
+        );
+
+        // Without a timeout, reads are just slow.
+        let mut reader = TimeoutReader::new(
+            SlowData { data: data.to_owned(), slow: 0..0, delay },
+            None,
+            512,
+        );
+
+        reader.seek(SeekFrom::Start(512)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+
+        assert!(buf == data[512..1024])
+    }
+
+    // Test for Recover::run() with a TimeoutReader
+    #[test]
+    fn test_run_timeout() {
+        let sector_size: usize = 512;
+        let sectors: usize = 32;
+        let delay = Duration::from_secs(2);
+
+        let input = TimeoutReader::new(
+            SlowData {
+                data: vec![0xaa; sectors * sector_size],
+                slow: (12 * sector_size) as u64..(13 * sector_size) as u64,
+                delay,
+            },
+            Some(Duration::from_millis(20)),
+            sector_size,
+        );
+
+        let map = MapFile::new(sector_size as u16)
+            .set_domain(Domain { start: 0, end: sectors })
+            .to_owned();
+
+        let start = Instant::now();
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", "slow", "-s", "512", "-c", "8"]),
+            input,
+            io::Cursor::new(vec![0u8; sectors * sector_size]),
+            map,
+        ).unwrap();
+        recover.run().unwrap();
+
+        assert!(
+            recover.map().get_domains(Stage::Damaged) == vec![Domain { start: 12, end: 13 }],
+            "Expected only the hung sector damaged, got {:?}.",
+            recover.map()
+        );
+        assert!(
+            start.elapsed() < delay,
+            "Expected the run not to wait on hung reads, took {:?}.",
+            start.elapsed()
+        )
+    }
+}
@@ -0,0 +1,180 @@
+//! Maps loaded from fixtures, recovered from in memory, and saved back.
+
+#[path = "../src/testing.rs"]
+mod testing;
+
+use clap::Parser;
+use kramer::{
+    Args,
+    mapping::{Domain, MapFile, MapFormat, Stage},
+    recovery::{Input, Recover},
+};
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::PathBuf,
+};
+use testing::scratch_path;
+
+
+const SECTOR_SIZE: usize = 512;
+const SECTORS: usize = 32;
+
+
+fn fixture_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Source sectors, differing sector to sector so misplaced copies show.
+fn source() -> Vec<u8> {
+    (0..SECTORS * SECTOR_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+/// In memory input, failing any read which touches a bad sector.
+#[derive(Debug)]
+struct FaultyInput {
+    data: io::Cursor<Vec<u8>>,
+    bad: Range<u64>,
+}
+
+impl FaultyInput {
+    fn new(bad: Range<usize>) -> Self {
+        FaultyInput {
+            data: io::Cursor::new(source()),
+            bad: (bad.start * SECTOR_SIZE) as u64..(bad.end * SECTOR_SIZE) as u64,
+        }
+    }
+}
+
+impl Read for FaultyInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = self.data.position();
+        let end = start + buf.len() as u64;
+
+        if start < self.bad.end && self.bad.start < end {
+            return Err(io::Error::other("Bad sector"));
+        }
+
+        self.data.read(buf)
+    }
+}
+
+impl Seek for FaultyInput {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.data.seek(pos)
+    }
+}
+
+impl Input for FaultyInput {}
+
+/// Sectors of map at stage, one by one.
+fn sectors(map: &MapFile, stage: Stage) -> Vec<usize> {
+    map.get_domains(stage)
+        .iter()
+        .flat_map(|d| d.start..d.end)
+        .collect()
+}
+
+// Test for loading a fixture, recovering it, and saving it back
+#[test]
+fn test_round_trip() {
+    let cases = vec![
+        // Sector 20 fails, so is isolated down to a single damaged sector.
+        (
+            "empty.map",
+            MapFormat::Ron,
+            (0..SECTORS).filter(|&s| s != 20).collect::<Vec<_>>(),
+            vec![20],
+        ),
+        // Damaged sectors are left as they are, and recovered ones unread.
+        (
+            "partial.map",
+            MapFormat::Ron,
+            (8..SECTORS).filter(|&s| !(12..14).contains(&s) && s != 20).collect(),
+            vec![12, 13, 20],
+        ),
+        // Saved big endian, as CBOR is on every machine.
+        (
+            "partial.cbor",
+            MapFormat::Cbor,
+            (8..SECTORS).filter(|&s| !(12..14).contains(&s) && s != 20).collect(),
+            vec![12, 13, 20],
+        ),
+        (
+            "partial.ddrescue",
+            MapFormat::Ddrescue,
+            (8..SECTORS).filter(|&s| !(12..14).contains(&s) && s != 20).collect(),
+            vec![12, 13, 20],
+        ),
+        // Nothing is left to read.
+        ("recovered.map", MapFormat::Ron, vec![], vec![]),
+    ];
+
+    let source = source();
+
+    for (name, format, read, damaged) in cases {
+        let (map, loaded_format) = MapFile::load(
+            File::open(fixture_path(name)).unwrap(),
+            SECTOR_SIZE as u16,
+        )
+        .unwrap();
+        let before = map.to_owned();
+
+        let mut recover = Recover::new(
+            Args::parse_from(["kramer", "-i", name, "-s", "512", "-c", "8", "-b", "1"]),
+            FaultyInput::new(20..21),
+            io::Cursor::new(vec![0u8; source.len()]),
+            map,
+        )
+        .unwrap();
+        recover.run().unwrap();
+
+        let output = recover.output().get_ref();
+
+        for sector in 0..SECTORS {
+            let range = sector * SECTOR_SIZE..(sector + 1) * SECTOR_SIZE;
+            let expected = match read.contains(&sector) {
+                true => &source[range.to_owned()],
+                false => &[0u8; SECTOR_SIZE][..],
+            };
+
+            assert!(
+                &output[range] == expected,
+                "Expected sector {} of {} {} this run.",
+                sector, name, if read.contains(&sector) { "read" } else { "left unread" }
+            )
+        }
+
+        // Recovered before, or this run.
+        let recovered: Vec<usize> = (0..SECTORS)
+            .filter(|&s| read.contains(&s) || sectors(&before, Stage::Recovered).contains(&s))
+            .collect();
+        let map = recover.map();
+
+        assert!(
+            sectors(map, Stage::Damaged) == damaged
+            && sectors(map, Stage::Recovered) == recovered
+            && map.domain == Domain { start: 0, end: SECTORS },
+            "Expected {:?} damaged and {:?} recovered in {}, got {:?}.",
+            damaged, recovered, name, map
+        );
+
+        let path = scratch_path(&format!("round_trip_{}", name));
+
+        map.save_as(&path, loaded_format).unwrap();
+        let reloaded = MapFile::load(File::open(&path).unwrap(), SECTOR_SIZE as u16);
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            loaded_format == format
+            && reloaded.as_ref().is_ok_and(|(reloaded, f)| reloaded == map && *f == format),
+            "Expected {} saved back as {:?} and reloaded the same, got {:?}.",
+            name, format, reloaded
+        )
+    }
+}